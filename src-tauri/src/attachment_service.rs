@@ -0,0 +1,466 @@
+// Photos and files attached to diary entries
+//
+// The contents are kept once per content hash in the attachment_blobs table
+// of the diary database, so the same photo attached to several days takes
+// space only once, and attachments are encrypted, backed up and moved
+// together with the entries. The signed event of an entry lists its
+// attachments in NIP-92 `imeta` tags. The files only live on this device, so
+// the tags carry the hash, MIME type, size and dimensions but no `url`.
+use chrono::{DateTime, Utc};
+use image::ImageFormat;
+use nostr_sdk::Tag;
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+use tauri::State;
+
+use crate::revision_service::{
+    find_entry_for_day, publish_revision, save_error, save_updated_entry, sign_revision,
+};
+use crate::DiaryStore;
+
+const MAX_ATTACHMENT_BYTES: usize = 25 * 1024 * 1024;
+// Longest side of generated thumbnails, in pixels
+const THUMBNAIL_SIZE: u32 = 256;
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Attachment {
+    pub sha256: String,
+    pub file_name: String,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub created_at: DateTime<Utc>,
+}
+
+fn is_valid_sha256(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// Width and height of an image in pixels
+type Dimensions = (u32, u32);
+
+// Scales an image down to a JPEG thumbnail. Returns the original dimensions
+// and the thumbnail, or None when the data is not an image we can decode.
+fn create_thumbnail(sha256: &str, data: &[u8]) -> Option<(Dimensions, Option<Vec<u8>>)> {
+    let image = match image::load_from_memory(data) {
+        Ok(image) => image,
+        Err(e) => {
+            println!("Failed to decode image {}: {}", sha256, e);
+            return None;
+        }
+    };
+
+    let mut thumbnail = Vec::new();
+    let encoded = image
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .to_rgb8()
+        .write_to(&mut Cursor::new(&mut thumbnail), ImageFormat::Jpeg);
+    let thumbnail = match encoded {
+        Ok(()) => Some(thumbnail),
+        Err(e) => {
+            println!("Failed to encode thumbnail for {}: {}", sha256, e);
+            None
+        }
+    };
+
+    Some(((image.width(), image.height()), thumbnail))
+}
+
+fn attachment_from_row(row: &rusqlite::Row) -> SqlResult<Attachment> {
+    let created_at_str: String = row.get(6)?;
+    let created_at = DateTime::parse_from_rfc3339(&created_at_str)
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now());
+
+    Ok(Attachment {
+        sha256: row.get(0)?,
+        file_name: row.get(1)?,
+        mime_type: row.get(2)?,
+        size_bytes: row.get(3)?,
+        width: row.get(4)?,
+        height: row.get(5)?,
+        created_at,
+    })
+}
+
+// Attachments of an entry in the order they were added
+pub fn load_attachments(conn: &Connection, entry_id: &str) -> SqlResult<Vec<Attachment>> {
+    let mut stmt = conn.prepare(
+        "SELECT sha256, file_name, mime_type, size_bytes, width, height, created_at
+         FROM attachments WHERE entry_id = ?1
+         ORDER BY created_at, rowid",
+    )?;
+    let attachments_iter = stmt.query_map(params![entry_id], attachment_from_row)?;
+
+    let mut attachments = Vec::new();
+    for attachment in attachments_iter {
+        attachments.push(attachment?);
+    }
+    Ok(attachments)
+}
+
+fn insert_attachment(conn: &Connection, entry_id: &str, attachment: &Attachment) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO attachments
+            (entry_id, sha256, file_name, mime_type, size_bytes, width, height, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            entry_id,
+            attachment.sha256,
+            attachment.file_name,
+            attachment.mime_type,
+            attachment.size_bytes,
+            attachment.width,
+            attachment.height,
+            attachment.created_at.to_rfc3339()
+        ],
+    )?;
+    Ok(())
+}
+
+// Stores the contents of an attachment unless a blob with the same hash
+// exists, and attaches it to the entry
+fn store_attachment(
+    conn: &Connection,
+    entry_id: &str,
+    attachment: &Attachment,
+    data: &[u8],
+    thumbnail: Option<&[u8]>,
+) -> SqlResult<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO attachment_blobs (sha256, data, thumbnail) VALUES (?1, ?2, ?3)",
+        params![attachment.sha256, data, thumbnail],
+    )?;
+    insert_attachment(conn, entry_id, attachment)
+}
+
+// Detaches a file from an entry and drops its contents once no entry uses it
+fn delete_attachment(conn: &Connection, entry_id: &str, sha256: &str) -> SqlResult<()> {
+    conn.execute(
+        "DELETE FROM attachments WHERE entry_id = ?1 AND sha256 = ?2",
+        params![entry_id, sha256],
+    )?;
+    remove_unreferenced_blobs(conn)?;
+    Ok(())
+}
+
+// Deletes stored contents that no attachment row refers to any more, e.g.
+// after an entry was purged. Run it in the same transaction as the change
+// that removed the rows.
+pub fn remove_unreferenced_blobs(conn: &Connection) -> SqlResult<usize> {
+    conn.execute(
+        "DELETE FROM attachment_blobs WHERE sha256 NOT IN (SELECT sha256 FROM attachments)",
+        [],
+    )
+}
+
+// The contents of an attachment that belongs to an entry, or its thumbnail
+fn load_attachment_data(
+    conn: &Connection,
+    sha256: &str,
+    thumbnail: bool,
+) -> SqlResult<Option<Vec<u8>>> {
+    conn.query_row(
+        "SELECT CASE WHEN ?2 AND thumbnail IS NOT NULL THEN thumbnail ELSE data END
+         FROM attachment_blobs
+         WHERE sha256 = ?1 AND sha256 IN (SELECT sha256 FROM attachments)",
+        params![sha256, thumbnail],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+// NIP-92 `imeta` tags for the signed event of an entry
+pub fn imeta_tags(attachments: &[Attachment]) -> Result<Vec<Tag>, String> {
+    attachments
+        .iter()
+        .map(|attachment| {
+            let mut fields = vec![
+                "imeta".to_string(),
+                format!("m {}", attachment.mime_type),
+                format!("x {}", attachment.sha256),
+                format!("size {}", attachment.size_bytes),
+            ];
+            if let (Some(width), Some(height)) = (attachment.width, attachment.height) {
+                fields.push(format!("dim {}x{}", width, height));
+            }
+            fields.push(format!("alt {}", attachment.file_name));
+
+            Tag::parse(fields).map_err(|e| format!("Failed to create imeta tag: {}", e))
+        })
+        .collect()
+}
+
+// The entry of a day with its attachments and explicit tags, which are
+// needed to re-sign it
+async fn load_entry_with_attachments(
+    store: &DiaryStore,
+    day: &str,
+) -> Result<(crate::DiaryEntry, Vec<Attachment>, Vec<String>), String> {
+    let day = day.to_string();
+    store
+        .db
+        .call(move |conn| {
+            let entry = find_entry_for_day(conn, &day)
+                .map_err(|e| format!("Failed to load entry: {}", e))?
+                .ok_or_else(|| format!("No entry exists for day: {}", day))?;
+            let attachments = load_attachments(conn, &entry.id)
+                .map_err(|e| format!("Failed to load attachments: {}", e))?;
+            let manual_tags = crate::tag_service::load_manual_tags(conn, &entry.id)
+                .map_err(|e| format!("Failed to load tags: {}", e))?;
+            Ok((entry, attachments, manual_tags))
+        })
+        .await
+}
+
+// Attaches a file to the entry of a day and re-signs the entry so its event
+// references the new file
+#[tauri::command]
+pub async fn add_attachment(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+    file_name: String,
+    data: Vec<u8>,
+) -> Result<Attachment, String> {
+    if data.is_empty() {
+        return Err("Attachment is empty".to_string());
+    }
+    if data.len() > MAX_ATTACHMENT_BYTES {
+        return Err(format!(
+            "Attachment is too large, the limit is {} MB",
+            MAX_ATTACHMENT_BYTES / 1024 / 1024
+        ));
+    }
+
+    // Keep only the final path component of whatever name the webview sent
+    let file_name = Path::new(&file_name)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| "attachment".to_string());
+
+    let (entry, mut attachments, manual_tags) = load_entry_with_attachments(&store, &day).await?;
+
+    let sha256 = hex::encode(Sha256::digest(&data));
+    if let Some(existing) = attachments.iter().find(|a| a.sha256 == sha256) {
+        return Ok(existing.clone());
+    }
+
+    let mime_type = infer::get(&data)
+        .map(|kind| kind.mime_type().to_string())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    let (dimensions, thumbnail) = if mime_type.starts_with("image/") {
+        match create_thumbnail(&sha256, &data) {
+            Some((dimensions, thumbnail)) => (Some(dimensions), thumbnail),
+            None => (None, None),
+        }
+    } else {
+        (None, None)
+    };
+
+    let attachment = Attachment {
+        sha256,
+        file_name,
+        mime_type,
+        size_bytes: data.len() as u64,
+        width: dimensions.map(|(width, _)| width),
+        height: dimensions.map(|(_, height)| height),
+        created_at: Utc::now(),
+    };
+    attachments.push(attachment.clone());
+
+    let content = entry.content.clone();
+    let weather = entry.weather.clone();
+    let (updated, nostr_event_json) =
+        sign_revision(&store, entry, content, weather, &attachments, &manual_tags).await?;
+
+    let (stored, event_json) = (attachment.clone(), nostr_event_json.clone());
+    store
+        .db
+        .call(move |conn| {
+            let tx = conn
+                .transaction()
+                .map_err(|e| format!("Failed to start transaction: {}", e))?;
+            store_attachment(&tx, &updated.id, &stored, &data, thumbnail.as_deref())
+                .and_then(|_| save_updated_entry(&tx, &updated, &event_json, &manual_tags))
+                .and_then(|_| tx.commit())
+                .map_err(|e| save_error("Failed to save attachment", e))
+        })
+        .await?;
+    publish_revision(&store, &nostr_event_json).await;
+
+    println!(
+        "Attached {} ({}) to entry for day {}",
+        attachment.file_name, attachment.sha256, day
+    );
+    Ok(attachment)
+}
+
+#[tauri::command]
+pub async fn list_attachments(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+) -> Result<Vec<Attachment>, String> {
+    load_entry_with_attachments(&store, &day)
+        .await
+        .map(|(_, attachments, _)| attachments)
+}
+
+// Returns the contents of an attachment, or its JPEG thumbnail when
+// `thumbnail` is set. Images without a thumbnail fall back to the original.
+#[tauri::command]
+pub async fn get_attachment(
+    store: State<'_, Arc<DiaryStore>>,
+    sha256: String,
+    thumbnail: Option<bool>,
+) -> Result<Vec<u8>, String> {
+    // Only hand out files that belong to an entry, never arbitrary paths
+    let sha256 = sha256.to_ascii_lowercase();
+    if !is_valid_sha256(&sha256) {
+        return Err(format!("Invalid attachment hash: {}", sha256));
+    }
+    let lookup = sha256.clone();
+    store
+        .db
+        .call(move |conn| {
+            load_attachment_data(conn, &lookup, thumbnail.unwrap_or(false))
+                .map_err(|e| format!("Failed to read attachment: {}", e))
+        })
+        .await?
+        .ok_or_else(|| format!("Attachment not found: {}", sha256))
+}
+
+// Detaches a file from the entry of a day and re-signs the entry. The stored
+// contents are deleted once no other entry uses them.
+#[tauri::command]
+pub async fn remove_attachment(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+    sha256: String,
+) -> Result<(), String> {
+    let sha256 = sha256.to_ascii_lowercase();
+    if !is_valid_sha256(&sha256) {
+        return Err(format!("Invalid attachment hash: {}", sha256));
+    }
+    let (entry, mut attachments, manual_tags) = load_entry_with_attachments(&store, &day).await?;
+
+    let before = attachments.len();
+    attachments.retain(|attachment| attachment.sha256 != sha256);
+    if attachments.len() == before {
+        return Err(format!("Attachment not found: {}", sha256));
+    }
+
+    let content = entry.content.clone();
+    let weather = entry.weather.clone();
+    let (updated, nostr_event_json) =
+        sign_revision(&store, entry, content, weather, &attachments, &manual_tags).await?;
+
+    let event_json = nostr_event_json.clone();
+    store
+        .db
+        .call(move |conn| {
+            let tx = conn
+                .transaction()
+                .map_err(|e| format!("Failed to start transaction: {}", e))?;
+            delete_attachment(&tx, &updated.id, &sha256)
+                .and_then(|_| save_updated_entry(&tx, &updated, &event_json, &manual_tags))
+                .and_then(|_| tx.commit())
+                .map_err(|e| save_error("Failed to remove attachment", e))
+        })
+        .await?;
+    publish_revision(&store, &nostr_event_json).await;
+
+    println!("Removed attachment from entry for day {}", day);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_connection() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        conn
+    }
+
+    fn text_attachment(data: &[u8]) -> Attachment {
+        Attachment {
+            sha256: hex::encode(Sha256::digest(data)),
+            file_name: "note.txt".to_string(),
+            mime_type: "text/plain".to_string(),
+            size_bytes: data.len() as u64,
+            width: None,
+            height: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn blob_count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM attachment_blobs", [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_same_file_is_stored_once() {
+        let conn = test_connection();
+        let attachment = text_attachment(b"hello");
+
+        store_attachment(&conn, "a", &attachment, b"hello", None).unwrap();
+        store_attachment(&conn, "b", &attachment, b"hello", None).unwrap();
+
+        assert_eq!(blob_count(&conn), 1);
+        assert_eq!(load_attachments(&conn, "a").unwrap().len(), 1);
+        assert_eq!(load_attachments(&conn, "b").unwrap().len(), 1);
+        assert_eq!(
+            load_attachment_data(&conn, &attachment.sha256, false).unwrap(),
+            Some(b"hello".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_contents_are_deleted_with_the_last_reference() {
+        let conn = test_connection();
+        let attachment = text_attachment(b"hello");
+        store_attachment(&conn, "a", &attachment, b"hello", None).unwrap();
+        store_attachment(&conn, "b", &attachment, b"hello", None).unwrap();
+
+        delete_attachment(&conn, "a", &attachment.sha256).unwrap();
+        assert!(load_attachments(&conn, "a").unwrap().is_empty());
+        assert_eq!(blob_count(&conn), 1);
+
+        delete_attachment(&conn, "b", &attachment.sha256).unwrap();
+        assert_eq!(blob_count(&conn), 0);
+        assert_eq!(
+            load_attachment_data(&conn, &attachment.sha256, false).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unreferenced_blobs_are_removed() {
+        let conn = test_connection();
+        let kept = text_attachment(b"kept");
+        store_attachment(&conn, "a", &kept, b"kept", Some(b"thumb")).unwrap();
+        conn.execute(
+            "INSERT INTO attachment_blobs (sha256, data) VALUES ('orphan', x'00')",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(remove_unreferenced_blobs(&conn).unwrap(), 1);
+        assert_eq!(blob_count(&conn), 1);
+        assert_eq!(
+            load_attachment_data(&conn, &kept.sha256, true).unwrap(),
+            Some(b"thumb".to_vec())
+        );
+    }
+}
@@ -0,0 +1,344 @@
+use chrono::{DateTime, Utc};
+use nostr_sdk::{Event, Keys, Kind, TagKind};
+use rusqlite::{Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tauri::State;
+
+use crate::DiaryStore;
+
+// The migrate-jrnl script fills pubkey and sig with zeros instead of signing
+const PLACEHOLDER_PUBKEY: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// One problem found with an entry
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditProblem {
+    MissingEvent,
+    UnparseableEvent {
+        error: String,
+    },
+    // All-zero pubkey and signature, written by the jrnl import
+    PlaceholderSignature,
+    InvalidId,
+    InvalidSignature,
+    ForeignPubkey {
+        pubkey: String,
+    },
+    WrongKind {
+        event_kind: u16,
+    },
+    NostrIdMismatch {
+        column: Option<String>,
+        event: String,
+    },
+    ContentMismatch,
+    DayTagMismatch {
+        tag: Option<String>,
+    },
+    WeatherTagMismatch {
+        tag: Option<String>,
+    },
+    DecryptionFailed {
+        error: String,
+    },
+    // The private column disagrees with whether the event is encrypted
+    VisibilityMismatch {
+        event_private: bool,
+    },
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct EntryAudit {
+    pub entry_id: String,
+    pub day: Option<String>,
+    pub nostr_id: Option<String>,
+    pub trashed: bool,
+    pub problems: Vec<AuditProblem>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct AuditReport {
+    pub checked_at: DateTime<Utc>,
+    pub pubkey: String,
+    pub total_entries: usize,
+    pub valid_entries: usize,
+    // Only entries with at least one problem
+    pub entries: Vec<EntryAudit>,
+}
+
+// The stored columns of a row, read as plain text so that rows written by
+// older versions or external scripts can still be audited
+struct StoredRow {
+    id: String,
+    day: Option<String>,
+    content: String,
+    weather: String,
+    nostr_id: Option<String>,
+    nostr_event: Option<String>,
+    trashed: bool,
+    private: bool,
+}
+
+fn load_stored_rows(conn: &Connection) -> SqlResult<Vec<StoredRow>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, day, content, weather, nostr_id, nostr_event, deleted_at IS NOT NULL, private
+         FROM diary_entries ORDER BY day",
+    )?;
+    let rows_iter = stmt.query_map([], |row| {
+        Ok(StoredRow {
+            id: row.get(0)?,
+            day: row.get(1)?,
+            content: row.get(2)?,
+            weather: row.get(3)?,
+            nostr_id: row.get(4)?,
+            nostr_event: row.get(5)?,
+            trashed: row.get(6)?,
+            private: row.get(7)?,
+        })
+    })?;
+
+    let mut rows = Vec::new();
+    for row in rows_iter {
+        rows.push(row?);
+    }
+    Ok(rows)
+}
+
+// The placeholder events are not valid Nostr events (the id is a UUID), so
+// they are recognised from the raw JSON before strict parsing
+fn has_placeholder_signature(event_json: &str) -> bool {
+    let value: serde_json::Value = match serde_json::from_str(event_json) {
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+    let is_zeros = |field: &str| {
+        value[field]
+            .as_str()
+            .map(|s| !s.is_empty() && s.chars().all(|c| c == '0'))
+            .unwrap_or(false)
+    };
+    value["pubkey"].as_str() == Some(PLACEHOLDER_PUBKEY) || is_zeros("sig")
+}
+
+fn tag_value(event: &Event, name: &str) -> Option<String> {
+    event
+        .tags
+        .find(TagKind::custom(name))
+        .and_then(|tag| tag.content())
+        .map(|value| value.to_string())
+}
+
+fn audit_row(row: &StoredRow, keys: &Keys) -> Vec<AuditProblem> {
+    let pubkey = keys.public_key().to_string();
+    let event_json = match row.nostr_event.as_deref() {
+        Some(json) if !json.trim().is_empty() => json,
+        _ => return vec![AuditProblem::MissingEvent],
+    };
+
+    if has_placeholder_signature(event_json) {
+        return vec![AuditProblem::PlaceholderSignature];
+    }
+
+    let event: Event = match serde_json::from_str(event_json) {
+        Ok(event) => event,
+        Err(e) => {
+            return vec![AuditProblem::UnparseableEvent {
+                error: e.to_string(),
+            }]
+        }
+    };
+
+    let mut problems = Vec::new();
+    if !event.verify_id() {
+        problems.push(AuditProblem::InvalidId);
+    }
+    if !event.verify_signature() {
+        problems.push(AuditProblem::InvalidSignature);
+    }
+    if event.pubkey.to_string() != pubkey {
+        problems.push(AuditProblem::ForeignPubkey {
+            pubkey: event.pubkey.to_string(),
+        });
+    }
+    if event.kind != Kind::from(30027) {
+        problems.push(AuditProblem::WrongKind {
+            event_kind: event.kind.as_u16(),
+        });
+    }
+    if row.nostr_id.as_deref() != Some(event.id.to_string().as_str()) {
+        problems.push(AuditProblem::NostrIdMismatch {
+            column: row.nostr_id.clone(),
+            event: event.id.to_string(),
+        });
+    }
+    if crate::private_event::is_private(&event) != row.private {
+        problems.push(AuditProblem::VisibilityMismatch {
+            event_private: !row.private,
+        });
+    }
+
+    // Private events are compared by their decrypted content and tags
+    let event = match crate::private_event::open_event(keys, &event) {
+        Ok(event) => event,
+        Err(error) => {
+            problems.push(AuditProblem::DecryptionFailed { error });
+            return problems;
+        }
+    };
+    if event.content != row.content {
+        problems.push(AuditProblem::ContentMismatch);
+    }
+
+    let day_tag = tag_value(&event, "d");
+    if day_tag.is_none() || day_tag != row.day {
+        problems.push(AuditProblem::DayTagMismatch { tag: day_tag });
+    }
+    let weather_tag = tag_value(&event, "weather");
+    if weather_tag.as_deref() != Some(row.weather.as_str()) {
+        problems.push(AuditProblem::WeatherTagMismatch { tag: weather_tag });
+    }
+
+    problems
+}
+
+fn audit_rows(rows: &[StoredRow], keys: &Keys) -> AuditReport {
+    let entries: Vec<EntryAudit> = rows
+        .iter()
+        .filter_map(|row| {
+            let problems = audit_row(row, keys);
+            if problems.is_empty() {
+                return None;
+            }
+            Some(EntryAudit {
+                entry_id: row.id.clone(),
+                day: row.day.clone(),
+                nostr_id: row.nostr_id.clone(),
+                trashed: row.trashed,
+                problems,
+            })
+        })
+        .collect();
+
+    AuditReport {
+        checked_at: Utc::now(),
+        pubkey: keys.public_key().to_string(),
+        total_entries: rows.len(),
+        valid_entries: rows.len() - entries.len(),
+        entries,
+    }
+}
+
+// Checks every stored entry, trashed ones included, against its signed event.
+// Auditing never generates keys, entries are checked against the identity
+// that should have signed them.
+#[tauri::command]
+pub async fn audit_diary(store: State<'_, Arc<DiaryStore>>) -> Result<AuditReport, String> {
+    let keys = crate::get_existing_nostr_keys(&store)?
+        .ok_or_else(|| "No Nostr keys found, there is no identity to audit against".to_string())?;
+
+    let rows = store
+        .db
+        .call(|conn| {
+            load_stored_rows(conn).map_err(|e| format!("Failed to load diary entries: {}", e))
+        })
+        .await?;
+
+    let report = audit_rows(&rows, &keys);
+    println!(
+        "Audited {} entries, {} with problems",
+        report.total_entries,
+        report.entries.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Tag};
+
+    fn signed_row(keys: &Keys, content: &str) -> StoredRow {
+        let event = EventBuilder::new(Kind::Custom(30027), content)
+            .tags(vec![
+                Tag::identifier("2024-03-01"),
+                Tag::parse(vec!["weather".to_string(), "晴".to_string()]).unwrap(),
+            ])
+            .sign_with_keys(keys)
+            .unwrap();
+        StoredRow {
+            id: "a".to_string(),
+            day: Some("2024-03-01".to_string()),
+            content: content.to_string(),
+            weather: "晴".to_string(),
+            nostr_id: Some(event.id.to_string()),
+            nostr_event: Some(serde_json::to_string(&event).unwrap()),
+            trashed: false,
+            private: false,
+        }
+    }
+
+    #[test]
+    fn test_signed_entry_has_no_problems() {
+        let keys = Keys::generate();
+        assert_eq!(audit_row(&signed_row(&keys, "今天晴"), &keys), vec![]);
+    }
+
+    #[test]
+    fn test_jrnl_placeholder_is_reported() {
+        let keys = Keys::generate();
+        let zeros = "0".repeat(128);
+        let event_json = serde_json::json!({
+            "id": "6f1c1e4e2b1a4d559a0e3b7b1f2d9c10",
+            "pubkey": PLACEHOLDER_PUBKEY,
+            "created_at": 1709251200,
+            "kind": 30027,
+            "tags": [["d", "2024-03-01"], ["weather", "晴"]],
+            "content": "今天晴",
+            "sig": zeros,
+        });
+        let row = StoredRow {
+            nostr_id: Some("6f1c1e4e2b1a4d559a0e3b7b1f2d9c10".to_string()),
+            nostr_event: Some(event_json.to_string()),
+            ..signed_row(&keys, "今天晴")
+        };
+        assert_eq!(
+            audit_row(&row, &keys),
+            vec![AuditProblem::PlaceholderSignature]
+        );
+    }
+
+    #[test]
+    fn test_missing_event_is_reported() {
+        let keys = Keys::generate();
+        for nostr_event in [None, Some("  ".to_string())] {
+            let row = StoredRow {
+                nostr_event,
+                ..signed_row(&keys, "今天晴")
+            };
+            assert_eq!(audit_row(&row, &keys), vec![AuditProblem::MissingEvent]);
+        }
+    }
+
+    #[test]
+    fn test_edited_content_is_reported() {
+        let keys = Keys::generate();
+        let row = StoredRow {
+            content: "今天下雨".to_string(),
+            ..signed_row(&keys, "今天晴")
+        };
+        assert_eq!(audit_row(&row, &keys), vec![AuditProblem::ContentMismatch]);
+    }
+
+    #[test]
+    fn test_entry_signed_by_another_key_is_reported() {
+        let keys = Keys::generate();
+        let other = Keys::generate();
+        assert_eq!(
+            audit_row(&signed_row(&other, "今天晴"), &keys),
+            vec![AuditProblem::ForeignPubkey {
+                pubkey: other.public_key().to_string()
+            }]
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rusqlite::backup::Backup;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tauri::State;
+
+use crate::DiaryStore;
+
+const BACKUP_PREFIX: &str = "diary-";
+const BACKUP_EXTENSION: &str = "db";
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+// How often the scheduler checks whether a scheduled backup is due
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const PAGES_PER_STEP: std::os::raw::c_int = 256;
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct BackupInfo {
+    pub file_name: String,
+    pub reason: String, // "manual", "scheduled", "before-purge", ...
+    pub created_at: DateTime<Utc>,
+    pub size_bytes: u64,
+}
+
+pub fn get_backups_dir() -> PathBuf {
+    let backups_dir = crate::get_data_dir().join("backups");
+    fs::create_dir_all(&backups_dir).expect("Failed to create backups directory");
+    backups_dir
+}
+
+// Backup files are named diary-<UTC timestamp>-<reason>.db
+fn parse_backup_name(file_name: &str) -> Option<(DateTime<Utc>, String)> {
+    let stem = file_name
+        .strip_prefix(BACKUP_PREFIX)?
+        .strip_suffix(&format!(".{}", BACKUP_EXTENSION))?;
+    let timestamp = stem.get(..15)?;
+    let reason = stem.get(16..)?;
+    let created_at = NaiveDateTime::parse_from_str(timestamp, BACKUP_TIME_FORMAT).ok()?;
+    Some((created_at.and_utc(), reason.to_string()))
+}
+
+// All backups, newest first
+pub fn list_backup_files() -> Vec<BackupInfo> {
+    let mut backups: Vec<BackupInfo> = match fs::read_dir(get_backups_dir()) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let (created_at, reason) = parse_backup_name(&file_name)?;
+                let size_bytes = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                Some(BackupInfo {
+                    file_name,
+                    reason,
+                    created_at,
+                    size_bytes,
+                })
+            })
+            .collect(),
+        Err(e) => {
+            println!("Failed to read backups directory: {}", e);
+            Vec::new()
+        }
+    };
+
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+    backups
+}
+
+// Opens a backup file with the current passphrase when the diary is encrypted,
+// since SQLCipher only copies pages between databases sharing the same key
+fn open_backup_file(path: &Path) -> rusqlite::Result<Connection> {
+    match crate::vault::session_passphrase() {
+        Some(passphrase) => crate::vault::open_encrypted(path, &passphrase),
+        None => Connection::open(path),
+    }
+}
+
+fn remove_old_backups(keep: usize) {
+    for backup in list_backup_files().into_iter().skip(keep.max(1)) {
+        match fs::remove_file(get_backups_dir().join(&backup.file_name)) {
+            Ok(_) => println!("Removed old backup: {}", backup.file_name),
+            Err(e) => println!("Failed to remove old backup {}: {}", backup.file_name, e),
+        }
+    }
+}
+
+// Copies the database behind `conn` to `dest_path` with the online backup API
+fn copy_database(conn: &Connection, dest_path: &Path) -> Result<(), String> {
+    let mut dest =
+        open_backup_file(dest_path).map_err(|e| format!("Failed to create backup file: {}", e))?;
+    let backup =
+        Backup::new(conn, &mut dest).map_err(|e| format!("Failed to start backup: {}", e))?;
+    backup
+        .run_to_completion(PAGES_PER_STEP, Duration::ZERO, None)
+        .map_err(|e| format!("Failed to back up database: {}", e))
+}
+
+// Replaces the database behind `conn` with the backup at `source_path`
+fn restore_database(conn: &mut Connection, source_path: &Path) -> Result<u32, String> {
+    let source =
+        open_backup_file(source_path).map_err(|e| format!("Failed to open backup: {}", e))?;
+    // Never replace the live database with a schema this app cannot read
+    let version = crate::migrations::current_version(&source)
+        .map_err(|e| format!("Failed to read backup: {}", e))?;
+    let latest = crate::migrations::latest_version(crate::migrations::MIGRATIONS);
+    if version > latest {
+        return Err(format!(
+            "Failed to restore backup: {}",
+            crate::migrations::newer_schema_error(version, latest)
+        ));
+    }
+    {
+        let backup =
+            Backup::new(&source, conn).map_err(|e| format!("Failed to start restore: {}", e))?;
+        backup
+            .run_to_completion(PAGES_PER_STEP, Duration::ZERO, None)
+            .map_err(|e| format!("Failed to restore database: {}", e))?;
+    }
+
+    // Backups taken by an older version of the app may use an older schema
+    crate::migrations::migrate(conn, None)
+        .map_err(|e| format!("Failed to migrate restored database: {}", e))
+}
+
+// Copies the live database into a new backup file, then drops the oldest
+// copies beyond the configured count
+pub async fn create_backup_file(
+    store: &DiaryStore,
+    reason: &str,
+    keep: usize,
+) -> Result<BackupInfo, String> {
+    if !crate::vault::is_unlocked() {
+        return Err("The diary is locked, unlock it first".to_string());
+    }
+
+    let created_at = Utc::now();
+    let file_name = format!(
+        "{}{}-{}.{}",
+        BACKUP_PREFIX,
+        created_at.format(BACKUP_TIME_FORMAT),
+        reason,
+        BACKUP_EXTENSION
+    );
+    let path = get_backups_dir().join(&file_name);
+
+    let dest_path = path.clone();
+    store
+        .db
+        .call(move |conn| copy_database(conn, &dest_path))
+        .await?;
+
+    println!("Created backup: {}", path.display());
+    remove_old_backups(keep);
+
+    Ok(BackupInfo {
+        file_name,
+        reason: reason.to_string(),
+        created_at,
+        size_bytes: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+    })
+}
+
+// Takes a safety copy before an operation that destroys or replaces data.
+// The operation should not go ahead if this fails.
+pub async fn backup_before(store: &DiaryStore, operation: &str) -> Result<(), String> {
+    let keep = store.config.lock().unwrap().backup_keep_count;
+    create_backup_file(store, &format!("before-{}", operation), keep)
+        .await
+        .map(|_| ())
+}
+
+// Creates a scheduled backup whenever the newest backup is older than the
+// configured interval. Runs for the lifetime of the app.
+pub fn start_backup_scheduler(store: Arc<DiaryStore>) {
+    thread::spawn(move || loop {
+        let (interval_hours, keep) = {
+            let config = store.config.lock().unwrap();
+            (config.backup_interval_hours, config.backup_keep_count)
+        };
+
+        if interval_hours > 0 && crate::vault::is_unlocked() {
+            let due = match list_backup_files().first() {
+                Some(latest) => {
+                    Utc::now() - latest.created_at >= chrono::Duration::hours(interval_hours as i64)
+                }
+                None => true,
+            };
+            if due {
+                let backup = create_backup_file(&store, "scheduled", keep);
+                if let Err(e) = futures::executor::block_on(backup) {
+                    println!("Scheduled backup failed: {}", e);
+                }
+            }
+        }
+
+        thread::sleep(SCHEDULE_CHECK_INTERVAL);
+    });
+}
+
+#[tauri::command]
+pub fn list_backups() -> Result<Vec<BackupInfo>, String> {
+    Ok(list_backup_files())
+}
+
+#[tauri::command]
+pub async fn create_backup(store: State<'_, Arc<DiaryStore>>) -> Result<BackupInfo, String> {
+    let keep = store.config.lock().unwrap().backup_keep_count;
+    create_backup_file(&store, "manual", keep).await
+}
+
+// Replaces the live database with the contents of a backup. The current state
+// is backed up first so a restore can itself be undone.
+#[tauri::command]
+pub async fn restore_backup(
+    store: State<'_, Arc<DiaryStore>>,
+    file_name: String,
+) -> Result<(), String> {
+    // Only accept names from the backups directory, never arbitrary paths
+    if !list_backup_files()
+        .iter()
+        .any(|backup| backup.file_name == file_name)
+    {
+        return Err(format!("Backup not found: {}", file_name));
+    }
+
+    // Prune only after the restore so the safety copy cannot rotate out the
+    // backup being restored
+    let keep = store.config.lock().unwrap().backup_keep_count;
+    create_backup_file(&store, "before-restore", usize::MAX).await?;
+
+    let source_path = get_backups_dir().join(&file_name);
+    store
+        .db
+        .call(move |conn| restore_database(conn, &source_path))
+        .await?;
+
+    remove_old_backups(keep);
+
+    println!("Restored database from backup: {}", file_name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_backup_path() -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("luxun-dairy-backups-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("diary-20240301-120000-manual.db")
+    }
+
+    fn test_connection() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        conn.execute(
+            "INSERT INTO diary_entries (id, content, weather, created_at, day)
+             VALUES ('a', '备份前', '晴', 'now', '2024-03-01')",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn contents(conn: &Connection) -> Vec<String> {
+        conn.prepare("SELECT content FROM diary_entries ORDER BY day")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_backup_round_trip() {
+        let mut conn = test_connection();
+        let path = temp_backup_path();
+        copy_database(&conn, &path).unwrap();
+
+        conn.execute_batch(
+            "UPDATE diary_entries SET content = '备份后';
+             INSERT INTO diary_entries (id, content, weather, created_at, day)
+             VALUES ('b', '新的', '雨', 'now', '2024-03-02');",
+        )
+        .unwrap();
+        let latest = crate::migrations::latest_version(crate::migrations::MIGRATIONS);
+        assert_eq!(restore_database(&mut conn, &path).unwrap(), latest);
+        assert_eq!(contents(&conn), vec!["备份前"]);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_backup_from_a_newer_version_is_refused() {
+        let mut conn = test_connection();
+        let path = temp_backup_path();
+        copy_database(&conn, &path).unwrap();
+        let latest = crate::migrations::latest_version(crate::migrations::MIGRATIONS);
+        Connection::open(&path)
+            .unwrap()
+            .pragma_update(None, "user_version", latest + 1)
+            .unwrap();
+
+        conn.execute("UPDATE diary_entries SET content = '备份后'", [])
+            .unwrap();
+        assert!(restore_database(&mut conn, &path).is_err());
+        assert_eq!(contents(&conn), vec!["备份后"]);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_backup_names_are_parsed() {
+        let (created_at, reason) =
+            parse_backup_name("diary-20240301-120000-before-restore.db").unwrap();
+        assert_eq!(created_at.to_rfc3339(), "2024-03-01T12:00:00+00:00");
+        assert_eq!(reason, "before-restore");
+        assert!(parse_backup_name("diary.db.v3.bak").is_none());
+        assert!(parse_backup_name("diary-2024-manual.db").is_none());
+    }
+}
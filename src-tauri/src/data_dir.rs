@@ -0,0 +1,516 @@
+// Location of the diary data directory
+//
+// The directory is resolved once, in this order:
+//   1. the `--data-dir <path>` command line argument
+//   2. the LUXUN_DIARY_DATA_DIR environment variable
+//   3. portable mode, when started with `--portable` or when a file named
+//      `portable` sits next to the executable: `data/` next to the executable
+//   4. a location recorded by move_data_dir in the default directory
+//   5. the platform default, e.g. ~/.local/share/diary on Linux
+use chrono::Utc;
+use directories::ProjectDirs;
+use rusqlite::backup::Backup;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, State};
+
+use crate::DiaryStore;
+
+pub const DATA_DIR_ENV: &str = "LUXUN_DIARY_DATA_DIR";
+const DATA_DIR_ARG: &str = "--data-dir";
+const PORTABLE_ARG: &str = "--portable";
+const PORTABLE_MARKER: &str = "portable";
+const PORTABLE_DATA_DIR: &str = "data";
+// Written to the default directory when the data has been moved elsewhere
+const LOCATION_FILE: &str = "data_dir_location.txt";
+// Left behind in the default directory when the data moves back to it
+const STALE_DIR_PREFIX: &str = "replaced-";
+// Time for the frontend to receive the result before the app restarts
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+// Files and directories that make up the diary, moved together
+const DATA_FILES: &[&str] = &["nostr_keys.json", "config.json"];
+const DATA_DIRS: &[&str] = &["common_diaries", "backups"];
+
+static DATA_DIR: OnceLock<ResolvedDataDir> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DataDirSource {
+    CommandLine,
+    Environment,
+    Portable,
+    Moved,
+    Default,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ResolvedDataDir {
+    pub path: PathBuf,
+    pub source: DataDirSource,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DataDirMove {
+    pub old_path: PathBuf,
+    pub new_path: PathBuf,
+    pub restart_required: bool,
+}
+
+fn default_data_dir() -> PathBuf {
+    match ProjectDirs::from("com", "luxun", "diary") {
+        Some(proj_dirs) => proj_dirs.data_dir().to_path_buf(),
+        // No home directory, e.g. a stripped-down service account
+        None => executable_dir().join(PORTABLE_DATA_DIR),
+    }
+}
+
+fn executable_dir() -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn data_dir_from_args(args: &[String]) -> Option<PathBuf> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == DATA_DIR_ARG {
+            return iter.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix(&format!("{}=", DATA_DIR_ARG)) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+fn read_location_file(default_dir: &Path) -> Option<PathBuf> {
+    let contents = fs::read_to_string(default_dir.join(LOCATION_FILE)).ok()?;
+    let path = contents.trim();
+    if path.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(path))
+    }
+}
+
+fn resolve() -> ResolvedDataDir {
+    let args: Vec<String> = env::args().collect();
+
+    let (path, source) = if let Some(path) = data_dir_from_args(&args) {
+        (path, DataDirSource::CommandLine)
+    } else if let Some(path) = env::var_os(DATA_DIR_ENV).filter(|value| !value.is_empty()) {
+        (PathBuf::from(path), DataDirSource::Environment)
+    } else if args.iter().any(|arg| arg == PORTABLE_ARG)
+        || executable_dir().join(PORTABLE_MARKER).exists()
+    {
+        (
+            executable_dir().join(PORTABLE_DATA_DIR),
+            DataDirSource::Portable,
+        )
+    } else if let Some(path) = read_location_file(&default_data_dir()) {
+        (path, DataDirSource::Moved)
+    } else {
+        (default_data_dir(), DataDirSource::Default)
+    };
+
+    if let Err(e) = fs::create_dir_all(&path) {
+        panic!("Failed to create data directory {}: {}", path.display(), e);
+    }
+    println!("Data directory: {} ({:?})", path.display(), source);
+    ResolvedDataDir { path, source }
+}
+
+pub fn resolved() -> &'static ResolvedDataDir {
+    DATA_DIR.get_or_init(resolve)
+}
+
+fn copy_dir_recursive(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_recursive(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+fn count_entries(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row("SELECT COUNT(*) FROM diary_entries", [], |row| row.get(0))
+}
+
+fn open_database_copy(path: &Path) -> rusqlite::Result<Connection> {
+    match crate::vault::session_passphrase() {
+        Some(passphrase) => crate::vault::open_encrypted(path, &passphrase),
+        None => Connection::open(path),
+    }
+}
+
+// Checks that a copied database opens and holds the same number of entries
+fn verify_copied_database(path: &Path, expected_entries: i64) -> Result<(), String> {
+    let conn =
+        open_database_copy(path).map_err(|e| format!("Failed to open copied database: {}", e))?;
+
+    let copied_entries =
+        count_entries(&conn).map_err(|e| format!("Failed to read copied database: {}", e))?;
+    if copied_entries != expected_entries {
+        return Err(format!(
+            "Copied database has {} entries, expected {}",
+            copied_entries, expected_entries
+        ));
+    }
+    Ok(())
+}
+
+fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path)
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(false)
+}
+
+// The default directory still holds the diary as it was before it was moved
+// away. Moving back sets that copy aside, so it can neither be mixed with the
+// current data nor be lost. Returns where it went, if there was anything.
+fn set_aside_stale_data(default_dir: &Path) -> Result<Option<PathBuf>, String> {
+    let stale_dir = default_dir.join(format!(
+        "{}{}",
+        STALE_DIR_PREFIX,
+        Utc::now().format("%Y%m%d-%H%M%S")
+    ));
+    let entries = fs::read_dir(default_dir)
+        .map_err(|e| format!("Failed to read {}: {}", default_dir.display(), e))?;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == LOCATION_FILE || name.starts_with(STALE_DIR_PREFIX) {
+            continue;
+        }
+        fs::create_dir_all(&stale_dir)
+            .map_err(|e| format!("Failed to create {}: {}", stale_dir.display(), e))?;
+        fs::rename(entry.path(), stale_dir.join(&name))
+            .map_err(|e| format!("Failed to set aside {}: {}", name, e))?;
+    }
+    Ok(Some(stale_dir).filter(|dir| dir.exists()))
+}
+
+// Removes whatever a failed move copied into `target`, and puts back the data
+// that was set aside when moving back to the default directory
+fn roll_back_move(target: &Path, stale_dir: Option<&Path>) {
+    if let Ok(entries) = fs::read_dir(target) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name == LOCATION_FILE || name.starts_with(STALE_DIR_PREFIX) {
+                continue;
+            }
+            let removed = if entry.path().is_dir() {
+                fs::remove_dir_all(entry.path())
+            } else {
+                fs::remove_file(entry.path())
+            };
+            if let Err(e) = removed {
+                println!("Failed to remove partly copied {}: {}", name, e);
+            }
+        }
+    }
+
+    let Some(stale_dir) = stale_dir else {
+        return;
+    };
+    if let Ok(entries) = fs::read_dir(stale_dir) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            if let Err(e) = fs::rename(entry.path(), target.join(entry.file_name())) {
+                println!(
+                    "Failed to put back {}: {}",
+                    entry.file_name().to_string_lossy(),
+                    e
+                );
+            }
+        }
+    }
+    let _ = fs::remove_dir(stale_dir);
+}
+
+// Copies the live database and the other data files from `source_dir`
+fn copy_data(conn: &Connection, source_dir: &Path, target: &Path) -> Result<(), String> {
+    // A consistent snapshot of the live database, encrypted with the same
+    // key when the diary has a passphrase
+    let db_target = target.join("diary.db");
+    let mut dest = open_database_copy(&db_target)
+        .map_err(|e| format!("Failed to create database copy: {}", e))?;
+    Backup::new(conn, &mut dest)
+        .and_then(|backup| backup.run_to_completion(256, Duration::ZERO, None))
+        .map_err(|e| format!("Failed to copy database: {}", e))?;
+    drop(dest);
+    let entry_count = count_entries(conn).map_err(|e| format!("Failed to count entries: {}", e))?;
+    verify_copied_database(&db_target, entry_count)?;
+
+    for file in DATA_FILES {
+        let source = source_dir.join(file);
+        if source.exists() {
+            fs::copy(&source, target.join(file))
+                .map_err(|e| format!("Failed to copy {}: {}", file, e))?;
+        }
+    }
+    for dir in DATA_DIRS {
+        let source = source_dir.join(dir);
+        if source.exists() {
+            copy_dir_recursive(&source, &target.join(dir))
+                .map_err(|e| format!("Failed to copy {}: {}", dir, e))?;
+        }
+    }
+    Ok(())
+}
+
+// Copies the data to `target` and points the default directory at it, or
+// removes the pointer when moving back to the default. A move that fails
+// part way is rolled back, so it can be retried.
+fn move_data(
+    conn: &Connection,
+    source_dir: &Path,
+    target: &Path,
+    default_dir: &Path,
+) -> Result<(), String> {
+    let moving_back = target == default_dir;
+    let stale_dir = if moving_back {
+        set_aside_stale_data(default_dir)?
+    } else if target.exists() && !is_empty_dir(target) {
+        return Err(format!(
+            "The new data directory must be empty: {}",
+            target.display()
+        ));
+    } else {
+        None
+    };
+
+    let location_file = default_dir.join(LOCATION_FILE);
+    let moved = fs::create_dir_all(target)
+        .map_err(|e| format!("Failed to create {}: {}", target.display(), e))
+        .and_then(|_| copy_data(conn, source_dir, target))
+        .and_then(|_| {
+            if moving_back {
+                fs::remove_file(&location_file)
+                    .map_err(|e| format!("Failed to remove the old data directory record: {}", e))
+            } else {
+                fs::create_dir_all(default_dir)
+                    .map_err(|e| format!("Failed to create {}: {}", default_dir.display(), e))?;
+                fs::write(&location_file, target.to_string_lossy().as_bytes())
+                    .map_err(|e| format!("Failed to record the new data directory: {}", e))
+            }
+        });
+    if moved.is_err() {
+        roll_back_move(target, stale_dir.as_deref());
+    }
+    moved
+}
+
+#[tauri::command]
+pub fn get_data_dir_info() -> ResolvedDataDir {
+    resolved().clone()
+}
+
+// Copies the database (attachments included), keys, config, common diaries
+// and backups to a new directory and makes it the data directory. The old
+// directory is left untouched so nothing is lost if the copy is incomplete;
+// it can be deleted by hand once the app runs from the new location. Once
+// copied, the database is closed and the app restarts, so nothing more is
+// written to the old directory.
+#[tauri::command]
+pub async fn move_data_dir(
+    app: AppHandle,
+    store: State<'_, Arc<DiaryStore>>,
+    target_dir: String,
+) -> Result<DataDirMove, String> {
+    let current = resolved();
+    match current.source {
+        DataDirSource::CommandLine | DataDirSource::Environment | DataDirSource::Portable => {
+            return Err(format!(
+                "The data directory is set by {:?}, change it there instead",
+                current.source
+            ));
+        }
+        DataDirSource::Moved | DataDirSource::Default => {}
+    }
+    if !crate::vault::is_unlocked() {
+        return Err("The diary is locked, unlock it first".to_string());
+    }
+
+    let target = PathBuf::from(target_dir.trim());
+    if !target.is_absolute() {
+        return Err("The new data directory must be an absolute path".to_string());
+    }
+    if target.starts_with(&current.path) || current.path.starts_with(&target) {
+        return Err(
+            "The new data directory cannot contain or be inside the current one".to_string(),
+        );
+    }
+    let default_dir = default_data_dir();
+
+    // Everything is copied on the database thread while the config is
+    // locked, so no entry or setting can be written in the meantime
+    let source_dir = current.path.clone();
+    let target_copy = target.clone();
+    let store_copy = store.inner().clone();
+    store
+        .db
+        .call(move |conn| {
+            let _config = store_copy.config.lock().unwrap();
+            move_data(conn, &source_dir, &target_copy, &default_dir)?;
+
+            // Nothing may reach the old database any more. Later queries
+            // fail until the app has restarted into the new directory.
+            *conn = Connection::open_in_memory()
+                .map_err(|e| format!("Failed to close database: {}", e))?;
+            Ok(())
+        })
+        .await?;
+
+    thread::spawn(move || {
+        thread::sleep(RESTART_DELAY);
+        app.restart();
+    });
+
+    println!(
+        "Copied data directory from {} to {}",
+        current.path.display(),
+        target.display()
+    );
+    Ok(DataDirMove {
+        old_path: current.path.clone(),
+        new_path: target,
+        restart_required: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "luxun-dairy-data-dir-{}-{}",
+            name,
+            uuid::Uuid::new_v4()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A data directory with every kind of file, and its open database
+    fn source_dir() -> (PathBuf, Connection) {
+        let dir = temp_dir("source");
+        fs::write(dir.join("config.json"), "{}").unwrap();
+        fs::write(dir.join("nostr_keys.json"), "{}").unwrap();
+        fs::create_dir_all(dir.join("common_diaries").join("luxun")).unwrap();
+        fs::write(
+            dir.join("common_diaries").join("luxun").join("1912.json"),
+            "[]",
+        )
+        .unwrap();
+
+        let mut conn = Connection::open(dir.join("diary.db")).unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        conn.execute(
+            "INSERT INTO diary_entries (id, content, weather, created_at, day)
+             VALUES ('a', '搬家', '晴', 'now', '2024-03-01')",
+            [],
+        )
+        .unwrap();
+        (dir, conn)
+    }
+
+    // Copying a dangling symlink fails part way through the move
+    #[cfg(unix)]
+    fn break_copy(source: &Path) {
+        std::os::unix::fs::symlink(
+            source.join("missing"),
+            source.join("common_diaries").join("broken"),
+        )
+        .unwrap();
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_data_is_copied_and_recorded() {
+        let (source, conn) = source_dir();
+        let default_dir = temp_dir("default");
+        let target = temp_dir("target").join("diary");
+
+        move_data(&conn, &source, &target, &default_dir).unwrap();
+
+        assert_eq!(
+            names(&target),
+            vec![
+                "common_diaries",
+                "config.json",
+                "diary.db",
+                "nostr_keys.json"
+            ]
+        );
+        assert!(target
+            .join("common_diaries")
+            .join("luxun")
+            .join("1912.json")
+            .exists());
+        let copy = Connection::open(target.join("diary.db")).unwrap();
+        assert_eq!(count_entries(&copy).unwrap(), 1);
+        assert_eq!(read_location_file(&default_dir), Some(target.clone()));
+
+        // The copy is never written over
+        assert!(move_data(&conn, &source, &target, &temp_dir("other")).is_err());
+        assert_eq!(read_location_file(&default_dir), Some(target));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_move_is_rolled_back() {
+        let (source, conn) = source_dir();
+        break_copy(&source);
+        let default_dir = temp_dir("default");
+        let target = temp_dir("target");
+
+        assert!(move_data(&conn, &source, &target, &default_dir).is_err());
+        assert!(names(&target).is_empty());
+        assert_eq!(read_location_file(&default_dir), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_move_back_restores_the_default_dir() {
+        let (source, conn) = source_dir();
+        break_copy(&source);
+        // The default directory still holds the diary from before it moved
+        let default_dir = temp_dir("default");
+        fs::write(default_dir.join("diary.db"), "old").unwrap();
+        fs::write(
+            default_dir.join(LOCATION_FILE),
+            source.to_string_lossy().as_bytes(),
+        )
+        .unwrap();
+
+        assert!(move_data(&conn, &source, &default_dir, &default_dir).is_err());
+        assert_eq!(names(&default_dir), vec![LOCATION_FILE, "diary.db"]);
+        assert_eq!(
+            fs::read_to_string(default_dir.join("diary.db")).unwrap(),
+            "old"
+        );
+        assert_eq!(read_location_file(&default_dir), Some(source));
+    }
+}
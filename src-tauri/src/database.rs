@@ -0,0 +1,74 @@
+// The diary database service
+//
+// A single SQLite connection is owned by a dedicated worker thread. Commands
+// send closures to it and await the result, so a slow query never blocks the
+// async runtime and a panic inside one query cannot poison the connection for
+// everyone else. The service is kept in DiaryStore, which lives in Tauri state.
+use futures::channel::oneshot;
+use rusqlite::{Connection, Result as SqlResult};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// How long a statement waits for a lock held by another connection, e.g. a
+// backup or an external tool reading the file, before failing with SQLITE_BUSY
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Job = Box<dyn FnOnce(&mut Connection) + Send>;
+
+pub struct Database {
+    sender: mpsc::Sender<Job>,
+}
+
+// Connection settings applied to the live database whenever it is opened.
+// Not applied to backup files, which should stay a single self-contained file.
+pub fn configure(conn: &Connection) -> SqlResult<()> {
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    Ok(())
+}
+
+impl Database {
+    // Moves the connection onto a new worker thread
+    pub fn start(conn: Connection) -> Database {
+        let (sender, receiver) = mpsc::channel::<Job>();
+
+        thread::Builder::new()
+            .name("diary-db".to_string())
+            .spawn(move || {
+                let mut conn = conn;
+                for job in receiver {
+                    if panic::catch_unwind(AssertUnwindSafe(|| job(&mut conn))).is_err() {
+                        println!("Database job panicked, continuing with the next one");
+                    }
+                }
+                println!("Database worker stopped");
+            })
+            .expect("Failed to start database worker");
+
+        Database { sender }
+    }
+
+    // Runs `f` on the database thread and waits for its result. Jobs run one
+    // at a time in the order they were sent.
+    pub async fn call<F, T>(&self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, String> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let job: Job = Box::new(move |conn| {
+            let _ = reply.send(f(conn));
+        });
+        self.sender
+            .send(job)
+            .map_err(|_| "Database worker is not running".to_string())?;
+
+        // The reply is dropped without a value if the job panicked
+        result
+            .await
+            .map_err(|_| "Database query was aborted".to_string())?
+    }
+}
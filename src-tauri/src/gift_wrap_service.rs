@@ -1,10 +1,12 @@
+use chrono::Utc;
 use futures::future::join_all;
 use nostr_sdk::nips::nip59::UnwrappedGift;
 use nostr_sdk::{
-    Alphabet, ClientBuilder, Event, EventBuilder, Filter, Keys, Kind, PublicKey, SingleLetterTag,
-    Timestamp, UnsignedEvent,
+    Alphabet, Event, EventBuilder, Filter, Kind, PublicKey, SingleLetterTag, Timestamp,
+    UnsignedEvent,
 };
 use rand::{rng, Rng};
+use rusqlite::{params, Connection};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -16,14 +18,15 @@ use crate::DiaryStore;
 #[derive(Deserialize, Serialize)]
 pub struct GiftWrapRequest {
     pub nostr_id: String,         // ID of the diary event to gift wrap
-    pub recipient_pubkey: String, // Public key of the recipient
+    pub recipient_pubkey: String, // hex, npub, nprofile or nostr: URI of the recipient
 }
 
 // Structure to hold the gift wrap response
 #[derive(Deserialize, Serialize)]
 pub struct GiftWrapResponse {
-    pub gift_wrap_event: String, // The gift-wrapped event as JSON
-    pub gift_wrap_id: String,    // The ID of the gift-wrapped event
+    pub gift_wrap_event: String,  // The gift-wrapped event as JSON
+    pub gift_wrap_id: String,     // The ID of the gift-wrapped event
+    pub relay_hints: Vec<String>, // Relays from the recipient's nprofile, for share_gift_wrap
 }
 
 #[derive(Deserialize, Serialize)]
@@ -47,16 +50,48 @@ fn random_past_timestamp() -> u64 {
 }
 
 // Gets the diary event from the database by ID
-fn get_diary_event(nostr_id: &str) -> Result<String, String> {
-    let conn = crate::DB_CONNECTION.lock().unwrap();
-    match conn.query_row(
-        "SELECT nostr_event FROM diary_entries WHERE nostr_id = ?1",
-        [nostr_id],
-        |row| row.get::<_, String>(0),
-    ) {
-        Ok(event_json) => Ok(event_json),
-        Err(e) => Err(format!("Failed to get Nostr event: {}", e)),
-    }
+async fn get_diary_event(store: &DiaryStore, nostr_id: &str) -> Result<String, String> {
+    let nostr_id = nostr_id.to_string();
+    store
+        .db
+        .call(move |conn| {
+            conn.query_row(
+                "SELECT nostr_event FROM diary_entries WHERE nostr_id = ?1",
+                [nostr_id],
+                |row| row.get::<_, String>(0),
+            )
+            .map_err(|e| format!("Failed to get Nostr event: {}", e))
+        })
+        .await
+}
+
+// Remembers who a diary entry was wrapped for, so entries can be filtered by recipient
+fn record_share(
+    conn: &Connection,
+    nostr_id: &str,
+    recipient_pubkey: &str,
+    gift_wrap_id: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT OR REPLACE INTO gift_wrap_shares
+            (gift_wrap_id, entry_id, nostr_id, recipient_pubkey, created_at)
+         VALUES (
+            ?1,
+            COALESCE(
+                (SELECT id FROM diary_entries WHERE nostr_id = ?2),
+                (SELECT entry_id FROM diary_entry_revisions WHERE nostr_id = ?2)
+            ),
+            ?2, ?3, ?4
+         )",
+        params![
+            gift_wrap_id,
+            nostr_id,
+            recipient_pubkey,
+            Utc::now().to_rfc3339()
+        ],
+    )
+    .map_err(|e| format!("Failed to record gift wrap share: {}", e))?;
+    Ok(())
 }
 
 // Tauri command to gift wrap a diary entry for private sharing
@@ -64,9 +99,16 @@ fn get_diary_event(nostr_id: &str) -> Result<String, String> {
 pub async fn gift_wrap_diary(
     store: State<'_, Arc<DiaryStore>>,
     request: GiftWrapRequest,
+) -> Result<GiftWrapResponse, String> {
+    wrap_entry(&store, request).await
+}
+
+pub async fn wrap_entry(
+    store: &Arc<DiaryStore>,
+    request: GiftWrapRequest,
 ) -> Result<GiftWrapResponse, String> {
     // Get the source diary event from the database
-    let event_json = get_diary_event(&request.nostr_id)?;
+    let event_json = get_diary_event(store, &request.nostr_id).await?;
 
     // Parse the event JSON into a Nostr Event
     let source_event: Event = match serde_json::from_str(&event_json) {
@@ -75,13 +117,17 @@ pub async fn gift_wrap_diary(
     };
 
     // Get the sender's keys
-    let sender_keys = get_or_create_nostr_keys(&store)?;
+    let sender_keys = crate::get_or_create_nostr_keys(store)?;
+
+    // The recipient cannot decrypt an entry encrypted to our own key, so a
+    // private entry is shared with its decrypted content and tags
+    let source_event = crate::private_event::open_event(&sender_keys, &source_event)?;
 
     // Parse recipient's public key
-    let recipient_pubkey = match PublicKey::from_hex(&request.recipient_pubkey) {
-        Ok(pk) => pk,
-        Err(e) => return Err(format!("Invalid recipient public key: {}", e)),
-    };
+    let recipient = crate::nostr_keys::parse_pubkey(&request.recipient_pubkey)
+        .map_err(|e| format!("Invalid recipient public key: {}", e))?;
+    let recipient_pubkey = PublicKey::from_hex(&recipient.hex)
+        .map_err(|e| format!("Invalid recipient public key: {}", e))?;
 
     // Create a new unsigned event with the content and tags from the source event
     let kind = Kind::from(30027);
@@ -107,128 +153,152 @@ pub async fn gift_wrap_diary(
     let gift_wrap_json = serde_json::to_string(&gift_wrap)
         .map_err(|e| format!("Failed to serialize gift wrap: {}", e))?;
 
+    let (nostr_id, recipient_hex, gift_wrap_id) = (
+        request.nostr_id.clone(),
+        recipient_pubkey.to_hex(),
+        gift_wrap.id.to_hex(),
+    );
+    store
+        .db
+        .call(move |conn| record_share(conn, &nostr_id, &recipient_hex, &gift_wrap_id))
+        .await?;
+
     Ok(GiftWrapResponse {
         gift_wrap_event: gift_wrap_json,
         gift_wrap_id: gift_wrap.id.to_hex(),
+        relay_hints: recipient.relays,
     })
 }
 
-// Gets or creates Nostr keys for the user
-fn get_or_create_nostr_keys(store: &Arc<DiaryStore>) -> Result<Keys, String> {
-    let mut nostr_keys_guard = store.nostr_keys.lock().unwrap();
-
-    if let Some(keys) = nostr_keys_guard.as_ref() {
-        return Ok(keys.clone());
-    }
-
-    // Try to load existing keys
-    if let Some(keys) = crate::load_nostr_keys() {
-        *nostr_keys_guard = Some(keys.clone());
-        return Ok(keys);
-    }
-
-    // Generate new keys
-    println!("Generating new Nostr keys");
-    let keys = Keys::generate();
-
-    // Save the keys
-    crate::save_nostr_keys(&keys)?;
-
-    // Update the store
-    *nostr_keys_guard = Some(keys.clone());
-
-    Ok(keys)
-}
-
-// Command to share the gift-wrapped event to a relay
+// Command to share the gift-wrapped event to a relay. Without a relay it
+// goes to the recipient's NIP-65 read relays, or our write relays when the
+// recipient has not published a relay list.
 #[tauri::command]
-pub async fn share_gift_wrap(gift_wrap_json: String, relay_url: String) -> Result<String, String> {
+pub async fn share_gift_wrap(
+    store: State<'_, Arc<DiaryStore>>,
+    gift_wrap_json: String,
+    relay_url: Option<String>,
+    relay_hints: Option<Vec<String>>,
+) -> Result<String, String> {
     // Parse the gift wrap JSON
     let gift_wrap: Event = match serde_json::from_str(&gift_wrap_json) {
         Ok(event) => event,
         Err(e) => return Err(format!("Failed to parse gift wrap: {}", e)),
     };
 
-    // Create a client to publish the event
-    //let keys: Keys = Keys::generate();
-    // Create a client builder and build it with the keys
-    let client = ClientBuilder::new().build();
+    send_gift_wrap(
+        &store,
+        &gift_wrap,
+        relay_url,
+        &relay_hints.unwrap_or_default(),
+    )
+    .await
+}
+
+pub async fn send_gift_wrap(
+    store: &Arc<DiaryStore>,
+    gift_wrap: &Event,
+    relay_url: Option<String>,
+    relay_hints: &[String],
+) -> Result<String, String> {
+    let relay_urls = match relay_url {
+        Some(relay_url) => vec![relay_url],
+        None => recipient_relay_urls(store, gift_wrap, relay_hints).await,
+    };
+
+    // The recipient's relays get a client of their own, not the shared pool
+    let connections = crate::nostr_client::connect_relays(store, &relay_urls).await;
+    let result = connections
+        .client
+        .send_event_to(connections.connected()?, gift_wrap)
+        .await;
+
+    match result {
+        Ok(event_id) => Ok(format!(
+            "Successfully shared gift-wrapped event with ID: {:?}",
+            event_id
+        )),
+        Err(e) => Err(format!("Failed to send gift-wrapped event: {}", e)),
+    }
+}
 
-    // Add the relay
-    client
-        .add_relay(relay_url.clone())
-        .await
-        .map_err(|e| format!("Failed to add relay: {}", e))?;
-
-    // Connect to the relay
-    client.connect().await;
-
-    // Publish the gift-wrapped event
-    match client.send_event(&gift_wrap).await {
-        Ok(event_id) => {
-            // Disconnect from the relay after publishing
-            let _ = client.disconnect().await;
-            Ok(format!(
-                "Successfully shared gift-wrapped event with ID: {:?}",
-                event_id
-            ))
+// Read relays of the recipient named in the gift wrap's p tag. The relay
+// list is looked up on our relays and the nprofile hints; without one the
+// hints are used, then our own write relays.
+async fn recipient_relay_urls(
+    store: &DiaryStore,
+    gift_wrap: &Event,
+    relay_hints: &[String],
+) -> Vec<String> {
+    let own_relays = crate::relay_list::write_relay_urls(store);
+    let fallback = if relay_hints.is_empty() {
+        own_relays.clone()
+    } else {
+        relay_hints.to_vec()
+    };
+    let Some(recipient) = gift_wrap.tags.public_keys().next().copied() else {
+        return fallback;
+    };
+
+    let mut lookup_relays = own_relays;
+    for hint in relay_hints {
+        if !lookup_relays.contains(hint) {
+            lookup_relays.push(hint.clone());
+        }
+    }
+    match crate::relay_list::fetch_relay_list(store, recipient, &lookup_relays).await {
+        Ok(Some(relay_list)) => {
+            let inbox = crate::relay_list::inbox_relays(&relay_list);
+            if inbox.is_empty() {
+                fallback
+            } else {
+                inbox
+            }
         }
+        Ok(None) => fallback,
         Err(e) => {
-            // Make sure to disconnect even if publishing fails
-            let _ = client.disconnect().await;
-            Err(format!("Failed to send gift-wrapped event: {}", e))
+            println!("Failed to look up relays of {}: {}", recipient, e);
+            fallback
         }
     }
 }
 
-// Verify if a pubkey is valid
+// Verify if a pubkey is valid. Returns the key in hex and npub form with
+// any nprofile relay hints, or nothing when it cannot be parsed. A secret
+// key is an error, so the user is told not to share it.
 #[tauri::command]
-pub fn validate_pubkey(pubkey: String) -> Result<bool, String> {
-    match PublicKey::from_hex(&pubkey) {
-        Ok(_) => Ok(true),
-        Err(_) => Ok(false),
+pub fn validate_pubkey(pubkey: String) -> Result<Option<crate::nostr_keys::PublicKeyInfo>, String> {
+    if crate::nostr_keys::is_secret_key(&pubkey) {
+        return crate::nostr_keys::parse_pubkey(&pubkey).map(Some);
     }
+    Ok(crate::nostr_keys::parse_pubkey(&pubkey).ok())
 }
 
 // Fetch gift wrap events for the current user from a relay
 #[tauri::command]
 pub async fn fetch_gift_wraps(
     store: State<'_, Arc<DiaryStore>>,
+) -> Result<Vec<UnwrappedGiftResponse>, String> {
+    receive_gift_wraps(&store).await
+}
+
+pub async fn receive_gift_wraps(
+    store: &Arc<DiaryStore>,
 ) -> Result<Vec<UnwrappedGiftResponse>, String> {
     // Get the user's keys
-    let keys = get_or_create_nostr_keys(&store)?;
+    let keys = crate::get_or_create_nostr_keys(store)?;
     let user_pubkey = keys.public_key();
 
     println!("Fetching gift wraps for pubkey: {}", user_pubkey);
 
-    // Get configured relay URLs from app state
-    // Clone the values so we don't hold the MutexGuard across await points
-    let relay_urls = {
-        let config_lock = store.config.lock().map_err(|_| "Failed to lock config".to_string())?;
-        if !config_lock.relay_urls.is_empty() {
-            config_lock.relay_urls.clone()
-        } else {
-            config_lock.default_relay_urls.clone()
-        }
-    }; // MutexGuard is dropped here
+    // Gift wraps for us are sent to our read relays
+    let relay_urls = crate::relay_list::read_relay_urls(store);
 
     if relay_urls.is_empty() {
-        return Err("No relay URLs configured".to_string());
+        return Err("No read relays configured, mark a relay for reading".to_string());
     }
 
-    // Create client
-    let client = ClientBuilder::new().build();
-
-    // Add all relays
-    for relay_url in &relay_urls {
-        client
-            .add_relay(relay_url)
-            .await
-            .map_err(|e| format!("Failed to add relay {}: {}", relay_url, e))?;
-    }
-
-    // Connect to the relays
-    client.connect().await;
+    let connections = crate::nostr_client::connect_relays(store, &relay_urls).await;
 
     // Create a filter to find gift wrap events (kind 1059) with p tag matching user's pubkey
     let filter = Filter::new()
@@ -240,9 +310,17 @@ pub async fn fetch_gift_wraps(
     // Create a subscription with timeout
     // Use a simpler approach - fetch from each relay individually
     let mut all_events = Vec::new();
-    for relay_url in &relay_urls {
-        match client
-            .fetch_events_from([relay_url.as_str()], filter.clone(), Duration::new(5, 0))
+    for (relay_url, connection) in relay_urls.iter().zip(&connections.results) {
+        let relay = match connection {
+            Ok(relay) => relay.clone(),
+            Err(e) => {
+                println!("Failed to connect to {}: {}", relay_url, e);
+                continue;
+            }
+        };
+        match connections
+            .client
+            .fetch_events_from([relay], filter.clone(), Duration::new(5, 0))
             .await
         {
             Ok(events) => {
@@ -258,9 +336,6 @@ pub async fn fetch_gift_wraps(
 
     println!("Received {} events total", all_events.len());
 
-    // Disconnect from the relay
-    let _ = client.disconnect().await;
-
     let events_json_futures = all_events.into_iter().map(|e| {
         let nostr_signer = &keys;
         async move {
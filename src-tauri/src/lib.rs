@@ -1,11 +1,11 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 use ::hex;
-use chrono::{DateTime, TimeZone, Utc};
-use directories::ProjectDirs;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use nostr_sdk::nips::nip11::RelayInformationDocument;
 use nostr_sdk::{Event, EventBuilder, Keys, Kind, SecretKey, Tag};
-use once_cell::sync::Lazy;
-use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use rusqlite::{params, Connection, Result as SqlResult};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::PathBuf;
@@ -16,13 +16,61 @@ use uuid::Uuid;
 // Additional imports for GitHub API access
 
 // Add mod declaration for the gift wrap service
+pub mod attachment_service;
+pub mod audit_service;
+pub mod backup_service;
+pub mod data_dir;
+pub mod database;
 pub mod gift_wrap_service;
+pub mod migrations;
+pub mod nostr_client;
+pub mod nostr_keys;
+pub mod outbox;
+pub mod private_event;
+pub mod relay_health;
+pub mod relay_list;
+#[cfg(test)]
+mod relay_tests;
+
+pub mod query_service;
+pub mod repository;
+pub mod restore_service;
+pub mod revision_service;
+pub mod search_service;
+pub mod tag_service;
+pub mod timezone;
+pub mod trash_service;
+pub mod vault;
 
 // Configuration structures
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Config {
     relay_urls: Vec<String>,
     default_relay_urls: Vec<String>,
+    #[serde(default)]
+    relay_roles: BTreeMap<String, relay_list::RelayRole>, // relays used only to read or only to write
+    #[serde(default = "default_backup_keep_count")]
+    backup_keep_count: usize, // rotating backups kept in the backups directory
+    #[serde(default = "default_backup_interval_hours")]
+    backup_interval_hours: u64, // 0 disables scheduled backups
+    #[serde(default)]
+    timezone: Option<String>, // IANA name, the system timezone when unset
+    #[serde(default)]
+    offline_mode: bool, // queue signed events without contacting relays
+    #[serde(default = "default_private_by_default")]
+    private_by_default: bool, // new entries are encrypted to our own key
+}
+
+fn default_backup_keep_count() -> usize {
+    10
+}
+
+fn default_backup_interval_hours() -> u64 {
+    24
+}
+
+fn default_private_by_default() -> bool {
+    true
 }
 
 impl Default for Config {
@@ -36,18 +84,19 @@ impl Default for Config {
                 "wss://relay.damus.io".to_string(),
                 "wss://nostr.wine".to_string(),
             ],
+            backup_keep_count: default_backup_keep_count(),
+            backup_interval_hours: default_backup_interval_hours(),
+            timezone: None,
+            relay_roles: BTreeMap::new(),
+            offline_mode: false,
+            private_by_default: default_private_by_default(),
         }
     }
 }
 
+// See data_dir for how the location is chosen
 fn get_data_dir() -> PathBuf {
-    let proj_dirs =
-        ProjectDirs::from("com", "luxun", "diary").expect("Failed to get project directories");
-
-    let data_dir = proj_dirs.data_dir();
-    fs::create_dir_all(data_dir).expect("Failed to create data directory");
-    println!("Data directory: {}", data_dir.display());
-    data_dir.to_path_buf()
+    data_dir::resolved().path.clone()
 }
 
 fn get_db_path() -> PathBuf {
@@ -131,40 +180,25 @@ fn setup_db() -> SqlResult<Connection> {
     let db_path = get_db_path();
     println!("Database path: {}", db_path.display());
 
-    let conn = Connection::open(db_path)?;
+    let mut conn = Connection::open(&db_path)?;
 
-    // Create the diary entries table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS diary_entries (
-            id TEXT PRIMARY KEY,
-            content TEXT NOT NULL,
-            weather TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            nostr_id TEXT UNIQUE,
-            day TEXT UNIQUE,
-            nostr_event TEXT
-        )",
-        [],
-    )?;
+    // An encrypted database cannot be read or migrated until unlock_diary
+    // supplies the passphrase and replaces this connection
+    if vault::is_database_encrypted(&db_path) {
+        println!("Database is encrypted, waiting for unlock");
+        return Ok(conn);
+    }
 
-    // Create the common diaries cache table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS common_diaries_cache (
-            author TEXT PRIMARY KEY,
-            data TEXT NOT NULL,
-            last_modified TEXT NOT NULL
-        )",
-        [],
-    )?;
+    database::configure(&conn)?;
+
+    // Bring the schema up to date, backing up existing data first
+    let version = migrations::migrate(&mut conn, Some(&db_path))?;
+    println!("Database schema version: {}", version);
+    repository::index_external_entries(&conn)?;
 
     Ok(conn)
 }
 
-static DB_CONNECTION: Lazy<Mutex<Connection>> = Lazy::new(|| {
-    let conn = setup_db().expect("Failed to set up database");
-    Mutex::new(conn)
-});
-
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DiaryEntry {
     id: String,
@@ -173,18 +207,76 @@ pub struct DiaryEntry {
     created_at: DateTime<Utc>,
     nostr_id: Option<String>,
     day: String, // YYYY-MM-DD format
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    utc_offset: Option<String>, // e.g. "+08:00", unknown for entries from older versions
+    #[serde(default)]
+    private: bool, // published encrypted to our own key, see private_event
 }
 
-#[derive(Default)]
 pub struct DiaryStore {
     nostr_keys: Mutex<Option<Keys>>,
     config: Mutex<Config>,
+    db: Arc<database::Database>,
+    repository: Box<dyn repository::DiaryRepository>,
+    // NIP-11 documents of relays, see relay_health
+    relay_information: Mutex<HashMap<String, RelayInformationDocument>>,
+    // Connections to relays shared by every operation, see nostr_client
+    client: nostr_sdk::Client,
 }
 
-// Using String for private key storage - hex encoded format
-#[derive(Serialize, Deserialize)]
+// Using String for private key storage - hex encoded format, or a NIP-49
+// ncryptsec string once the diary has a passphrase
+#[derive(Serialize, Deserialize, Default)]
 struct StoredKeys {
-    private_key_hex: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    private_key_hex: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encrypted_private_key: Option<String>,
+}
+
+fn read_stored_keys() -> Result<Option<StoredKeys>, String> {
+    let file_path = get_nostr_keys_file_path();
+    if !file_path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(&file_path)
+        .map_err(|e| format!("Failed to read nostr keys file: {}", e))?;
+    serde_json::from_str::<StoredKeys>(&contents)
+        .map(Some)
+        .map_err(|e| format!("Failed to parse nostr keys file: {}", e))
+}
+
+fn write_stored_keys(stored_keys: &StoredKeys) -> Result<(), String> {
+    let json = serde_json::to_string(stored_keys)
+        .map_err(|e| format!("Failed to serialize nostr keys: {}", e))?;
+    fs::write(get_nostr_keys_file_path(), json)
+        .map_err(|e| format!("Failed to write nostr keys: {}", e))
+}
+
+// Loads keys protected by the diary passphrase. Plaintext keys left from
+// before the diary was encrypted are accepted too.
+fn load_encrypted_nostr_keys(passphrase: &str) -> Result<Option<Keys>, String> {
+    match read_stored_keys()? {
+        Some(StoredKeys {
+            encrypted_private_key: Some(ncryptsec),
+            ..
+        }) => vault::decrypt_keys(&ncryptsec, passphrase).map(Some),
+        Some(_) => Ok(load_nostr_keys()),
+        None => Ok(None),
+    }
+}
+
+fn save_encrypted_nostr_keys(keys: &Keys, passphrase: &str) -> Result<(), String> {
+    let stored_keys = StoredKeys {
+        private_key_hex: None,
+        encrypted_private_key: Some(vault::encrypt_keys(keys, passphrase)?),
+    };
+    write_stored_keys(&stored_keys)?;
+    println!("Successfully saved encrypted Nostr keys");
+    Ok(())
 }
 
 fn load_nostr_keys() -> Option<Keys> {
@@ -208,7 +300,17 @@ fn load_nostr_keys() -> Option<Keys> {
     }
 
     match serde_json::from_str::<StoredKeys>(&contents) {
-        Ok(stored_keys) => match hex::decode(&stored_keys.private_key_hex) {
+        Ok(StoredKeys {
+            private_key_hex: None,
+            ..
+        }) => {
+            println!("Nostr keys are encrypted, unlock the diary to load them");
+            None
+        }
+        Ok(StoredKeys {
+            private_key_hex: Some(private_key_hex),
+            ..
+        }) => match hex::decode(&private_key_hex) {
             Ok(bytes) => match SecretKey::from_slice(&bytes) {
                 Ok(secret_key) => {
                     let keys = Keys::new(secret_key);
@@ -239,7 +341,10 @@ fn save_nostr_keys(keys: &Keys) -> Result<(), String> {
     let secret_key = keys.secret_key();
     let private_key_hex = hex::encode(secret_key.as_ref());
 
-    let stored_keys = StoredKeys { private_key_hex };
+    let stored_keys = StoredKeys {
+        private_key_hex: Some(private_key_hex),
+        ..Default::default()
+    };
 
     let json = match serde_json::to_string(&stored_keys) {
         Ok(json) => json,
@@ -260,14 +365,31 @@ fn save_nostr_keys(keys: &Keys) -> Result<(), String> {
     }
 }
 
-// Since sign() returns a Future, we need to make this function async
-async fn create_nostr_event(
-    keys: Keys,
+// How a signed diary event is published
+#[derive(Clone, Copy, Debug, Default)]
+struct PublishOptions {
+    private: bool,       // encrypt content and tags to our own key
+    publish_later: bool, // hold in the outbox until publish_pending_entries
+}
+
+// Publishes the event of an entry that has been saved. Never publish before
+// the save, or a failed save leaves relays with an entry we do not have.
+async fn publish_saved_event(event: &Event, hold: bool, store: &DiaryStore) {
+    // Relays that cannot be reached now are retried by the outbox worker
+    if let Err(e) = outbox::publish_event(event, hold, store).await {
+        println!("Failed to publish event: {}", e);
+    }
+}
+
+// Signs the kind 30027 event of a diary entry without publishing it
+async fn sign_diary_event(
+    keys: &Keys,
     content: &str,
     weather: &str,
     day: &str,
-    store: &Arc<DiaryStore>,
-) -> Result<(String, String), String> {
+    extra_tags: Vec<Tag>,
+    private: bool,
+) -> Result<Event, String> {
     // Create tags
     let d_tag = Tag::parse(vec!["d".to_string(), day.to_string()])
         .map_err(|e| format!("Failed to create d tag: {}", e))?;
@@ -275,60 +397,56 @@ async fn create_nostr_event(
     let weather_tag = Tag::parse(vec!["weather".to_string(), weather.to_string()])
         .map_err(|e| format!("Failed to create weather tag: {}", e))?;
 
-    // Create event builder with content and kind and add tags
-    let event = EventBuilder::new(Kind::from(30027), content)
-        .tags(vec![d_tag, weather_tag])
-        .sign(&keys)
-        .await
-        .map_err(|e| format!("Failed to create Nostr event: {}", e))?;
-
-    let event_json = serde_json::to_string(&event)
-        .map_err(|e| format!("Failed to serialize Nostr event: {}", e))?;
-
-    // Get configured relay URLs and clone them
-    let urls = {
-        let config = store.config.lock().unwrap();
-        if config.relay_urls.is_empty() {
-            config.default_relay_urls.clone()
-        } else {
-            config.relay_urls.clone()
-        }
+    // Create event builder with content and kind and add tags. A private
+    // event only shows its d tag, everything else is encrypted.
+    let builder = if private {
+        let mut tags = vec![weather_tag];
+        tags.extend(extra_tags);
+        let (ciphertext, marker) = private_event::seal(keys, content, tags)?;
+        EventBuilder::new(Kind::from(30027), ciphertext).tags(vec![d_tag, marker])
+    } else {
+        EventBuilder::new(Kind::from(30027), content)
+            .tags(vec![d_tag, weather_tag])
+            .tags(extra_tags)
     };
+    builder
+        .sign(keys)
+        .await
+        .map_err(|e| format!("Failed to create Nostr event: {}", e))
+}
 
-    // Create a single client for all relays
-    let client = nostr_sdk::Client::new(keys);
-
-    // Add all relays
-    for url in &urls {
-        if let Err(e) = client.add_relay(url).await {
-            println!("Failed to add relay {}: {}", url, e);
-        }
+// The keys already in memory or in the keys file, without generating any
+fn load_existing_nostr_keys(cached: &mut Option<Keys>) -> Result<Option<Keys>, String> {
+    if let Some(keys) = cached.as_ref() {
+        return Ok(Some(keys.clone()));
     }
 
-    // Connect to all relays
-    client.connect().await;
+    if !vault::is_unlocked() {
+        return Err("The diary is locked, unlock it first".to_string());
+    }
 
-    // Send event to all connected relays
-    if let Err(e) = client.send_event(&event).await {
-        println!("Failed to send event: {}", e);
+    // Try to load existing keys
+    if let Some(keys) = load_nostr_keys() {
+        *cached = Some(keys.clone());
+        return Ok(Some(keys));
     }
 
-    // Disconnect from all relays
-    let _ = client.disconnect().await;
+    // Never replace a keys file that exists but could not be loaded, it may
+    // be the only copy of the identity that signed the diary
+    if get_nostr_keys_file_path().exists() {
+        return Err("Failed to load existing Nostr keys".to_string());
+    }
+    Ok(None)
+}
 
-    Ok((event.id.to_string(), event_json))
+fn get_existing_nostr_keys(store: &Arc<DiaryStore>) -> Result<Option<Keys>, String> {
+    load_existing_nostr_keys(&mut store.nostr_keys.lock().unwrap())
 }
 
 fn get_or_create_nostr_keys(store: &Arc<DiaryStore>) -> Result<Keys, String> {
     let mut nostr_keys_guard = store.nostr_keys.lock().unwrap();
 
-    if let Some(keys) = nostr_keys_guard.as_ref() {
-        return Ok(keys.clone());
-    }
-
-    // Try to load existing keys
-    if let Some(keys) = load_nostr_keys() {
-        *nostr_keys_guard = Some(keys.clone());
+    if let Some(keys) = load_existing_nostr_keys(&mut nostr_keys_guard)? {
         return Ok(keys);
     }
 
@@ -351,21 +469,21 @@ async fn save_diary_entry(
     content: String,
     weather: String,
     day: Option<String>,
+    tags: Option<Vec<String>>,
+    publish_later: Option<bool>,
+    private: Option<bool>,
 ) -> Result<DiaryEntry, String> {
     println!("Creating new diary entry with weather: {}", weather);
 
-    // Use provided day or get current day
-    let entry_day = day.unwrap_or_else(|| Utc::now().format("%Y-%m-%d").to_string());
+    // Use provided day or get current day in the configured timezone
+    let (today, utc_offset) = timezone::configured_timezone(&store).today();
+    let entry_day = day.unwrap_or(today);
+    parse_day(&entry_day)?;
 
-    // Check if an entry already exists for this day
-    match entry_exists_for_day(&entry_day) {
-        Ok(exists) if exists => {
-            return Err(format!("An entry already exists for day: {}", entry_day));
-        }
-        Err(e) => {
-            return Err(format!("Failed to check if entry exists: {}", e));
-        }
-        _ => {}
+    // Check if an entry already exists for this day, trashed entries included
+    if store.repository.entry_exists_for_day(&entry_day).await? {
+        let trashed = store.repository.is_day_trashed(&entry_day).await?;
+        return Err(repository::day_taken_error(&entry_day, trashed));
     }
 
     // Get or create Nostr keys
@@ -373,164 +491,181 @@ async fn save_diary_entry(
     let pubkey_hex = keys.public_key().to_string();
     println!("Using Nostr public key: {}", pubkey_hex);
 
-    // Create Nostr event - use await
-    let (nostr_id, nostr_event_json) =
-        create_nostr_event(keys, &content, &weather, &entry_day, &store).await?;
+    // Explicit tags plus #hashtags from the content
+    let manual_tags = tags.unwrap_or_default();
+    let mut extra_tags: Vec<Tag> = tag_service::combine_tags(&content, &manual_tags)
+        .iter()
+        .map(Tag::hashtag)
+        .collect();
+    extra_tags.push(timezone::offset_tag(&utc_offset)?);
+
+    let options = PublishOptions {
+        private: private.unwrap_or_else(|| store.config.lock().unwrap().private_by_default),
+        publish_later: publish_later.unwrap_or(false),
+    };
+
+    let event = sign_diary_event(
+        &keys,
+        &content,
+        &weather,
+        &entry_day,
+        extra_tags,
+        options.private,
+    )
+    .await?;
+    let nostr_event_json = serde_json::to_string(&event)
+        .map_err(|e| format!("Failed to serialize Nostr event: {}", e))?;
 
     let entry = DiaryEntry {
         id: Uuid::new_v4().to_string(),
         content,
         weather,
         created_at: Utc::now(),
-        nostr_id: Some(nostr_id.clone()),
+        nostr_id: Some(event.id.to_string()),
         day: entry_day,
+        updated_at: None,
+        utc_offset: Some(utc_offset),
+        private: options.private,
     };
 
     println!("Generated diary entry with ID: {}", entry.id);
 
     // Save entry to database
-    if let Err(e) = save_entry_to_db(&entry, &nostr_event_json) {
-        return Err(format!("Failed to save entry to database: {}", e));
-    }
+    store
+        .repository
+        .save_entry(&entry, &nostr_event_json, &manual_tags)
+        .await?;
 
+    publish_saved_event(&event, options.publish_later, &store).await;
     Ok(entry)
 }
 
 #[tauri::command]
-fn get_diary_entries(_store: State<Arc<DiaryStore>>) -> Result<Vec<DiaryEntry>, String> {
-    match load_entries_from_db() {
-        Ok(entries) => Ok(entries),
-        Err(e) => Err(format!("Failed to load entries from database: {}", e)),
-    }
+async fn get_diary_entries(store: State<'_, Arc<DiaryStore>>) -> Result<Vec<DiaryEntry>, String> {
+    store.repository.load_entries().await
 }
 
 #[tauri::command]
-fn get_nostr_event(nostr_id: String) -> Result<String, String> {
-    match get_nostr_event_from_db(&nostr_id) {
-        Ok(Some(event)) => Ok(event),
-        Ok(None) => Err(format!("Nostr event with ID {} not found", nostr_id)),
-        Err(e) => Err(format!("Failed to get Nostr event: {}", e)),
-    }
-}
-
-#[tauri::command]
-fn check_day_has_entry(day: String) -> Result<bool, String> {
-    match entry_exists_for_day(&day) {
-        Ok(exists) => Ok(exists),
-        Err(e) => Err(format!("Failed to check if day has entry: {}", e)),
-    }
+async fn get_entries_for_month(
+    store: State<'_, Arc<DiaryStore>>,
+    year: i32,
+    month: u32,
+) -> Result<Vec<DiaryEntry>, String> {
+    let start = NaiveDate::from_ymd_opt(year, month, 1)
+        .ok_or_else(|| format!("Invalid month: {}-{}", year, month))?;
+    let end = start
+        .checked_add_months(chrono::Months::new(1))
+        .and_then(|next| next.pred_opt())
+        .ok_or_else(|| format!("Invalid month: {}-{}", year, month))?;
+
+    store.repository.load_entries_in_range(start, end).await
 }
 
 #[tauri::command]
-fn get_nostr_public_key(store: State<Arc<DiaryStore>>) -> Result<String, String> {
-    let keys = get_or_create_nostr_keys(&store)?;
-    Ok(keys.public_key().to_string())
+async fn get_entries_for_year(
+    store: State<'_, Arc<DiaryStore>>,
+    year: i32,
+) -> Result<Vec<DiaryEntry>, String> {
+    let start =
+        NaiveDate::from_ymd_opt(year, 1, 1).ok_or_else(|| format!("Invalid year: {}", year))?;
+    let end =
+        NaiveDate::from_ymd_opt(year, 12, 31).ok_or_else(|| format!("Invalid year: {}", year))?;
+
+    store.repository.load_entries_in_range(start, end).await
 }
 
 #[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
-}
-
-fn save_entry_to_db(entry: &DiaryEntry, nostr_event_json: &str) -> SqlResult<()> {
-    let conn = DB_CONNECTION.lock().unwrap();
-
-    // Check if an entry already exists for this day
-    let existing: Option<String> = conn
-        .query_row(
-            "SELECT id FROM diary_entries WHERE day = ?1",
-            params![entry.day],
-            |row| row.get(0),
-        )
-        .optional()?;
-
-    if let Some(existing_id) = existing {
-        if existing_id != entry.id {
-            return Err(rusqlite::Error::InvalidParameterName(format!(
-                "An entry already exists for day: {}",
-                entry.day
-            )));
-        }
+async fn get_entries_in_range(
+    store: State<'_, Arc<DiaryStore>>,
+    start_day: String,
+    end_day: String,
+) -> Result<Vec<DiaryEntry>, String> {
+    let start = parse_day(&start_day)?;
+    let end = parse_day(&end_day)?;
+    if start > end {
+        return Err(format!(
+            "Start day {} is after end day {}",
+            start_day, end_day
+        ));
     }
 
-    conn.execute(
-        "INSERT OR REPLACE INTO diary_entries (id, content, weather, created_at, nostr_id, day, nostr_event)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-        params![
-            entry.id,
-            entry.content,
-            entry.weather,
-            entry.created_at.to_rfc3339(),
-            entry.nostr_id,
-            entry.day,
-            nostr_event_json
-        ],
-    )?;
+    store.repository.load_entries_in_range(start, end).await
+}
 
-    println!("Saved entry to database with ID: {}", entry.id);
-    Ok(())
+// Loads a stored event by ID, current or superseded
+async fn load_nostr_event(store: &DiaryStore, nostr_id: &str) -> Result<String, String> {
+    let event = store.repository.get_nostr_event(nostr_id).await?;
+    event.ok_or_else(|| format!("Nostr event with ID {} not found", nostr_id))
 }
 
-fn load_entries_from_db() -> SqlResult<Vec<DiaryEntry>> {
-    let conn = DB_CONNECTION.lock().unwrap();
-    let mut stmt = conn.prepare(
-        "SELECT id, content, weather, created_at, nostr_id, day FROM diary_entries 
-         ORDER BY created_at DESC",
-    )?;
+#[tauri::command]
+async fn get_nostr_event(
+    store: State<'_, Arc<DiaryStore>>,
+    nostr_id: String,
+) -> Result<String, String> {
+    load_nostr_event(&store, &nostr_id).await
+}
 
-    let entries_iter = stmt.query_map([], |row| {
-        let created_at_str: String = row.get(3)?;
-        let created_at = DateTime::parse_from_rfc3339(&created_at_str)
-            .map(|dt| dt.with_timezone(&Utc))
-            .unwrap_or_else(|_| Utc::now());
-
-        Ok(DiaryEntry {
-            id: row.get(0)?,
-            content: row.get(1)?,
-            weather: row.get(2)?,
-            created_at,
-            nostr_id: row.get(4)?,
-            day: row.get(5)?,
-        })
-    })?;
+#[tauri::command]
+async fn check_day_has_entry(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+) -> Result<bool, String> {
+    store.repository.entry_exists_for_day(&day).await
+}
 
-    let mut entries = Vec::new();
-    for entry_result in entries_iter {
-        entries.push(entry_result?);
-    }
+#[tauri::command]
+fn get_nostr_public_key(
+    store: State<Arc<DiaryStore>>,
+) -> Result<nostr_keys::PublicKeyInfo, String> {
+    let keys = get_or_create_nostr_keys(&store)?;
+    nostr_keys::public_key_info(&keys.public_key(), Vec::new())
+}
 
-    println!("Loaded {} entries from database", entries.len());
-    Ok(entries)
+#[tauri::command]
+fn greet(name: &str) -> String {
+    format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
-fn get_nostr_event_from_db(nostr_id: &str) -> SqlResult<Option<String>> {
-    let conn = DB_CONNECTION.lock().unwrap();
-    conn.query_row(
-        "SELECT nostr_event FROM diary_entries WHERE nostr_id = ?1",
-        params![nostr_id],
-        |row| row.get(0),
-    )
-    .optional()
+// Parses a `YYYY-MM-DD` day string into a calendar date
+fn parse_day(day: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .map_err(|e| format!("Invalid day '{}', expected YYYY-MM-DD: {}", day, e))
 }
 
-fn entry_exists_for_day(day: &str) -> SqlResult<bool> {
-    let conn = DB_CONNECTION.lock().unwrap();
-    let count: i64 = conn.query_row(
-        "SELECT COUNT(*) FROM diary_entries WHERE day = ?1",
-        params![day],
-        |row| row.get(0),
-    )?;
-    Ok(count > 0)
+const ENTRY_COLUMNS: &str =
+    "id, content, weather, created_at, nostr_id, day, updated_at, utc_offset, private";
+
+fn entry_from_row(row: &rusqlite::Row) -> SqlResult<DiaryEntry> {
+    let created_at_str: String = row.get(3)?;
+    let created_at = DateTime::parse_from_rfc3339(&created_at_str)
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now());
+    let updated_at = row
+        .get::<_, Option<String>>(6)?
+        .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+        .map(|dt| dt.with_timezone(&Utc));
+
+    Ok(DiaryEntry {
+        id: row.get(0)?,
+        content: row.get(1)?,
+        weather: row.get(2)?,
+        created_at,
+        nostr_id: row.get(4)?,
+        day: row.get(5)?,
+        updated_at,
+        utc_offset: row.get(7)?,
+        private: row.get(8)?,
+    })
 }
 
 #[tauri::command]
-fn verify_nostr_signature(nostr_id: String) -> Result<bool, String> {
+async fn verify_nostr_signature(
+    store: State<'_, Arc<DiaryStore>>,
+    nostr_id: String,
+) -> Result<bool, String> {
     // Get the event JSON from the database
-    let event_json = match get_nostr_event_from_db(&nostr_id) {
-        Ok(Some(json)) => json,
-        Ok(None) => return Err(format!("Nostr event with ID {} not found", nostr_id)),
-        Err(e) => return Err(format!("Failed to get Nostr event: {}", e)),
-    };
+    let event_json = load_nostr_event(&store, &nostr_id).await?;
 
     // Parse the event JSON
     let event: Event = match serde_json::from_str(&event_json) {
@@ -611,8 +746,7 @@ fn load_common_diary(file_path: &PathBuf) -> Result<CommonDiary, String> {
 }
 
 // Function to save a common diary to cache
-fn save_common_diary_to_cache(diary: &CommonDiary) -> SqlResult<()> {
-    let conn = DB_CONNECTION.lock().unwrap();
+fn save_common_diary_to_cache(conn: &Connection, diary: &CommonDiary) -> SqlResult<()> {
     let data = serde_json::to_string(&diary).unwrap();
     let now = Utc::now().to_rfc3339();
 
@@ -626,8 +760,7 @@ fn save_common_diary_to_cache(diary: &CommonDiary) -> SqlResult<()> {
 }
 
 // Function to load all common diaries from cache
-fn load_all_common_diaries_from_cache() -> SqlResult<Vec<CommonDiary>> {
-    let conn = DB_CONNECTION.lock().unwrap();
+fn load_all_common_diaries_from_cache(conn: &Connection) -> SqlResult<Vec<CommonDiary>> {
     let mut stmt = conn.prepare("SELECT data FROM common_diaries_cache")?;
 
     let diaries_iter = stmt.query_map([], |row| {
@@ -653,8 +786,40 @@ fn load_all_common_diaries_from_cache() -> SqlResult<Vec<CommonDiary>> {
     Ok(diaries)
 }
 
+// Number of cached diaries and the latest modification time of the cache
+fn common_diaries_cache_stats(conn: &Connection) -> SqlResult<(i64, Option<String>)> {
+    let count: i64 = conn.query_row("SELECT COUNT(*) FROM common_diaries_cache", [], |row| {
+        row.get(0)
+    })?;
+    let latest_mod_time: Option<String> = conn.query_row(
+        "SELECT MAX(last_modified) FROM common_diaries_cache",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok((count, latest_mod_time))
+}
+
+// Saves diaries loaded from files to the cache on the database thread
+async fn save_common_diaries_to_cache(store: &DiaryStore, diaries: Vec<CommonDiary>) -> usize {
+    store
+        .db
+        .call(move |conn| {
+            let mut saved = 0;
+            for diary in &diaries {
+                match save_common_diary_to_cache(conn, diary) {
+                    Ok(_) => saved += 1,
+                    Err(e) => println!("Failed to save diary to cache: {}", e),
+                }
+            }
+            Ok(saved)
+        })
+        .await
+        .unwrap_or(0)
+}
+
 // Function to check if cache is up to date by comparing file modification times
-fn is_cache_up_to_date() -> bool {
+// with the cache stats from common_diaries_cache_stats
+fn is_cache_up_to_date(cached_count: i64, latest_mod_time: Option<String>) -> bool {
     // Get list of diary files
     match list_common_diary_files() {
         Ok(files) => {
@@ -666,18 +831,10 @@ fn is_cache_up_to_date() -> bool {
             // Store the file count for later
             let file_count = files.len();
 
-            // Get the latest modification time from the database
-            let conn = DB_CONNECTION.lock().unwrap();
-            let latest_mod_time: Result<Option<String>, rusqlite::Error> = conn.query_row(
-                "SELECT MAX(last_modified) FROM common_diaries_cache",
-                [],
-                |row| row.get(0),
-            );
-
             // If we don't have any cached entries, cache is not up to date
             let latest_mod_time = match latest_mod_time {
-                Ok(Some(time)) => time,
-                _ => return false,
+                Some(time) => time,
+                None => return false,
             };
 
             // Parse the time
@@ -706,18 +863,10 @@ fn is_cache_up_to_date() -> bool {
             }
 
             // Also check if we have the same number of diaries in cache as files
-            let count: i64 =
-                match conn.query_row("SELECT COUNT(*) FROM common_diaries_cache", [], |row| {
-                    row.get(0)
-                }) {
-                    Ok(count) => count,
-                    Err(_) => return false,
-                };
-
-            if count as usize != file_count {
+            if cached_count as usize != file_count {
                 println!(
                     "Number of cached diaries ({}) differs from number of files ({})",
-                    count, file_count
+                    cached_count, file_count
                 );
                 return false;
             }
@@ -732,8 +881,7 @@ fn is_cache_up_to_date() -> bool {
 }
 
 // Function to clear the common diaries cache
-fn clear_common_diaries_cache() -> SqlResult<()> {
-    let conn = DB_CONNECTION.lock().unwrap();
+fn clear_common_diaries_cache(conn: &Connection) -> SqlResult<()> {
     conn.execute("DELETE FROM common_diaries_cache", [])?;
     println!("Common diaries cache cleared");
     Ok(())
@@ -741,11 +889,25 @@ fn clear_common_diaries_cache() -> SqlResult<()> {
 
 // Function to list all available common diaries
 #[tauri::command]
-fn list_common_diaries() -> Result<Vec<CommonDiary>, String> {
+async fn list_common_diaries(
+    store: State<'_, Arc<DiaryStore>>,
+) -> Result<Vec<CommonDiary>, String> {
+    let (cached_count, latest_mod_time) = store
+        .db
+        .call(|conn| {
+            common_diaries_cache_stats(conn)
+                .map_err(|e| format!("Failed to read common diaries cache: {}", e))
+        })
+        .await?;
+
     // First check if our cache is up to date
-    if is_cache_up_to_date() {
+    if is_cache_up_to_date(cached_count, latest_mod_time) {
         // Try to load from cache
-        match load_all_common_diaries_from_cache() {
+        let cached = store
+            .db
+            .call(|conn| load_all_common_diaries_from_cache(conn).map_err(|e| e.to_string()))
+            .await;
+        match cached {
             Ok(diaries) if !diaries.is_empty() => {
                 println!(
                     "Returning {} common diaries from SQLite cache",
@@ -768,13 +930,7 @@ fn list_common_diaries() -> Result<Vec<CommonDiary>, String> {
     let mut diaries = Vec::new();
     for file_path in files {
         match load_common_diary(&file_path) {
-            Ok(diary) => {
-                // Save to cache
-                if let Err(e) = save_common_diary_to_cache(&diary) {
-                    println!("Failed to save diary to cache: {}", e);
-                }
-                diaries.push(diary);
-            }
+            Ok(diary) => diaries.push(diary),
             Err(e) => println!(
                 "Failed to load common diary from {}: {}",
                 file_path.display(),
@@ -783,6 +939,9 @@ fn list_common_diaries() -> Result<Vec<CommonDiary>, String> {
         }
     }
 
+    // Save to cache
+    save_common_diaries_to_cache(&store, diaries.clone()).await;
+
     println!(
         "Loaded and cached {} common diaries from files",
         diaries.len()
@@ -799,30 +958,26 @@ fn get_common_diaries_dir_path() -> String {
 
 // Function to refresh the common diaries cache
 #[tauri::command]
-fn refresh_common_diaries_cache() -> Result<(), String> {
+async fn refresh_common_diaries_cache(store: State<'_, Arc<DiaryStore>>) -> Result<(), String> {
     println!("Refreshing common diaries cache");
 
     // Clear existing cache
-    match clear_common_diaries_cache() {
-        Ok(_) => (),
-        Err(e) => return Err(format!("Failed to clear common diaries cache: {}", e)),
-    }
+    store
+        .db
+        .call(|conn| {
+            clear_common_diaries_cache(conn)
+                .map_err(|e| format!("Failed to clear common diaries cache: {}", e))
+        })
+        .await?;
 
     // Reload files and rebuild cache
     println!("Reloading common diaries from files");
     let files = list_common_diary_files()?;
 
-    let mut diary_count = 0;
+    let mut diaries = Vec::new();
     for file_path in files {
         match load_common_diary(&file_path) {
-            Ok(diary) => {
-                // Save to cache
-                if let Err(e) = save_common_diary_to_cache(&diary) {
-                    println!("Failed to save diary to cache: {}", e);
-                } else {
-                    diary_count += 1;
-                }
-            }
+            Ok(diary) => diaries.push(diary),
             Err(e) => println!(
                 "Failed to load common diary from {}: {}",
                 file_path.display(),
@@ -831,6 +986,9 @@ fn refresh_common_diaries_cache() -> Result<(), String> {
         }
     }
 
+    // Save to cache
+    let diary_count = save_common_diaries_to_cache(&store, diaries).await;
+
     println!(
         "Refreshed cache with {} common diaries from files",
         diary_count
@@ -839,34 +997,27 @@ fn refresh_common_diaries_cache() -> Result<(), String> {
 }
 
 #[tauri::command]
-fn get_common_diaries_cache_status() -> Result<String, String> {
-    let conn = DB_CONNECTION.lock().unwrap();
-
-    // Count the number of cached diaries
-    let count: i64 = match conn.query_row("SELECT COUNT(*) FROM common_diaries_cache", [], |row| {
-        row.get(0)
-    }) {
-        Ok(count) => count,
-        Err(e) => return Err(format!("Failed to count cached diaries: {}", e)),
-    };
-
-    // Get the latest modification time
-    let latest_mod_time: Result<Option<String>, rusqlite::Error> = conn.query_row(
-        "SELECT MAX(last_modified) FROM common_diaries_cache",
-        [],
-        |row| row.get(0),
-    );
+async fn get_common_diaries_cache_status(
+    store: State<'_, Arc<DiaryStore>>,
+) -> Result<String, String> {
+    // Count the number of cached diaries and get the latest modification time
+    let (count, latest_mod_time) = store
+        .db
+        .call(|conn| {
+            common_diaries_cache_stats(conn)
+                .map_err(|e| format!("Failed to read common diaries cache: {}", e))
+        })
+        .await?;
 
     let latest_mod_time = match latest_mod_time {
-        Ok(Some(time)) => {
+        Some(time) => {
             // Parse the full timestamp into a more readable format
             match DateTime::parse_from_rfc3339(&time) {
                 Ok(dt) => dt.format("%Y-%m-%d %H:%M:%S").to_string(),
                 Err(_) => time,
             }
         }
-        Ok(None) => "未缓存".to_string(),
-        Err(e) => return Err(format!("Failed to get latest modification time: {}", e)),
+        None => "未缓存".to_string(),
     };
 
     // Count the actual files in directory for comparison
@@ -900,7 +1051,7 @@ fn get_common_diaries_cache_status() -> Result<String, String> {
 }
 
 #[tauri::command]
-async fn download_common_diaries() -> Result<(), String> {
+async fn download_common_diaries(store: State<'_, Arc<DiaryStore>>) -> Result<(), String> {
     println!("Downloading common diaries from GitHub");
 
     // 获取名人日记目录
@@ -930,7 +1081,7 @@ async fn download_common_diaries() -> Result<(), String> {
     }
 
     // 刷新缓存
-    refresh_common_diaries_cache()?;
+    refresh_common_diaries_cache(store).await?;
 
     println!("Common diaries downloaded successfully");
     Ok(())
@@ -968,7 +1119,7 @@ fn get_config(store: State<Arc<DiaryStore>>) -> Result<Config, String> {
 }
 
 #[tauri::command]
-fn update_config(store: State<Arc<DiaryStore>>, new_config: Config) -> Result<(), String> {
+fn update_config(store: State<Arc<DiaryStore>>, mut new_config: Config) -> Result<(), String> {
     println!("Updating config with: {:?}", new_config);
 
     // Validate relay URLs
@@ -977,6 +1128,18 @@ fn update_config(store: State<Arc<DiaryStore>>, new_config: Config) -> Result<()
             return Err(format!("Invalid relay URL: {}", url));
         }
     }
+    // Roles of relays that were removed are dropped with them
+    let relay_urls = new_config.relay_urls.clone();
+    new_config
+        .relay_roles
+        .retain(|url, _| relay_urls.contains(url));
+    if let Some(name) = new_config
+        .timezone
+        .as_deref()
+        .filter(|n| !n.trim().is_empty())
+    {
+        timezone::parse_timezone(name)?;
+    }
 
     // Save to file
     save_config(&new_config)?;
@@ -984,6 +1147,7 @@ fn update_config(store: State<Arc<DiaryStore>>, new_config: Config) -> Result<()
     // Update in-memory config
     let mut config = store.config.lock().unwrap();
     *config = new_config;
+    nostr_client::spawn_sync_relays(store.inner().clone());
     println!("Config updated successfully");
 
     Ok(())
@@ -993,8 +1157,12 @@ fn update_config(store: State<Arc<DiaryStore>>, new_config: Config) -> Result<()
 pub fn run() {
     println!("Starting Lu Xun's Diary application");
 
-    // Initialize database
-    Lazy::force(&DB_CONNECTION);
+    // Initialize database. An encrypted diary starts locked: the frontend
+    // checks get_vault_status and calls unlock_diary with the passphrase,
+    // which opens the database, migrates it and loads the Nostr keys.
+    let db = Arc::new(database::Database::start(
+        setup_db().expect("Failed to set up database"),
+    ));
     println!("Database initialized");
 
     // Load config
@@ -1005,8 +1173,16 @@ pub fn run() {
     let diary_store = Arc::new(DiaryStore {
         nostr_keys: Mutex::new(None),
         config: Mutex::new(config),
+        repository: Box::new(repository::SqliteDiaryRepository::new(db.clone())),
+        db,
+        relay_information: Mutex::new(HashMap::new()),
+        client: nostr_client::new_client(),
     });
 
+    backup_service::start_backup_scheduler(diary_store.clone());
+    outbox::start_outbox_worker(diary_store.clone());
+    nostr_client::spawn_sync_relays(diary_store.clone());
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_http::init())
@@ -1015,10 +1191,33 @@ pub fn run() {
             greet,
             save_diary_entry,
             get_diary_entries,
+            query_service::query_diary_entries,
+            get_entries_for_month,
+            get_entries_for_year,
+            get_entries_in_range,
             get_nostr_event,
             get_nostr_public_key,
             check_day_has_entry,
             verify_nostr_signature,
+            audit_service::audit_diary,
+            search_service::search_diary_entries,
+            revision_service::update_diary_entry,
+            revision_service::list_entry_revisions,
+            revision_service::diff_entry_revisions,
+            revision_service::set_entry_visibility,
+            trash_service::trash_diary_entry,
+            trash_service::restore_diary_entry,
+            trash_service::list_trashed_entries,
+            trash_service::purge_diary_entry,
+            tag_service::list_tags,
+            tag_service::get_entry_tags,
+            tag_service::set_entry_tags,
+            timezone::get_today,
+            timezone::get_timezone_report,
+            attachment_service::add_attachment,
+            attachment_service::list_attachments,
+            attachment_service::get_attachment,
+            attachment_service::remove_attachment,
             list_common_diaries,
             get_common_diaries_dir_path,
             refresh_common_diaries_cache,
@@ -1026,6 +1225,25 @@ pub fn run() {
             download_common_diaries,
             get_config,
             update_config,
+            outbox::list_outbox,
+            outbox::retry_outbox,
+            outbox::get_publish_status,
+            outbox::publish_pending_entries,
+            relay_list::get_relay_list,
+            relay_list::publish_relay_list,
+            relay_list::import_relay_list,
+            relay_health::check_relays,
+            relay_health::check_entry_limits,
+            nostr_client::get_relay_connection_status,
+            restore_service::restore_from_relays,
+            backup_service::list_backups,
+            backup_service::create_backup,
+            backup_service::restore_backup,
+            data_dir::get_data_dir_info,
+            data_dir::move_data_dir,
+            vault::get_vault_status,
+            vault::unlock_diary,
+            vault::change_passphrase,
             // Add the new gift wrap service commands
             gift_wrap_service::gift_wrap_diary,
             gift_wrap_service::share_gift_wrap,
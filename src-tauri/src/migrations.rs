@@ -0,0 +1,684 @@
+// Versioned schema migrations for diary.db
+//
+// The schema version lives in `PRAGMA user_version`. Every migration runs in
+// its own transaction together with the version bump, so a failure leaves the
+// database at the last fully applied version. Databases created before this
+// module existed report version 0 but already contain the original tables,
+// which is why the first migration only uses `CREATE TABLE IF NOT EXISTS`.
+// A database written by a newer version of the app is refused rather than
+// opened with a schema this version does not know.
+use chrono::Utc;
+use rusqlite::{Connection, Result as SqlResult, Transaction};
+use std::path::{Path, PathBuf};
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub up: fn(&Transaction) -> SqlResult<()>,
+}
+
+// Ordered list of all migrations. Append new steps at the end and never edit
+// a step that has already shipped.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial diary_entries and common_diaries_cache tables",
+        up: migrate_v1_initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "year/month/day keys on diary_entries",
+        up: migrate_v2_date_keys,
+    },
+    Migration {
+        version: 3,
+        description: "full-text search index over diary_entries",
+        up: migrate_v3_search_index,
+    },
+    Migration {
+        version: 4,
+        description: "signed revision history for diary entries",
+        up: migrate_v4_entry_revisions,
+    },
+    Migration {
+        version: 5,
+        description: "soft delete for diary entries",
+        up: migrate_v5_trash,
+    },
+    Migration {
+        version: 6,
+        description: "record gift wrap shares per recipient",
+        up: migrate_v6_gift_wrap_shares,
+    },
+    Migration {
+        version: 7,
+        description: "attachments stored by content hash",
+        up: migrate_v7_attachments,
+    },
+    Migration {
+        version: 8,
+        description: "normalized tags for diary entries",
+        up: migrate_v8_tags,
+    },
+    Migration {
+        version: 9,
+        description: "UTC offset an entry was written at",
+        up: migrate_v9_utc_offset,
+    },
+    Migration {
+        version: 10,
+        description: "outbox of events waiting to be published",
+        up: migrate_v10_outbox,
+    },
+    Migration {
+        version: 11,
+        description: "per-relay publish receipts",
+        up: migrate_v11_publish_receipts,
+    },
+    Migration {
+        version: 12,
+        description: "private entries encrypted to our own key",
+        up: migrate_v12_private_entries,
+    },
+];
+
+fn migrate_v1_initial_schema(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS diary_entries (
+            id TEXT PRIMARY KEY,
+            content TEXT NOT NULL,
+            weather TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            nostr_id TEXT UNIQUE,
+            day TEXT UNIQUE,
+            nostr_event TEXT
+        );
+        CREATE TABLE IF NOT EXISTS common_diaries_cache (
+            author TEXT PRIMARY KEY,
+            data TEXT NOT NULL,
+            last_modified TEXT NOT NULL
+        );",
+    )
+}
+
+// Splits the free-text `day` column into indexed integer keys so calendar
+// views can query a month or year without scanning every entry. Triggers keep
+// the keys in sync with `day`, also for rows written by other tools such as
+// scripts/migrate-jrnl, which only set `day`.
+fn migrate_v2_date_keys(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "ALTER TABLE diary_entries ADD COLUMN year INTEGER;
+        ALTER TABLE diary_entries ADD COLUMN month INTEGER;
+        ALTER TABLE diary_entries ADD COLUMN day_of_month INTEGER;
+        UPDATE diary_entries SET
+            year = CAST(substr(day, 1, 4) AS INTEGER),
+            month = CAST(substr(day, 6, 2) AS INTEGER),
+            day_of_month = CAST(substr(day, 9, 2) AS INTEGER)
+        WHERE day GLOB '[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]';
+        CREATE INDEX IF NOT EXISTS idx_diary_entries_date
+            ON diary_entries (year, month, day_of_month);
+        CREATE TRIGGER IF NOT EXISTS diary_entries_date_keys_insert
+            AFTER INSERT ON diary_entries
+            WHEN NEW.day GLOB '[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]'
+        BEGIN
+            UPDATE diary_entries SET
+                year = CAST(substr(NEW.day, 1, 4) AS INTEGER),
+                month = CAST(substr(NEW.day, 6, 2) AS INTEGER),
+                day_of_month = CAST(substr(NEW.day, 9, 2) AS INTEGER)
+            WHERE rowid = NEW.rowid;
+        END;
+        CREATE TRIGGER IF NOT EXISTS diary_entries_date_keys_update
+            AFTER UPDATE OF day ON diary_entries
+            WHEN NEW.day GLOB '[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]'
+        BEGIN
+            UPDATE diary_entries SET
+                year = CAST(substr(NEW.day, 1, 4) AS INTEGER),
+                month = CAST(substr(NEW.day, 6, 2) AS INTEGER),
+                day_of_month = CAST(substr(NEW.day, 9, 2) AS INTEGER)
+            WHERE rowid = NEW.rowid;
+        END;",
+    )
+}
+
+// The FTS table stores pre-tokenized text (see search_service::tokenize), so
+// the plain unicode61 tokenizer only has to split on the spaces we insert.
+fn migrate_v3_search_index(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS diary_entries_fts USING fts5(
+            entry_id UNINDEXED,
+            content,
+            weather,
+            tokenize = 'unicode61'
+        );",
+    )?;
+    crate::search_service::rebuild_index(tx)
+}
+
+// Every signed event of an entry is kept as a revision. Existing entries get
+// their current event as revision 1.
+fn migrate_v4_entry_revisions(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "ALTER TABLE diary_entries ADD COLUMN updated_at TEXT;
+        CREATE TABLE IF NOT EXISTS diary_entry_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            entry_id TEXT NOT NULL,
+            revision INTEGER NOT NULL,
+            nostr_id TEXT,
+            nostr_event TEXT,
+            content TEXT NOT NULL,
+            weather TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            UNIQUE (entry_id, revision)
+        );
+        CREATE INDEX IF NOT EXISTS idx_diary_entry_revisions_nostr_id
+            ON diary_entry_revisions (nostr_id);
+        INSERT INTO diary_entry_revisions
+            (entry_id, revision, nostr_id, nostr_event, content, weather, created_at)
+        SELECT id, 1, nostr_id, nostr_event, content, weather, created_at
+        FROM diary_entries;",
+    )
+}
+
+fn migrate_v5_trash(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch("ALTER TABLE diary_entries ADD COLUMN deleted_at TEXT;")
+}
+
+fn migrate_v6_gift_wrap_shares(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS gift_wrap_shares (
+            gift_wrap_id TEXT PRIMARY KEY,
+            entry_id TEXT,
+            nostr_id TEXT NOT NULL,
+            recipient_pubkey TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_gift_wrap_shares_entry
+            ON gift_wrap_shares (entry_id, recipient_pubkey);",
+    )
+}
+
+// Metadata per entry, contents and thumbnails once per hash in
+// attachment_blobs, so they are encrypted with the rest of the diary
+fn migrate_v7_attachments(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS attachments (
+            entry_id TEXT NOT NULL,
+            sha256 TEXT NOT NULL,
+            file_name TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            width INTEGER,
+            height INTEGER,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (entry_id, sha256)
+        );
+        CREATE INDEX IF NOT EXISTS idx_attachments_sha256 ON attachments (sha256);
+        CREATE TABLE IF NOT EXISTS attachment_blobs (
+            sha256 TEXT PRIMARY KEY,
+            data BLOB NOT NULL,
+            thumbnail BLOB
+        );",
+    )
+}
+
+// Existing entries get the hashtags found in their content
+fn migrate_v8_tags(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE
+        );
+        CREATE TABLE IF NOT EXISTS entry_tags (
+            entry_id TEXT NOT NULL,
+            tag_id INTEGER NOT NULL REFERENCES tags (id),
+            manual INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (entry_id, tag_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_entry_tags_tag ON entry_tags (tag_id);",
+    )?;
+    crate::tag_service::rebuild_tags(tx)
+}
+
+// Left empty for existing entries, see timezone::find_misfiled_entries
+fn migrate_v9_utc_offset(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch("ALTER TABLE diary_entries ADD COLUMN utc_offset TEXT;")
+}
+
+// next_attempt_at and leased_until are Unix timestamps so due rows can be
+// compared in SQL. event_created_at lets a queued version of an entry be
+// replaced only by a newer one.
+fn migrate_v10_outbox(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS outbox (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            event_id TEXT NOT NULL,
+            event_json TEXT NOT NULL,
+            kind INTEGER NOT NULL,
+            coordinate TEXT,
+            relay_url TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            attempts INTEGER NOT NULL DEFAULT 0,
+            last_error TEXT,
+            next_attempt_at INTEGER,
+            leased_until INTEGER,
+            event_created_at INTEGER,
+            created_at TEXT NOT NULL,
+            sent_at TEXT,
+            UNIQUE (event_id, relay_url)
+        );
+        CREATE INDEX IF NOT EXISTS idx_outbox_due ON outbox (status, next_attempt_at);
+        CREATE INDEX IF NOT EXISTS idx_outbox_coordinate ON outbox (coordinate);",
+    )
+}
+
+// The latest answer of each relay to an event
+fn migrate_v11_publish_receipts(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS publish_receipts (
+            event_id TEXT NOT NULL,
+            relay_url TEXT NOT NULL,
+            accepted INTEGER NOT NULL,
+            message TEXT,
+            received_at TEXT NOT NULL,
+            PRIMARY KEY (event_id, relay_url)
+        );",
+    )
+}
+
+// Existing entries were published in the clear
+fn migrate_v12_private_entries(tx: &Transaction) -> SqlResult<()> {
+    tx.execute_batch("ALTER TABLE diary_entries ADD COLUMN private INTEGER NOT NULL DEFAULT 0;")
+}
+
+pub fn latest_version(migrations: &[Migration]) -> u32 {
+    migrations.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn current_version(conn: &Connection) -> SqlResult<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+// A database is "fresh" when it has no user tables yet, so there is nothing
+// worth backing up before migrating.
+fn is_fresh_database(conn: &Connection) -> SqlResult<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count == 0)
+}
+
+// Writes a consistent copy of the database next to `db_path` before any
+// migration touches it. Returns the path of the backup file.
+pub fn backup_before_migration(
+    conn: &Connection,
+    db_path: &Path,
+    from_version: u32,
+) -> SqlResult<PathBuf> {
+    let file_name = db_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "diary.db".to_string());
+    let backup_path = db_path.with_file_name(format!(
+        "{}.pre-v{}-{}.bak",
+        file_name,
+        from_version,
+        Utc::now().format("%Y%m%d%H%M%S")
+    ));
+
+    conn.execute(
+        "VACUUM INTO ?1",
+        [backup_path.to_string_lossy().to_string()],
+    )?;
+    println!("Backed up database to: {}", backup_path.display());
+    Ok(backup_path)
+}
+
+// Applies every migration newer than the current `user_version`, in order.
+// When `db_path` is given and the database already holds data, a backup
+// is written next to it first. Returns the resulting schema version.
+pub fn apply_migrations(
+    conn: &mut Connection,
+    migrations: &[Migration],
+    db_path: Option<&Path>,
+) -> SqlResult<u32> {
+    let mut version = current_version(conn)?;
+    let latest = latest_version(migrations);
+    if version > latest {
+        return Err(newer_schema_error(version, latest));
+    }
+    let pending: Vec<&Migration> = migrations.iter().filter(|m| m.version > version).collect();
+
+    if pending.is_empty() {
+        return Ok(version);
+    }
+
+    if let Some(db_path) = db_path {
+        if !is_fresh_database(conn)? {
+            backup_before_migration(conn, db_path, version)?;
+        }
+    }
+
+    for migration in pending {
+        if migration.version <= version {
+            return Err(rusqlite::Error::InvalidParameterName(format!(
+                "Migration {} is out of order",
+                migration.version
+            )));
+        }
+
+        println!(
+            "Applying database migration {}: {}",
+            migration.version, migration.description
+        );
+
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+
+        version = migration.version;
+    }
+
+    Ok(version)
+}
+
+pub fn newer_schema_error(version: u32, latest: u32) -> rusqlite::Error {
+    rusqlite::Error::InvalidParameterName(format!(
+        "Database schema version {} is newer than this app supports ({}), please update the app",
+        version, latest
+    ))
+}
+
+pub fn migrate(conn: &mut Connection, db_path: Option<&Path>) -> SqlResult<u32> {
+    apply_migrations(conn, MIGRATIONS, db_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    fn table_exists(conn: &Connection, name: &str) -> bool {
+        conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [name],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap()
+            > 0
+    }
+
+    fn temp_db_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "luxun-dairy-migrations-{}-{}",
+            name,
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("diary.db")
+    }
+
+    // Applies the migrations up to `version` only
+    fn migrate_to(conn: &mut Connection, version: u32) {
+        let steps: Vec<Migration> = MIGRATIONS
+            .iter()
+            .filter(|m| m.version <= version)
+            .map(|m| Migration {
+                version: m.version,
+                description: m.description,
+                up: m.up,
+            })
+            .collect();
+        apply_migrations(conn, &steps, None).unwrap();
+    }
+
+    #[test]
+    fn test_migration_versions_are_strictly_increasing() {
+        let mut previous = 0;
+        for migration in MIGRATIONS {
+            assert!(migration.version > previous);
+            previous = migration.version;
+        }
+    }
+
+    #[test]
+    fn test_fresh_database_migrates_to_latest() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let version = migrate(&mut conn, None).unwrap();
+
+        assert_eq!(version, latest_version(MIGRATIONS));
+        assert_eq!(current_version(&conn).unwrap(), version);
+        assert!(table_exists(&conn, "diary_entries"));
+        assert!(table_exists(&conn, "common_diaries_cache"));
+    }
+
+    #[test]
+    fn test_migrate_is_idempotent() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let first = migrate(&mut conn, None).unwrap();
+        let second = migrate(&mut conn, None).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_legacy_database_keeps_its_entries() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE diary_entries (
+                id TEXT PRIMARY KEY,
+                content TEXT NOT NULL,
+                weather TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                nostr_id TEXT UNIQUE,
+                day TEXT UNIQUE,
+                nostr_event TEXT
+            );",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO diary_entries (id, content, weather, created_at, nostr_id, day, nostr_event)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                "legacy-id",
+                "晴。上午往图书馆。",
+                "晴",
+                "2024-01-02T03:04:05+00:00",
+                "legacy-nostr-id",
+                "2024-01-02",
+                "{}"
+            ],
+        )
+        .unwrap();
+
+        migrate(&mut conn, None).unwrap();
+
+        let content: String = conn
+            .query_row(
+                "SELECT content FROM diary_entries WHERE id = 'legacy-id'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(content, "晴。上午往图书馆。");
+        assert_eq!(current_version(&conn).unwrap(), latest_version(MIGRATIONS));
+
+        let date_keys: (i32, u32, u32) = conn
+            .query_row(
+                "SELECT year, month, day_of_month FROM diary_entries WHERE id = 'legacy-id'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(date_keys, (2024, 1, 2));
+    }
+
+    #[test]
+    fn test_date_keys_follow_day() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, None).unwrap();
+
+        // Inserted the way scripts/migrate-jrnl does, without the keys
+        conn.execute(
+            "INSERT INTO diary_entries (id, content, weather, created_at, day)
+             VALUES ('jrnl-id', '晴。', '晴', 'now', '1912-05-05')",
+            [],
+        )
+        .unwrap();
+        let keys = |conn: &Connection| -> (i32, u32, u32) {
+            conn.query_row(
+                "SELECT year, month, day_of_month FROM diary_entries WHERE id = 'jrnl-id'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap()
+        };
+        assert_eq!(keys(&conn), (1912, 5, 5));
+
+        conn.execute(
+            "UPDATE diary_entries SET day = '1912-06-01' WHERE id = 'jrnl-id'",
+            [],
+        )
+        .unwrap();
+        assert_eq!(keys(&conn), (1912, 6, 1));
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        fn create_table(tx: &Transaction) -> SqlResult<()> {
+            tx.execute_batch("CREATE TABLE first (id INTEGER PRIMARY KEY);")
+        }
+        fn broken(tx: &Transaction) -> SqlResult<()> {
+            tx.execute_batch("CREATE TABLE second (id INTEGER PRIMARY KEY); NOT VALID SQL;")
+        }
+        let migrations = [
+            Migration {
+                version: 1,
+                description: "first",
+                up: create_table,
+            },
+            Migration {
+                version: 2,
+                description: "broken",
+                up: broken,
+            },
+        ];
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert!(apply_migrations(&mut conn, &migrations, None).is_err());
+
+        assert_eq!(current_version(&conn).unwrap(), 1);
+        assert!(table_exists(&conn, "first"));
+        assert!(!table_exists(&conn, "second"));
+    }
+
+    #[test]
+    fn test_legacy_entries_are_indexed_and_tagged() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, 2);
+        conn.execute(
+            "INSERT INTO diary_entries (id, content, weather, created_at, day)
+             VALUES ('legacy-id', '上午往 #图书馆# http://a.b/page#x #2024 #Read', '晴', 'now', '2024-01-02')",
+            [],
+        )
+        .unwrap();
+
+        migrate(&mut conn, None).unwrap();
+
+        let indexed: String = conn
+            .query_row(
+                "SELECT content FROM diary_entries_fts WHERE entry_id = 'legacy-id'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            indexed,
+            "上午 午往 往 图书 书馆 馆 http a b page x 2024 read"
+        );
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT t.name FROM entry_tags et JOIN tags t ON t.id = et.tag_id
+                 WHERE et.entry_id = 'legacy-id' ORDER BY t.name",
+            )
+            .unwrap();
+        let tags: Vec<String> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<SqlResult<_>>()
+            .unwrap();
+        assert_eq!(tags, vec!["read", "图书馆"]);
+    }
+
+    #[test]
+    fn test_newer_database_is_rejected() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", 999).unwrap();
+
+        fn noop(_tx: &Transaction) -> SqlResult<()> {
+            Ok(())
+        }
+        let migrations = [Migration {
+            version: 1,
+            description: "noop",
+            up: noop,
+        }];
+
+        assert!(apply_migrations(&mut conn, &migrations, None).is_err());
+        assert_eq!(current_version(&conn).unwrap(), 999);
+    }
+
+    #[test]
+    fn test_backup_is_written_before_migrating_existing_data() {
+        let db_path = temp_db_path("backup");
+        {
+            let conn = Connection::open(&db_path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE common_diaries_cache (
+                    author TEXT PRIMARY KEY,
+                    data TEXT NOT NULL,
+                    last_modified TEXT NOT NULL
+                );
+                INSERT INTO common_diaries_cache VALUES ('鲁迅', '{}', 'now');",
+            )
+            .unwrap();
+        }
+
+        let mut conn = Connection::open(&db_path).unwrap();
+        migrate(&mut conn, Some(&db_path)).unwrap();
+
+        let backups: Vec<PathBuf> = std::fs::read_dir(db_path.parent().unwrap())
+            .unwrap()
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.to_string_lossy().ends_with(".bak"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+
+        let backup = Connection::open(&backups[0]).unwrap();
+        assert_eq!(current_version(&backup).unwrap(), 0);
+        let author: String = backup
+            .query_row("SELECT author FROM common_diaries_cache", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(author, "鲁迅");
+
+        std::fs::remove_dir_all(db_path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_fresh_database_is_not_backed_up() {
+        let db_path = temp_db_path("fresh");
+        let mut conn = Connection::open(&db_path).unwrap();
+        migrate(&mut conn, Some(&db_path)).unwrap();
+
+        let files = std::fs::read_dir(db_path.parent().unwrap())
+            .unwrap()
+            .count();
+        assert_eq!(files, 1);
+
+        std::fs::remove_dir_all(db_path.parent().unwrap()).unwrap();
+    }
+}
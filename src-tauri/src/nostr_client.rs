@@ -0,0 +1,192 @@
+// The Nostr client shared by every relay operation
+//
+// DiaryStore holds one Client for the lifetime of the app, so publishing,
+// restoring and gift wraps reuse open connections instead of a handshake per
+// action. The configured relays stay in its pool and reconnect on their own;
+// sync_relays brings the pool in line with Config when it changes. Other
+// relays, e.g. a recipient's inbox relays, never join the pool: an operation
+// that needs them gets a client of its own, shut down when it is done, so a
+// sync cannot cut it off.
+use futures::future::join_all;
+use nostr_sdk::prelude::RelayStatus;
+use nostr_sdk::{Client, RelayUrl};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::State;
+
+use crate::DiaryStore;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RelayConnection {
+    pub url: String,
+    pub status: String, // "connected", "connecting", "disconnected", ...
+    pub configured: bool,
+    pub connected_at: Option<i64>, // unix time of the last connection
+    pub latency_ms: Option<u64>,
+    pub attempts: usize,
+    pub successes: usize,
+}
+
+pub fn new_client() -> Client {
+    Client::default()
+}
+
+// Relays that belong in the pool: every configured relay, read or write
+fn configured_relays(store: &DiaryStore) -> Vec<RelayUrl> {
+    crate::relay_list::all_relay_urls(&store.config.lock().unwrap())
+        .iter()
+        .filter_map(|url| match RelayUrl::parse(url) {
+            Ok(relay_url) => Some(relay_url),
+            Err(e) => {
+                println!("Skipping invalid relay URL {}: {}", url, e);
+                None
+            }
+        })
+        .collect()
+}
+
+// Adds the configured relays to the pool and removes the others. The pool
+// connects in the background and keeps reconnecting, unless the app is in
+// offline mode, where every connection is closed.
+pub async fn sync_relays(store: &DiaryStore) {
+    let client = &store.client;
+    let configured = configured_relays(store);
+
+    for url in client.relays().await.into_keys() {
+        if !configured.contains(&url) {
+            if let Err(e) = client.force_remove_relay(&url).await {
+                println!("Failed to remove relay {}: {}", url, e);
+            }
+        }
+    }
+    for url in &configured {
+        if let Err(e) = client.add_relay(url).await {
+            println!("Failed to add relay {}: {}", url, e);
+        }
+    }
+
+    if store.config.lock().unwrap().offline_mode {
+        client.disconnect().await;
+    } else {
+        client.connect().await;
+    }
+}
+
+// Syncs the pool without waiting for it, for callers that are not async
+pub fn spawn_sync_relays(store: Arc<DiaryStore>) {
+    tauri::async_runtime::spawn(async move {
+        sync_relays(&store).await;
+    });
+}
+
+async fn connect_relay(client: &Client, url: &str) -> Result<RelayUrl, String> {
+    let relay_url = RelayUrl::parse(url).map_err(|e| format!("Invalid relay URL: {}", e))?;
+    client
+        .add_relay(&relay_url)
+        .await
+        .map_err(|e| format!("Failed to add relay: {}", e))?;
+    let relay = client
+        .relay(&relay_url)
+        .await
+        .map_err(|e| format!("Failed to add relay: {}", e))?;
+
+    match relay.status() {
+        RelayStatus::Connected => {}
+        // Never connected, or given up on: connect now
+        RelayStatus::Initialized | RelayStatus::Terminated => relay
+            .try_connect(CONNECT_TIMEOUT)
+            .await
+            .map_err(|e| format!("Failed to connect: {}", e))?,
+        // A connection attempt is already under way
+        _ => relay.wait_for_connection(CONNECT_TIMEOUT).await,
+    }
+
+    match relay.status() {
+        RelayStatus::Connected => Ok(relay_url),
+        status => Err(format!("Failed to connect: relay is {}", status)),
+    }
+}
+
+// The client to use for one operation and the result of connecting to each
+// of its relays, in order
+pub struct RelayConnections {
+    pub client: Client,
+    pub results: Vec<Result<RelayUrl, String>>,
+    temporary: bool,
+}
+
+impl RelayConnections {
+    // The relays that are connected, or an error when none is
+    pub fn connected(&self) -> Result<Vec<RelayUrl>, String> {
+        let relays: Vec<RelayUrl> = self.results.iter().flatten().cloned().collect();
+        if relays.is_empty() {
+            Err("Could not connect to any relay".to_string())
+        } else {
+            Ok(relays)
+        }
+    }
+}
+
+impl Drop for RelayConnections {
+    fn drop(&mut self) {
+        if self.temporary {
+            let client = self.client.clone();
+            tauri::async_runtime::spawn(async move {
+                client.shutdown().await;
+            });
+        }
+    }
+}
+
+// Connects to `relay_urls` for one operation. When they are all configured
+// the shared client is used; otherwise every relay is opened on a new client
+// that is shut down once the returned connections are dropped.
+pub async fn connect_relays(store: &DiaryStore, relay_urls: &[String]) -> RelayConnections {
+    let configured = configured_relays(store);
+    let temporary = relay_urls.iter().any(
+        |url| matches!(RelayUrl::parse(url), Ok(relay_url) if !configured.contains(&relay_url)),
+    );
+    let client = if temporary {
+        new_client()
+    } else {
+        store.client.clone()
+    };
+
+    let results = join_all(relay_urls.iter().map(|url| connect_relay(&client, url))).await;
+    RelayConnections {
+        client,
+        results,
+        temporary,
+    }
+}
+
+#[tauri::command]
+pub async fn get_relay_connection_status(
+    store: State<'_, Arc<DiaryStore>>,
+) -> Result<Vec<RelayConnection>, String> {
+    let configured = configured_relays(&store);
+    let mut connections: Vec<RelayConnection> = store
+        .client
+        .relays()
+        .await
+        .into_iter()
+        .map(|(url, relay)| {
+            let stats = relay.stats();
+            let connected_at = stats.connected_at().as_u64();
+            RelayConnection {
+                configured: configured.contains(&url),
+                url: url.to_string(),
+                status: relay.status().to_string().to_lowercase(),
+                connected_at: (connected_at > 0).then_some(connected_at as i64),
+                latency_ms: stats.latency().map(|d| d.as_millis() as u64),
+                attempts: stats.attempts(),
+                successes: stats.success(),
+            }
+        })
+        .collect();
+    connections.sort_by(|a, b| a.url.cmp(&b.url));
+    Ok(connections)
+}
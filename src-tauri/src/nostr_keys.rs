@@ -0,0 +1,60 @@
+// Public keys as users enter and share them
+//
+// Recipients may be given as hex, an NIP-19 `npub` or `nprofile`, or any of
+// the bech32 forms as a NIP-21 `nostr:` URI. An nprofile also carries relay
+// hints, which are tried when looking for the recipient's inbox relays.
+use nostr_sdk::nips::nip19::{FromBech32, Nip19Profile, ToBech32};
+use nostr_sdk::nips::nip21::FromNostrUri;
+use nostr_sdk::PublicKey;
+use serde::{Deserialize, Serialize};
+
+const NOSTR_URI_PREFIX: &str = "nostr:";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PublicKeyInfo {
+    pub hex: String,
+    pub npub: String,
+    pub relays: Vec<String>, // relay hints of an nprofile
+}
+
+pub fn public_key_info(
+    public_key: &PublicKey,
+    relays: Vec<String>,
+) -> Result<PublicKeyInfo, String> {
+    Ok(PublicKeyInfo {
+        hex: public_key.to_hex(),
+        npub: public_key
+            .to_bech32()
+            .map_err(|e| format!("Failed to encode npub: {}", e))?,
+        relays,
+    })
+}
+
+// An nsec or NIP-49 ncryptsec, pasted where a public key belongs
+pub fn is_secret_key(input: &str) -> bool {
+    let input = input.trim();
+    let bare = input.strip_prefix(NOSTR_URI_PREFIX).unwrap_or(input);
+    bare.starts_with("nsec1") || bare.starts_with("ncryptsec1")
+}
+
+pub fn parse_pubkey(input: &str) -> Result<PublicKeyInfo, String> {
+    // Catch a pasted secret key before it ends up anywhere else
+    if is_secret_key(input) {
+        return Err("This is a secret key, never share it. Use the npub instead".to_string());
+    }
+
+    let input = input.trim();
+    let bare = input.strip_prefix(NOSTR_URI_PREFIX).unwrap_or(input);
+
+    if let Ok(public_key) = PublicKey::parse(input) {
+        return public_key_info(&public_key, Vec::new());
+    }
+
+    let profile = Nip19Profile::from_bech32(bare)
+        .or_else(|_| Nip19Profile::from_nostr_uri(input))
+        .map_err(|_| format!("Invalid public key: {}", input))?;
+    public_key_info(
+        &profile.public_key,
+        profile.relays.iter().map(|url| url.to_string()).collect(),
+    )
+}
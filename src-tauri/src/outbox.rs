@@ -0,0 +1,773 @@
+// Persistent queue of events waiting to reach relays
+//
+// Every signed event is queued once per relay before the first attempt, so
+// nothing is lost when the app is offline or a relay is down. A background
+// worker retries failed deliveries with exponential backoff until
+// MAX_ATTEMPTS, after which the row stays `failed` until retried by hand.
+// Events saved to publish later are `held` and only sent by
+// publish_pending_entries. In offline mode events are queued but neither
+// sent right away nor by the worker.
+use chrono::{DateTime, Utc};
+use nostr_sdk::Event;
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tauri::State;
+
+use crate::DiaryStore;
+
+const STATUS_PENDING: &str = "pending";
+const STATUS_SENT: &str = "sent";
+const STATUS_FAILED: &str = "failed";
+// Kept locally until published with publish_pending_entries
+const STATUS_HELD: &str = "held";
+// A newer version of the same replaceable event was queued after this one
+const STATUS_SUPERSEDED: &str = "superseded";
+
+const MAX_ATTEMPTS: u32 = 20;
+const BASE_RETRY_DELAY_SECS: i64 = 30;
+const MAX_RETRY_DELAY_SECS: i64 = 6 * 60 * 60;
+// Rows being delivered are hidden from other deliveries for this long, so a
+// crash mid-send only delays the next attempt
+const DELIVERY_LEASE_SECS: i64 = 2 * 60;
+
+const WORKER_INTERVAL: Duration = Duration::from_secs(30);
+// Delivered rows are kept this long for inspection
+const SENT_RETENTION_DAYS: i64 = 30;
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct OutboxItem {
+    pub id: i64,
+    pub event_id: String,
+    pub kind: u16,
+    pub relay_url: String,
+    pub status: String, // "pending", "held", "sent", "failed" or "superseded"
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<DateTime<Utc>>, // None once sent or given up
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+// What one relay made of the current event of an entry
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RelayReceipt {
+    pub relay_url: String,
+    pub accepted: bool,
+    pub message: Option<String>, // the relay's error, or why it could not be reached
+    pub received_at: Option<DateTime<Utc>>, // None until the first attempt
+    pub retrying: bool,          // the outbox will try this relay again
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PublishStatus {
+    pub event_id: Option<String>,
+    pub accepted: usize,
+    pub total: usize,
+    pub held: bool,    // saved to publish later and not sent yet
+    pub private: bool, // the entry is published encrypted to our own key
+    pub relays: Vec<RelayReceipt>,
+}
+
+// A queued event with the relays it is due to be sent to
+struct Delivery {
+    event_json: String,
+    rows: Vec<(i64, String, u32)>, // (id, relay_url, attempts)
+}
+
+fn retry_delay_secs(attempts: u32) -> i64 {
+    let exponent = attempts.saturating_sub(1).min(20);
+    (BASE_RETRY_DELAY_SECS << exponent).min(MAX_RETRY_DELAY_SECS)
+}
+
+// `kind:pubkey:d` for addressable events such as diary entries, whose newer
+// versions replace older ones on relays
+fn event_coordinate(event: &Event) -> Option<String> {
+    if !event.kind.is_addressable() {
+        return None;
+    }
+    let identifier = event.tags.identifier().unwrap_or_default();
+    Some(format!(
+        "{}:{}:{}",
+        event.kind.as_u16(),
+        event.pubkey,
+        identifier
+    ))
+}
+
+// Whether an earlier version of the entry `event` belongs to is held for
+// publish_pending_entries
+pub fn is_held(conn: &Connection, event: &Event) -> SqlResult<bool> {
+    match event_coordinate(event) {
+        Some(coordinate) => conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM outbox WHERE coordinate = ?1 AND status = ?2)",
+            params![coordinate, STATUS_HELD],
+            |row| row.get(0),
+        ),
+        None => Ok(false),
+    }
+}
+
+// Queues an event for each relay, held until publish_pending_entries when
+// `hold` is set
+pub fn enqueue_event(
+    conn: &mut Connection,
+    event: &Event,
+    relay_urls: &[String],
+    hold: bool,
+) -> SqlResult<()> {
+    let tx = conn.transaction()?;
+    let event_json = serde_json::to_string(event)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    let coordinate = event_coordinate(event);
+    let created_at = event.created_at.as_u64() as i64;
+    let now = Utc::now();
+
+    // Older undelivered versions of the same entry are no longer worth sending,
+    // and neither are events a deletion request withdraws
+    for withdrawn in event.tags.event_ids() {
+        tx.execute(
+            "UPDATE outbox SET status = ?1, next_attempt_at = NULL
+             WHERE event_id = ?2 AND status IN (?3, ?4, ?5)",
+            params![
+                STATUS_SUPERSEDED,
+                withdrawn.to_hex(),
+                STATUS_PENDING,
+                STATUS_FAILED,
+                STATUS_HELD
+            ],
+        )?;
+    }
+    // Only versions at most as new as this one are replaced. Relays keep the
+    // newest version, so an older event queued late is superseded itself.
+    let mut outdated = false;
+    if let Some(coordinate) = &coordinate {
+        tx.execute(
+            "UPDATE outbox SET status = ?1, next_attempt_at = NULL
+             WHERE coordinate = ?2 AND event_id != ?3 AND status IN (?4, ?5, ?6)
+               AND (event_created_at IS NULL OR event_created_at <= ?7)",
+            params![
+                STATUS_SUPERSEDED,
+                coordinate,
+                event.id.to_hex(),
+                STATUS_PENDING,
+                STATUS_FAILED,
+                STATUS_HELD,
+                created_at
+            ],
+        )?;
+        outdated = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM outbox WHERE coordinate = ?1 AND event_created_at > ?2)",
+            params![coordinate, created_at],
+            |row| row.get(0),
+        )?;
+    }
+
+    let (status, next_attempt_at) = if outdated {
+        (STATUS_SUPERSEDED, None)
+    } else if hold {
+        (STATUS_HELD, Some(now.timestamp()))
+    } else {
+        (STATUS_PENDING, Some(now.timestamp()))
+    };
+    for relay_url in relay_urls {
+        tx.execute(
+            "INSERT OR IGNORE INTO outbox
+                (event_id, event_json, kind, coordinate, relay_url, status, attempts,
+                 next_attempt_at, created_at, event_created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, ?7, ?8, ?9)",
+            params![
+                event.id.to_hex(),
+                event_json,
+                event.kind.as_u16(),
+                coordinate,
+                relay_url,
+                status,
+                next_attempt_at,
+                now.to_rfc3339(),
+                created_at
+            ],
+        )?;
+    }
+
+    tx.commit()
+}
+
+// Takes every due row, or only those of one event, and leases them so a
+// concurrent delivery does not pick them up as well
+fn claim_due_deliveries(
+    conn: &mut Connection,
+    event_id: Option<&str>,
+) -> SqlResult<BTreeMap<String, Delivery>> {
+    let tx = conn.transaction()?;
+    let now = Utc::now().timestamp();
+
+    let mut deliveries: BTreeMap<String, Delivery> = BTreeMap::new();
+    {
+        let mut stmt = tx.prepare(
+            "SELECT id, event_id, event_json, relay_url, attempts FROM outbox
+             WHERE status = ?1 AND next_attempt_at <= ?2 AND (?3 IS NULL OR event_id = ?3)
+               AND (leased_until IS NULL OR leased_until <= ?2)
+             ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![STATUS_PENDING, now, event_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, u32>(4)?,
+            ))
+        })?;
+        for row in rows {
+            let (id, event_id, event_json, relay_url, attempts) = row?;
+            deliveries
+                .entry(event_id)
+                .or_insert_with(|| Delivery {
+                    event_json,
+                    rows: Vec::new(),
+                })
+                .rows
+                .push((id, relay_url, attempts));
+        }
+    }
+
+    for delivery in deliveries.values() {
+        for (id, _, _) in &delivery.rows {
+            tx.execute(
+                "UPDATE outbox SET leased_until = ?1 WHERE id = ?2",
+                params![now + DELIVERY_LEASE_SECS, id],
+            )?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(deliveries)
+}
+
+fn record_attempt(
+    conn: &Connection,
+    id: i64,
+    attempts: u32,
+    result: &Result<(), String>,
+) -> SqlResult<()> {
+    let now = Utc::now();
+    match result {
+        Ok(()) => conn.execute(
+            "UPDATE outbox
+             SET status = ?1, attempts = ?2, last_error = NULL, next_attempt_at = NULL, sent_at = ?3,
+                 leased_until = NULL
+             WHERE id = ?4",
+            params![STATUS_SENT, attempts, now.to_rfc3339(), id],
+        )?,
+        Err(e) if attempts >= MAX_ATTEMPTS => conn.execute(
+            "UPDATE outbox
+             SET status = ?1, attempts = ?2, last_error = ?3, next_attempt_at = NULL,
+                 leased_until = NULL
+             WHERE id = ?4",
+            params![STATUS_FAILED, attempts, e, id],
+        )?,
+        Err(e) => conn.execute(
+            "UPDATE outbox
+             SET attempts = ?1, last_error = ?2, next_attempt_at = ?3, leased_until = NULL
+             WHERE id = ?4",
+            params![
+                attempts,
+                e,
+                now.timestamp() + retry_delay_secs(attempts),
+                id
+            ],
+        )?,
+    };
+    Ok(())
+}
+
+// Keeps the latest answer of a relay, replacing the one from an earlier attempt
+fn record_receipt(
+    conn: &Connection,
+    event_id: &str,
+    relay_url: &str,
+    result: &Result<(), String>,
+) -> SqlResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO publish_receipts (event_id, relay_url, accepted, message, received_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            event_id,
+            relay_url,
+            result.is_ok(),
+            result.as_ref().err(),
+            Utc::now().to_rfc3339()
+        ],
+    )?;
+    Ok(())
+}
+
+// Sends one event to the given relays and returns the result for each relay
+async fn send_to_relays(
+    store: &DiaryStore,
+    event: &Event,
+    relay_urls: &[String],
+) -> Vec<Result<(), String>> {
+    let connections = crate::nostr_client::connect_relays(store, relay_urls).await;
+    let results = match connections.connected() {
+        Ok(relays) => connections
+            .client
+            .send_event_to(relays, event)
+            .await
+            .map_err(|e| format!("Failed to send event: {}", e)),
+        Err(e) => Err(e),
+    };
+
+    connections
+        .results
+        .iter()
+        .map(|connection| {
+            let relay = connection.clone()?;
+            let output = results.as_ref().map_err(|e| e.clone())?;
+            if output.success.contains(&relay) {
+                Ok(())
+            } else {
+                Err(output
+                    .failed
+                    .get(&relay)
+                    .cloned()
+                    .unwrap_or_else(|| "Relay did not accept the event".to_string()))
+            }
+        })
+        .collect()
+}
+
+// Attempts every due delivery, or only those of one event. Returns the
+// number of relays that accepted an event.
+pub async fn deliver_due(store: &DiaryStore, event_id: Option<String>) -> Result<usize, String> {
+    let deliveries = store
+        .db
+        .call(move |conn| {
+            claim_due_deliveries(conn, event_id.as_deref())
+                .map_err(|e| format!("Failed to load outbox: {}", e))
+        })
+        .await?;
+
+    let mut sent = 0;
+    for (event_id, delivery) in deliveries {
+        let relay_urls: Vec<String> = delivery
+            .rows
+            .iter()
+            .map(|(_, url, _)| url.clone())
+            .collect();
+        let results = match serde_json::from_str::<Event>(&delivery.event_json) {
+            Ok(event) => send_to_relays(store, &event, &relay_urls).await,
+            Err(e) => vec![Err(format!("Failed to parse queued event: {}", e)); relay_urls.len()],
+        };
+
+        for ((_, relay_url, _), result) in delivery.rows.iter().zip(&results) {
+            match result {
+                Ok(()) => sent += 1,
+                Err(e) => println!("Failed to publish {} to {}: {}", event_id, relay_url, e),
+            }
+        }
+
+        let attempts: Vec<(i64, String, u32, Result<(), String>)> = delivery
+            .rows
+            .into_iter()
+            .zip(results)
+            .map(|((id, relay_url, attempts), result)| (id, relay_url, attempts + 1, result))
+            .collect();
+        store
+            .db
+            .call(move |conn| {
+                for (id, relay_url, attempts, result) in &attempts {
+                    record_attempt(conn, *id, *attempts, result)
+                        .and_then(|_| record_receipt(conn, &event_id, relay_url, result))
+                        .map_err(|e| format!("Failed to update outbox: {}", e))?;
+                }
+                Ok(())
+            })
+            .await?;
+    }
+
+    Ok(sent)
+}
+
+fn remove_old_sent(conn: &Connection) -> SqlResult<usize> {
+    let cutoff = Utc::now() - chrono::Duration::days(SENT_RETENTION_DAYS);
+    conn.execute(
+        "DELETE FROM outbox WHERE status IN (?1, ?2) AND created_at < ?3",
+        params![STATUS_SENT, STATUS_SUPERSEDED, cutoff.to_rfc3339()],
+    )
+}
+
+fn is_offline(store: &DiaryStore) -> bool {
+    store.config.lock().unwrap().offline_mode
+}
+
+// Queues an event for every write relay and, unless it is held or the
+// app is offline, makes a first attempt right away. Failed relays are
+// retried by the outbox worker. Fails when no relay is used for writing.
+pub async fn publish_event(event: &Event, hold: bool, store: &DiaryStore) -> Result<(), String> {
+    let relay_urls = crate::relay_list::write_relay_urls(store);
+    if relay_urls.is_empty() {
+        return Err("No write relays configured, mark a relay for writing".to_string());
+    }
+    publish_event_to(event, relay_urls, hold, store).await
+}
+
+// Like publish_event, for events that belong on other relays than our write
+// relays
+pub async fn publish_event_to(
+    event: &Event,
+    relay_urls: Vec<String>,
+    hold: bool,
+    store: &DiaryStore,
+) -> Result<(), String> {
+    let queued = event.clone();
+    store
+        .db
+        .call(move |conn| {
+            enqueue_event(conn, &queued, &relay_urls, hold)
+                .map_err(|e| format!("Failed to queue event: {}", e))
+        })
+        .await?;
+
+    if hold || is_offline(store) {
+        println!("Queued event {} for later publishing", event.id);
+        return Ok(());
+    }
+    deliver_due(store, Some(event.id.to_hex())).await?;
+    Ok(())
+}
+
+// Retries due deliveries for the lifetime of the app
+pub fn start_outbox_worker(store: Arc<DiaryStore>) {
+    thread::spawn(move || loop {
+        thread::sleep(WORKER_INTERVAL);
+        if !crate::vault::is_unlocked() || is_offline(&store) {
+            continue;
+        }
+
+        // The relay client needs the Tokio runtime that Tauri runs on
+        match tauri::async_runtime::block_on(deliver_due(&store, None)) {
+            Ok(0) => {}
+            Ok(sent) => println!("Outbox delivered {} queued events", sent),
+            Err(e) => println!("Outbox delivery failed: {}", e),
+        }
+
+        let cleanup = store.db.call(|conn| {
+            remove_old_sent(conn).map_err(|e| format!("Failed to clean up outbox: {}", e))
+        });
+        if let Err(e) = futures::executor::block_on(cleanup) {
+            println!("{}", e);
+        }
+    });
+}
+
+fn parse_timestamp(value: Option<String>) -> Option<DateTime<Utc>> {
+    value
+        .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+fn load_outbox(conn: &Connection, status: Option<&str>) -> SqlResult<Vec<OutboxItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, event_id, kind, relay_url, status, attempts, last_error,
+                next_attempt_at, created_at, sent_at
+         FROM outbox WHERE ?1 IS NULL OR status = ?1
+         ORDER BY id DESC",
+    )?;
+    let items_iter = stmt.query_map(params![status], |row| {
+        Ok(OutboxItem {
+            id: row.get(0)?,
+            event_id: row.get(1)?,
+            kind: row.get(2)?,
+            relay_url: row.get(3)?,
+            status: row.get(4)?,
+            attempts: row.get(5)?,
+            last_error: row.get(6)?,
+            next_attempt_at: row
+                .get::<_, Option<i64>>(7)?
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            created_at: parse_timestamp(row.get(8)?).unwrap_or_else(Utc::now),
+            sent_at: parse_timestamp(row.get(9)?),
+        })
+    })?;
+
+    let mut items = Vec::new();
+    for item in items_iter {
+        items.push(item?);
+    }
+    Ok(items)
+}
+
+// Queued deliveries, newest first, optionally only those with one status
+#[tauri::command]
+pub async fn list_outbox(
+    store: State<'_, Arc<DiaryStore>>,
+    status: Option<String>,
+) -> Result<Vec<OutboxItem>, String> {
+    store
+        .db
+        .call(move |conn| {
+            load_outbox(conn, status.as_deref())
+                .map_err(|e| format!("Failed to load outbox: {}", e))
+        })
+        .await
+}
+
+// Makes pending and failed deliveries due now, all of them or the one with
+// `id`. Rows leased by a delivery in progress are left to it.
+fn reset_for_retry(conn: &Connection, id: Option<i64>) -> SqlResult<usize> {
+    let now = Utc::now().timestamp();
+    conn.execute(
+        "UPDATE outbox SET status = ?1, attempts = 0, next_attempt_at = ?2
+         WHERE status IN (?1, ?3) AND (?4 IS NULL OR id = ?4)
+           AND (leased_until IS NULL OR leased_until <= ?2)",
+        params![STATUS_PENDING, now, STATUS_FAILED, id],
+    )
+}
+
+// Makes pending and failed deliveries due now and attempts them. Returns the
+// number of relays that accepted.
+#[tauri::command]
+pub async fn retry_outbox(
+    store: State<'_, Arc<DiaryStore>>,
+    id: Option<i64>,
+) -> Result<usize, String> {
+    store
+        .db
+        .call(move |conn| {
+            reset_for_retry(conn, id).map_err(|e| format!("Failed to reset outbox: {}", e))
+        })
+        .await?;
+
+    deliver_due(&store, None).await
+}
+
+// Publishes every entry saved to publish later, and whatever else is due.
+// This is an explicit request, so it also sends while in offline mode.
+// Returns the number of relays that accepted.
+#[tauri::command]
+pub async fn publish_pending_entries(store: State<'_, Arc<DiaryStore>>) -> Result<usize, String> {
+    let released = store
+        .db
+        .call(move |conn| {
+            conn.execute(
+                "UPDATE outbox SET status = ?1, next_attempt_at = ?2 WHERE status = ?3",
+                params![STATUS_PENDING, Utc::now().timestamp(), STATUS_HELD],
+            )
+            .map_err(|e| format!("Failed to release held events: {}", e))
+        })
+        .await?;
+    println!("Publishing {} held deliveries", released);
+
+    deliver_due(&store, None).await
+}
+
+// Receipts of an event merged with its outbox rows, so relays that were
+// queued but have not answered yet are listed too
+fn load_publish_status(conn: &Connection, event_id: &str) -> SqlResult<PublishStatus> {
+    let mut relays: BTreeMap<String, RelayReceipt> = BTreeMap::new();
+    let mut held = false;
+
+    let mut stmt = conn.prepare(
+        "SELECT relay_url, accepted, message, received_at FROM publish_receipts
+         WHERE event_id = ?1",
+    )?;
+    let receipts = stmt.query_map(params![event_id], |row| {
+        Ok(RelayReceipt {
+            relay_url: row.get(0)?,
+            accepted: row.get(1)?,
+            message: row.get(2)?,
+            received_at: parse_timestamp(row.get(3)?),
+            retrying: false,
+        })
+    })?;
+    for receipt in receipts {
+        let receipt = receipt?;
+        relays.insert(receipt.relay_url.clone(), receipt);
+    }
+
+    let mut stmt = conn.prepare("SELECT relay_url, status FROM outbox WHERE event_id = ?1")?;
+    let rows = stmt.query_map(params![event_id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    for row in rows {
+        let (relay_url, status) = row?;
+        let receipt = relays
+            .entry(relay_url.clone())
+            .or_insert_with(|| RelayReceipt {
+                relay_url,
+                accepted: false,
+                message: None,
+                received_at: None,
+                retrying: false,
+            });
+        receipt.retrying = status == STATUS_PENDING;
+        held |= status == STATUS_HELD;
+    }
+
+    let relays: Vec<RelayReceipt> = relays.into_values().collect();
+    Ok(PublishStatus {
+        event_id: Some(event_id.to_string()),
+        accepted: relays.iter().filter(|r| r.accepted).count(),
+        total: relays.len(),
+        held,
+        private: false,
+        relays,
+    })
+}
+
+// Which relays accepted the current event of the entry of a day. Entries
+// saved before receipts were recorded report no relays.
+#[tauri::command]
+pub async fn get_publish_status(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+) -> Result<PublishStatus, String> {
+    store
+        .db
+        .call(move |conn| {
+            let entry = crate::revision_service::find_entry_for_day(conn, &day)
+                .map_err(|e| format!("Failed to load entry: {}", e))?
+                .ok_or_else(|| format!("No entry exists for day: {}", day))?;
+            let status = match &entry.nostr_id {
+                Some(event_id) => load_publish_status(conn, event_id)
+                    .map_err(|e| format!("Failed to load publish status: {}", e))?,
+                None => PublishStatus {
+                    event_id: None,
+                    accepted: 0,
+                    total: 0,
+                    held: false,
+                    private: false,
+                    relays: Vec::new(),
+                },
+            };
+            Ok(PublishStatus {
+                private: entry.private,
+                ..status
+            })
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Keys, Kind, Tag, Timestamp};
+
+    fn test_connection() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        conn
+    }
+
+    fn entry_event(keys: &Keys, day: &str, created_at: u64) -> Event {
+        EventBuilder::new(Kind::Custom(30027), "content")
+            .tag(Tag::identifier(day))
+            .custom_created_at(Timestamp::from(created_at))
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    fn status_of(conn: &Connection, event: &Event) -> String {
+        conn.query_row(
+            "SELECT status FROM outbox WHERE event_id = ?1",
+            params![event.id.to_hex()],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    fn relays() -> Vec<String> {
+        vec!["wss://relay.example.com".to_string()]
+    }
+
+    #[test]
+    fn test_retry_delay_grows_to_a_limit() {
+        assert_eq!(retry_delay_secs(1), BASE_RETRY_DELAY_SECS);
+        assert_eq!(retry_delay_secs(2), BASE_RETRY_DELAY_SECS * 2);
+        assert_eq!(retry_delay_secs(4), BASE_RETRY_DELAY_SECS * 8);
+        assert_eq!(retry_delay_secs(MAX_ATTEMPTS), MAX_RETRY_DELAY_SECS);
+        assert_eq!(retry_delay_secs(u32::MAX), MAX_RETRY_DELAY_SECS);
+    }
+
+    #[test]
+    fn test_failed_attempts_back_off_then_give_up() {
+        let mut conn = test_connection();
+        let event = entry_event(&Keys::generate(), "2024-03-01", 100);
+        enqueue_event(&mut conn, &event, &relays(), false).unwrap();
+        let id: i64 = conn
+            .query_row("SELECT id FROM outbox", [], |row| row.get(0))
+            .unwrap();
+
+        let before = Utc::now().timestamp();
+        record_attempt(&conn, id, 3, &Err("offline".to_string())).unwrap();
+        let (status, next_attempt_at): (String, i64) = conn
+            .query_row(
+                "SELECT status, next_attempt_at FROM outbox WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(status, STATUS_PENDING);
+        assert!(next_attempt_at >= before + retry_delay_secs(3));
+
+        record_attempt(&conn, id, MAX_ATTEMPTS, &Err("offline".to_string())).unwrap();
+        assert_eq!(status_of(&conn, &event), STATUS_FAILED);
+    }
+
+    #[test]
+    fn test_leased_rows_are_not_claimed_or_reset() {
+        let mut conn = test_connection();
+        let event = entry_event(&Keys::generate(), "2024-03-01", 100);
+        enqueue_event(&mut conn, &event, &relays(), false).unwrap();
+
+        assert_eq!(claim_due_deliveries(&mut conn, None).unwrap().len(), 1);
+        assert!(claim_due_deliveries(&mut conn, None).unwrap().is_empty());
+        assert_eq!(reset_for_retry(&conn, None).unwrap(), 0);
+
+        // Once the lease runs out the row is due again
+        conn.execute(
+            "UPDATE outbox SET leased_until = ?1",
+            params![Utc::now().timestamp() - 1],
+        )
+        .unwrap();
+        assert_eq!(reset_for_retry(&conn, None).unwrap(), 1);
+        assert_eq!(claim_due_deliveries(&mut conn, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_newer_versions_supersede_older_ones() {
+        let mut conn = test_connection();
+        let keys = Keys::generate();
+        let first = entry_event(&keys, "2024-03-01", 100);
+        let second = entry_event(&keys, "2024-03-01", 200);
+        let other_day = entry_event(&keys, "2024-03-02", 50);
+
+        enqueue_event(&mut conn, &first, &relays(), false).unwrap();
+        enqueue_event(&mut conn, &other_day, &relays(), false).unwrap();
+        enqueue_event(&mut conn, &second, &relays(), false).unwrap();
+
+        assert_eq!(status_of(&conn, &first), STATUS_SUPERSEDED);
+        assert_eq!(status_of(&conn, &second), STATUS_PENDING);
+        assert_eq!(status_of(&conn, &other_day), STATUS_PENDING);
+    }
+
+    #[test]
+    fn test_older_version_queued_late_does_not_supersede() {
+        let mut conn = test_connection();
+        let keys = Keys::generate();
+        let newer = entry_event(&keys, "2024-03-01", 200);
+        let older = entry_event(&keys, "2024-03-01", 100);
+
+        enqueue_event(&mut conn, &newer, &relays(), true).unwrap();
+        enqueue_event(&mut conn, &older, &relays(), false).unwrap();
+
+        assert_eq!(status_of(&conn, &newer), STATUS_HELD);
+        assert_eq!(status_of(&conn, &older), STATUS_SUPERSEDED);
+        assert!(claim_due_deliveries(&mut conn, None).unwrap().is_empty());
+    }
+}
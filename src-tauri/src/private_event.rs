@@ -0,0 +1,157 @@
+// Diary events encrypted to our own key
+//
+// A private event keeps only its `d` tag in the clear, so relays can still
+// replace older versions, plus an `encrypted` marker. The content and every
+// other tag (weather, `t`, imeta, utc_offset) are sealed together with NIP-44
+// to our own pubkey, which makes relays an encrypted backup of the diary.
+use nostr_sdk::nips::nip44::{self, Version};
+use nostr_sdk::{Event, Keys, Tag, TagKind, Tags};
+use serde::{Deserialize, Serialize};
+
+const ENCRYPTED_TAG: &str = "encrypted";
+const ENCRYPTION_SCHEME: &str = "nip44";
+// NIP-44 v2 allows up to 65535 bytes of plaintext, but the nostr-sdk
+// implementation refuses anything over 65408
+const MAX_SEALED_BYTES: usize = 65536 - 128;
+
+// What the ciphertext of a private event decrypts to
+#[derive(Serialize, Deserialize)]
+struct SealedPayload {
+    content: String,
+    tags: Vec<Vec<String>>,
+}
+
+pub fn is_private(event: &Event) -> bool {
+    event.tags.find(TagKind::custom(ENCRYPTED_TAG)).is_some()
+}
+
+// Encrypts the content and tags to our own key. Returns the ciphertext to
+// sign as the event content and the marker tag to add next to the `d` tag.
+pub fn seal(keys: &Keys, content: &str, tags: Vec<Tag>) -> Result<(String, Tag), String> {
+    let payload = SealedPayload {
+        content: content.to_string(),
+        tags: tags.into_iter().map(|tag| tag.to_vec()).collect(),
+    };
+    let plaintext = serde_json::to_string(&payload)
+        .map_err(|e| format!("Failed to serialize private event: {}", e))?;
+    // The limit covers the tags as well as the content
+    if plaintext.len() > MAX_SEALED_BYTES {
+        return Err(format!(
+            "This entry is too long to save as private ({} bytes with its tags, the limit is {}). Shorten it or save it as public.",
+            plaintext.len(),
+            MAX_SEALED_BYTES
+        ));
+    }
+
+    let ciphertext = nip44::encrypt(
+        keys.secret_key(),
+        &keys.public_key(),
+        plaintext,
+        Version::default(),
+    )
+    .map_err(|e| format!("Failed to encrypt private event: {}", e))?;
+
+    let marker = Tag::parse(vec![
+        ENCRYPTED_TAG.to_string(),
+        ENCRYPTION_SCHEME.to_string(),
+    ])
+    .map_err(|e| format!("Failed to create encrypted tag: {}", e))?;
+    Ok((ciphertext, marker))
+}
+
+// A readable copy of an event: for private events the content and tags are
+// the decrypted ones next to the public `d` tag, public events are returned
+// as they are. Verify the signature on the original, the copy no longer
+// matches it.
+pub fn open_event(keys: &Keys, event: &Event) -> Result<Event, String> {
+    if !is_private(event) {
+        return Ok(event.clone());
+    }
+
+    let plaintext = nip44::decrypt(keys.secret_key(), &event.pubkey, &event.content)
+        .map_err(|e| format!("Failed to decrypt private event: {}", e))?;
+    let payload: SealedPayload = serde_json::from_str(&plaintext)
+        .map_err(|e| format!("Failed to parse private event: {}", e))?;
+
+    let mut tags: Vec<Tag> = event
+        .tags
+        .iter()
+        .filter(|tag| tag.kind() == TagKind::d())
+        .cloned()
+        .collect();
+    for tag in payload.tags {
+        tags.push(Tag::parse(tag).map_err(|e| format!("Failed to parse private tag: {}", e))?);
+    }
+
+    let mut opened = event.clone();
+    opened.content = payload.content;
+    opened.tags = Tags::from_list(tags);
+    Ok(opened)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::{EventBuilder, Kind};
+
+    fn private_event(keys: &Keys, content: &str, tags: Vec<Tag>) -> Result<Event, String> {
+        let (ciphertext, marker) = seal(keys, content, tags)?;
+        Ok(EventBuilder::new(Kind::from(30027), ciphertext)
+            .tags(vec![Tag::identifier("2024-03-01"), marker])
+            .sign_with_keys(keys)
+            .unwrap())
+    }
+
+    #[test]
+    fn test_sealed_event_opens_to_the_original() {
+        let keys = Keys::generate();
+        let weather = Tag::parse(vec!["weather".to_string(), "晴".to_string()]).unwrap();
+        let topic = Tag::hashtag("读书");
+        let event = private_event(
+            &keys,
+            "今天读了《呐喊》",
+            vec![weather.clone(), topic.clone()],
+        )
+        .unwrap();
+
+        assert!(is_private(&event));
+        assert!(!event.content.contains("呐喊"));
+        assert!(event.tags.find(TagKind::custom("weather")).is_none());
+
+        let opened = open_event(&keys, &event).unwrap();
+        assert_eq!(opened.content, "今天读了《呐喊》");
+        let tags: Vec<Tag> = opened.tags.iter().cloned().collect();
+        assert_eq!(tags, vec![Tag::identifier("2024-03-01"), weather, topic]);
+    }
+
+    #[test]
+    fn test_other_keys_cannot_open() {
+        let event = private_event(&Keys::generate(), "秘密", Vec::new()).unwrap();
+        assert!(open_event(&Keys::generate(), &event).is_err());
+    }
+
+    #[test]
+    fn test_public_events_are_returned_as_they_are() {
+        let keys = Keys::generate();
+        let event = EventBuilder::new(Kind::from(30027), "公开")
+            .tag(Tag::identifier("2024-03-01"))
+            .sign_with_keys(&keys)
+            .unwrap();
+        assert!(!is_private(&event));
+        assert_eq!(open_event(&keys, &event).unwrap(), event);
+    }
+
+    #[test]
+    fn test_sealed_payload_size_limit() {
+        let keys = Keys::generate();
+        // {"content":"…","tags":[]} adds 24 bytes around the content
+        let fits = "a".repeat(MAX_SEALED_BYTES - 24);
+        assert!(seal(&keys, &fits, Vec::new()).is_ok());
+
+        let error = seal(&keys, &format!("{}a", fits), Vec::new()).unwrap_err();
+        assert!(error.contains("save it as public"));
+        // Tags count towards the limit too
+        let tag = Tag::hashtag("t");
+        assert!(seal(&keys, &fits, vec![tag]).is_err());
+    }
+}
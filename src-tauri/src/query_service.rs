@@ -0,0 +1,282 @@
+use chrono::{Datelike, NaiveDate};
+use rusqlite::{params_from_iter, types::Value, Connection};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tauri::State;
+
+use crate::{DiaryEntry, DiaryStore};
+
+const DEFAULT_PAGE_SIZE: u32 = 20;
+const MAX_PAGE_SIZE: u32 = 200;
+
+// An entry counts as published once a relay has accepted its latest version.
+// Held, queued and failed versions only have an outbox row.
+const PUBLISHED_CONDITION: &str = "EXISTS (SELECT 1 FROM publish_receipts r
+                WHERE r.event_id = diary_entries.nostr_id AND r.accepted = 1)";
+
+// Filters and paging options for query_diary_entries. Every field is optional.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct EntryQuery {
+    pub cursor: Option<String>, // next_cursor of the previous page
+    pub limit: Option<u32>,
+    pub start_day: Option<String>, // YYYY-MM-DD, inclusive
+    pub end_day: Option<String>,   // YYYY-MM-DD, inclusive
+    pub weather: Option<String>,   // substring match, e.g. "雨"
+    pub published: Option<bool>,
+    pub shared_with: Option<String>, // hex pubkey of a gift wrap recipient
+    pub tag: Option<String>,         // with or without the leading #
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct EntryPage {
+    pub entries: Vec<DiaryEntry>,
+    pub total: u64, // number of entries matching the filters, across all pages
+    pub next_cursor: Option<String>,
+}
+
+fn parse_query_day(day: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .map_err(|e| format!("Invalid day '{}', expected YYYY-MM-DD: {}", day, e))
+}
+
+fn date_values(date: NaiveDate) -> [Value; 3] {
+    [
+        Value::Integer(date.year() as i64),
+        Value::Integer(date.month() as i64),
+        Value::Integer(date.day() as i64),
+    ]
+}
+
+// Builds the WHERE clause shared by the count and the page query
+fn filter_conditions(query: &EntryQuery) -> Result<(Vec<String>, Vec<Value>), String> {
+    let mut conditions = vec!["deleted_at IS NULL".to_string()];
+    let mut values = Vec::new();
+
+    if let Some(start_day) = &query.start_day {
+        conditions.push("(year, month, day_of_month) >= (?, ?, ?)".to_string());
+        values.extend(date_values(parse_query_day(start_day)?));
+    }
+    if let Some(end_day) = &query.end_day {
+        conditions.push("(year, month, day_of_month) <= (?, ?, ?)".to_string());
+        values.extend(date_values(parse_query_day(end_day)?));
+    }
+    if let Some(weather) = query.weather.as_ref().filter(|w| !w.is_empty()) {
+        conditions.push("instr(weather, ?) > 0".to_string());
+        values.push(Value::Text(weather.clone()));
+    }
+    match query.published {
+        Some(true) => conditions.push(format!("({})", PUBLISHED_CONDITION)),
+        Some(false) => conditions.push(format!("NOT ({})", PUBLISHED_CONDITION)),
+        None => {}
+    }
+    if let Some(recipient) = query.shared_with.as_ref().filter(|r| !r.is_empty()) {
+        conditions.push(
+            "EXISTS (SELECT 1 FROM gift_wrap_shares s
+                     WHERE s.entry_id = diary_entries.id AND s.recipient_pubkey = ?)"
+                .to_string(),
+        );
+        // Shares are recorded by hex key, the filter may be an npub
+        let recipient = crate::nostr_keys::parse_pubkey(recipient)
+            .map(|key| key.hex)
+            .unwrap_or_else(|_| recipient.to_lowercase());
+        values.push(Value::Text(recipient));
+    }
+    if let Some(tag) = query
+        .tag
+        .as_deref()
+        .and_then(crate::tag_service::normalize_tag)
+    {
+        conditions.push(
+            "EXISTS (SELECT 1 FROM entry_tags et JOIN tags t ON t.id = et.tag_id
+                     WHERE et.entry_id = diary_entries.id AND t.name = ?)"
+                .to_string(),
+        );
+        values.push(Value::Text(tag));
+    }
+
+    Ok((conditions, values))
+}
+
+// Returns one page of entries, newest day first. The cursor is the day of the
+// last entry on the previous page; since there is only one entry per day it
+// identifies a unique position in the ordering.
+pub fn query_entries(conn: &Connection, query: &EntryQuery) -> Result<EntryPage, String> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let (conditions, values) = filter_conditions(query)?;
+
+    let total: u64 = conn
+        .query_row(
+            &format!(
+                "SELECT COUNT(*) FROM diary_entries WHERE {}",
+                conditions.join(" AND ")
+            ),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Failed to count entries: {}", e))?;
+
+    let mut page_conditions = conditions;
+    let mut page_values = values;
+    if let Some(cursor) = &query.cursor {
+        let cursor_day =
+            parse_query_day(cursor).map_err(|_| format!("Invalid cursor: {}", cursor))?;
+        page_conditions.push("(year, month, day_of_month) < (?, ?, ?)".to_string());
+        page_values.extend(date_values(cursor_day));
+    }
+    // Fetch one extra row to know whether another page follows
+    page_values.push(Value::Integer(limit as i64 + 1));
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM diary_entries
+             WHERE {}
+             ORDER BY year DESC, month DESC, day_of_month DESC
+             LIMIT ?",
+            crate::ENTRY_COLUMNS,
+            page_conditions.join(" AND ")
+        ))
+        .map_err(|e| format!("Failed to prepare entry query: {}", e))?;
+
+    let entries_iter = stmt
+        .query_map(params_from_iter(page_values), crate::entry_from_row)
+        .map_err(|e| format!("Failed to query entries: {}", e))?;
+
+    let mut entries = Vec::new();
+    for entry in entries_iter {
+        entries.push(entry.map_err(|e| format!("Failed to read entry: {}", e))?);
+    }
+
+    let next_cursor = if entries.len() > limit as usize {
+        entries.truncate(limit as usize);
+        entries.last().map(|entry| entry.day.clone())
+    } else {
+        None
+    };
+
+    Ok(EntryPage {
+        entries,
+        total,
+        next_cursor,
+    })
+}
+
+#[tauri::command]
+pub async fn query_diary_entries(
+    store: State<'_, Arc<DiaryStore>>,
+    query: Option<EntryQuery>,
+) -> Result<EntryPage, String> {
+    store
+        .db
+        .call(move |conn| query_entries(conn, &query.unwrap_or_default()))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO diary_entries (id, content, weather, created_at, nostr_id, day)
+             VALUES ('a', '#读书 一天', '晴', 'now', 'event-a', '2024-01-31'),
+                    ('b', '#散步', '小雨', 'now', 'event-b', '2024-02-01'),
+                    ('c', '#读书', '大雨', 'now', 'event-c', '2024-02-09'),
+                    ('d', '#读书', '晴', 'now', 'event-d', '2024-02-10'),
+                    ('e', '#读书', '晴', 'now', 'event-e', '2024-03-01');
+             INSERT INTO outbox (event_id, event_json, kind, relay_url, status, created_at)
+             VALUES ('event-a', '{}', 30027, 'wss://r', 'sent', 'now'),
+                    ('event-b', '{}', 30027, 'wss://r', 'held', 'now'),
+                    ('event-c', '{}', 30027, 'wss://r', 'failed', 'now'),
+                    ('event-d', '{}', 30027, 'wss://r', 'pending', 'now');
+             INSERT INTO publish_receipts (event_id, relay_url, accepted, received_at)
+             VALUES ('event-a', 'wss://r', 1, 'now'), ('event-c', 'wss://r', 0, 'now');",
+        )
+        .unwrap();
+        crate::tag_service::rebuild_tags(&conn).unwrap();
+        conn
+    }
+
+    fn days(page: &EntryPage) -> Vec<&str> {
+        page.entries.iter().map(|e| e.day.as_str()).collect()
+    }
+
+    #[test]
+    fn test_cursor_pages_through_all_entries() {
+        let conn = setup();
+        let mut query = EntryQuery {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let mut pages = Vec::new();
+        loop {
+            let page = query_entries(&conn, &query).unwrap();
+            assert_eq!(page.total, 5);
+            pages.push(days(&page).join(","));
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(
+            pages,
+            vec![
+                "2024-03-01,2024-02-10",
+                "2024-02-09,2024-02-01",
+                "2024-01-31"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_only_accepted_entries_are_published() {
+        let conn = setup();
+        let published = EntryQuery {
+            published: Some(true),
+            ..Default::default()
+        };
+        let page = query_entries(&conn, &published).unwrap();
+        assert_eq!(days(&page), vec!["2024-01-31"]);
+
+        let unpublished = EntryQuery {
+            published: Some(false),
+            ..Default::default()
+        };
+        let page = query_entries(&conn, &unpublished).unwrap();
+        assert_eq!(
+            days(&page),
+            vec!["2024-03-01", "2024-02-10", "2024-02-09", "2024-02-01"]
+        );
+    }
+
+    #[test]
+    fn test_tag_and_date_filters() {
+        let conn = setup();
+        let query = EntryQuery {
+            start_day: Some("2024-02-01".to_string()),
+            end_day: Some("2024-02-10".to_string()),
+            tag: Some("#读书".to_string()),
+            ..Default::default()
+        };
+        let page = query_entries(&conn, &query).unwrap();
+        assert_eq!(days(&page), vec!["2024-02-10", "2024-02-09"]);
+        assert_eq!(page.total, 2);
+
+        let query = EntryQuery {
+            weather: Some("雨".to_string()),
+            ..query
+        };
+        let page = query_entries(&conn, &query).unwrap();
+        assert_eq!(days(&page), vec!["2024-02-09"]);
+
+        let query = EntryQuery {
+            start_day: Some("2024/02/01".to_string()),
+            ..Default::default()
+        };
+        assert!(query_entries(&conn, &query).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+// Reachability of the configured relays and their NIP-11 information
+//
+// check_relays connects to every relay to measure latency and fetches its
+// information document. The limits in those documents are kept in
+// DiaryStore so check_entry_limits can warn about an entry that a write
+// relay would reject before it is saved and published.
+use futures::future::join_all;
+use nostr_sdk::nips::nip11::RelayInformationDocument;
+use nostr_sdk::{ClientMessage, Event, JsonUtil, Keys, RelayUrl, Tag};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::State;
+
+use crate::DiaryStore;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const INFORMATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RelayHealth {
+    pub url: String,
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+    pub name: Option<String>,
+    pub software: Option<String>,
+    pub supported_nips: Vec<u16>,
+    pub max_message_length: Option<i32>,
+    pub max_content_length: Option<i32>,
+    pub auth_required: bool,
+    pub payment_required: bool,
+    pub information_error: Option<String>,
+}
+
+// A limit of a write relay that an entry would exceed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RelayLimitWarning {
+    pub relay_url: String,
+    pub limit: String, // "max_message_length" or "max_content_length"
+    pub allowed: i32,
+    pub actual: usize,
+}
+
+// Fetches the NIP-11 document over HTTP(S) from the relay's address
+pub async fn fetch_information(url: &str) -> Result<RelayInformationDocument, String> {
+    let http_url = if let Some(rest) = url.strip_prefix("wss://") {
+        format!("https://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("ws://") {
+        format!("http://{}", rest)
+    } else {
+        return Err(format!("Invalid relay URL: {}", url));
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(INFORMATION_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    let response = client
+        .get(&http_url)
+        .header("Accept", "application/nostr+json")
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch relay information: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Failed to fetch relay information, status: {}",
+            response.status()
+        ));
+    }
+
+    response
+        .json::<RelayInformationDocument>()
+        .await
+        .map_err(|e| format!("Invalid relay information document: {}", e))
+}
+
+// Time it takes to open a websocket connection to the relay
+async fn measure_latency(url: &str) -> Result<Duration, String> {
+    let relay_url = RelayUrl::parse(url).map_err(|e| format!("Invalid relay URL: {}", e))?;
+    let client = nostr_sdk::Client::default();
+    client
+        .add_relay(relay_url.clone())
+        .await
+        .map_err(|e| format!("Failed to add relay: {}", e))?;
+    let relay = client
+        .relay(relay_url)
+        .await
+        .map_err(|e| format!("Failed to add relay: {}", e))?;
+
+    let started = Instant::now();
+    let connected = relay.try_connect(CONNECT_TIMEOUT).await;
+    let elapsed = started.elapsed();
+    client.disconnect().await;
+
+    connected
+        .map(|_| elapsed)
+        .map_err(|e| format!("Failed to connect: {}", e))
+}
+
+async fn check_relay(url: String) -> (RelayHealth, Option<RelayInformationDocument>) {
+    let (latency, information) = futures::join!(measure_latency(&url), fetch_information(&url));
+
+    let mut health = RelayHealth {
+        url,
+        reachable: latency.is_ok(),
+        latency_ms: latency.as_ref().ok().map(|d| d.as_millis() as u64),
+        error: latency.err(),
+        name: None,
+        software: None,
+        supported_nips: Vec::new(),
+        max_message_length: None,
+        max_content_length: None,
+        auth_required: false,
+        payment_required: false,
+        information_error: None,
+    };
+
+    match information {
+        Ok(document) => {
+            health.name = document.name.clone();
+            health.software = document.software.clone();
+            health.supported_nips = document.supported_nips.clone().unwrap_or_default();
+            if let Some(limitation) = &document.limitation {
+                health.max_message_length = limitation.max_message_length;
+                health.max_content_length = limitation.max_content_length;
+                health.auth_required = limitation.auth_required.unwrap_or(false);
+                health.payment_required = limitation.payment_required.unwrap_or(false);
+            }
+            (health, Some(document))
+        }
+        Err(e) => {
+            health.information_error = Some(e);
+            (health, None)
+        }
+    }
+}
+
+// Checks every configured relay at once and keeps the information
+// documents for check_entry_limits
+#[tauri::command]
+pub async fn check_relays(store: State<'_, Arc<DiaryStore>>) -> Result<Vec<RelayHealth>, String> {
+    let relay_urls = crate::relay_list::all_relay_urls(&store.config.lock().unwrap());
+    let results = join_all(relay_urls.into_iter().map(check_relay)).await;
+
+    let mut report = Vec::new();
+    let mut documents = store.relay_information.lock().unwrap();
+    for (health, document) in results {
+        if let Some(document) = document {
+            documents.insert(health.url.clone(), document);
+        }
+        report.push(health);
+    }
+
+    println!("Checked {} relays", report.len());
+    Ok(report)
+}
+
+// Limits of `documents` that `event` exceeds
+pub fn limit_warnings<'a>(
+    event: &Event,
+    documents: impl IntoIterator<Item = (&'a String, &'a RelayInformationDocument)>,
+) -> Vec<RelayLimitWarning> {
+    // Relays measure the whole message the event is sent in
+    let message_length = ClientMessage::event(event.clone()).as_json().len();
+    let content_length = event.content.chars().count();
+
+    let mut warnings = Vec::new();
+    for (url, document) in documents {
+        let Some(limitation) = &document.limitation else {
+            continue;
+        };
+        let limits = [
+            (
+                "max_message_length",
+                limitation.max_message_length,
+                message_length,
+            ),
+            (
+                "max_content_length",
+                limitation.max_content_length,
+                content_length,
+            ),
+        ];
+        for (limit, allowed, actual) in limits {
+            if let Some(allowed) = allowed.filter(|allowed| *allowed > 0) {
+                if actual > allowed as usize {
+                    warnings.push(RelayLimitWarning {
+                        relay_url: url.clone(),
+                        limit: limit.to_string(),
+                        allowed,
+                        actual,
+                    });
+                }
+            }
+        }
+    }
+    warnings
+}
+
+// Signs a draft of the entry, without saving or publishing it, and compares
+// it with the limits of the write relays. Information documents that were
+// not fetched yet are fetched now, unless the app is offline.
+#[tauri::command]
+pub async fn check_entry_limits(
+    store: State<'_, Arc<DiaryStore>>,
+    content: String,
+    weather: String,
+    day: Option<String>,
+    tags: Option<Vec<String>>,
+    private: Option<bool>,
+) -> Result<Vec<RelayLimitWarning>, String> {
+    let keys: Keys = crate::get_or_create_nostr_keys(&store)?;
+    let (today, utc_offset) = crate::timezone::configured_timezone(&store).today();
+    let mut extra_tags: Vec<Tag> =
+        crate::tag_service::combine_tags(&content, &tags.unwrap_or_default())
+            .iter()
+            .map(Tag::hashtag)
+            .collect();
+    extra_tags.push(crate::timezone::offset_tag(&utc_offset)?);
+    let private = private.unwrap_or_else(|| store.config.lock().unwrap().private_by_default);
+    let draft = crate::sign_diary_event(
+        &keys,
+        &content,
+        &weather,
+        &day.unwrap_or(today),
+        extra_tags,
+        private,
+    )
+    .await?;
+
+    let relay_urls = crate::relay_list::write_relay_urls(&store);
+    let offline = store.config.lock().unwrap().offline_mode;
+    if !offline {
+        let missing: Vec<String> = {
+            let documents = store.relay_information.lock().unwrap();
+            relay_urls
+                .iter()
+                .filter(|url| !documents.contains_key(*url))
+                .cloned()
+                .collect()
+        };
+        let fetched = join_all(missing.iter().map(|url| fetch_information(url))).await;
+        let mut documents = store.relay_information.lock().unwrap();
+        for (url, document) in missing.into_iter().zip(fetched) {
+            match document {
+                Ok(document) => {
+                    documents.insert(url, document);
+                }
+                Err(e) => println!("No relay information for {}: {}", url, e),
+            }
+        }
+    }
+
+    let documents = store.relay_information.lock().unwrap();
+    Ok(limit_warnings(
+        &draft,
+        documents
+            .iter()
+            .filter(|(url, _)| relay_urls.contains(*url)),
+    ))
+}
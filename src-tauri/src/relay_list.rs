@@ -0,0 +1,239 @@
+// Read and write roles of the configured relays, and our NIP-65 relay list
+//
+// Config.relay_roles marks relays used only for reading or only for writing,
+// the same way a kind 10002 `r` tag does; a relay without a role is used for
+// both. Diary events are written to the write relays and read back from them
+// on restore. Gift wraps addressed to us are fetched from the read relays,
+// and gift wraps for someone else go to their read relays.
+use nostr_sdk::nips::nip65::{self, RelayMetadata};
+use nostr_sdk::{Event, EventBuilder, Filter, Kind, PublicKey, RelayUrl};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::State;
+
+use crate::{Config, DiaryStore};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayRole {
+    Read,
+    Write,
+}
+
+// Relays of the config, the defaults when none are configured
+pub fn all_relay_urls(config: &Config) -> Vec<String> {
+    if config.relay_urls.is_empty() {
+        config.default_relay_urls.clone()
+    } else {
+        config.relay_urls.clone()
+    }
+}
+
+// Relays that are not limited to `excluded`. Empty when every relay has
+// the other role: falling back to relays the user did not pick would send
+// the diary somewhere they did not ask for.
+fn relays_without_role(config: &Config, excluded: RelayRole) -> Vec<String> {
+    all_relay_urls(config)
+        .into_iter()
+        .filter(|url| config.relay_roles.get(url) != Some(&excluded))
+        .collect()
+}
+
+pub fn write_relay_urls(store: &DiaryStore) -> Vec<String> {
+    relays_without_role(&store.config.lock().unwrap(), RelayRole::Read)
+}
+
+pub fn read_relay_urls(store: &DiaryStore) -> Vec<String> {
+    relays_without_role(&store.config.lock().unwrap(), RelayRole::Write)
+}
+
+// Every relay we know of, where a relay list is most likely to be found
+fn lookup_relay_urls(store: &DiaryStore) -> Vec<String> {
+    let config = store.config.lock().unwrap();
+    let mut urls = all_relay_urls(&config);
+    for url in &config.default_relay_urls {
+        if !urls.contains(url) {
+            urls.push(url.clone());
+        }
+    }
+    urls
+}
+
+// The newest kind 10002 relay list of `pubkey` found on `relay_urls`
+pub async fn fetch_relay_list(
+    store: &DiaryStore,
+    pubkey: PublicKey,
+    relay_urls: &[String],
+) -> Result<Option<Event>, String> {
+    let connections = crate::nostr_client::connect_relays(store, relay_urls).await;
+    let connected = connections.connected()?;
+
+    let filter = Filter::new().author(pubkey).kind(Kind::RelayList);
+    let events = connections
+        .client
+        .fetch_events_from(connected, filter, FETCH_TIMEOUT)
+        .await
+        .map_err(|e| format!("Failed to fetch relay list: {}", e))?;
+    Ok(events
+        .into_iter()
+        .filter(|event| event.verify().is_ok())
+        .max_by_key(|event| event.created_at))
+}
+
+// Read relays of a relay list, the inbox relays NIP-65 asks others to use
+pub fn inbox_relays(relay_list: &Event) -> Vec<String> {
+    nip65::extract_relay_list(relay_list)
+        .filter(|(_, metadata)| **metadata != Some(RelayMetadata::Write))
+        .map(|(url, _)| url.to_string())
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RelayListEntry {
+    pub url: String,
+    pub read: bool,
+    pub write: bool,
+}
+
+fn relay_list_entries(config: &Config) -> Vec<RelayListEntry> {
+    all_relay_urls(config)
+        .into_iter()
+        .map(|url| {
+            let role = config.relay_roles.get(&url).copied();
+            RelayListEntry {
+                read: role != Some(RelayRole::Write),
+                write: role != Some(RelayRole::Read),
+                url,
+            }
+        })
+        .collect()
+}
+
+// Signs our relays as a kind 10002 event and publishes it to every relay we
+// know of, so others can find where to read our diary and reach us. Returns
+// the event id.
+#[tauri::command]
+pub async fn publish_relay_list(store: State<'_, Arc<DiaryStore>>) -> Result<String, String> {
+    let keys = crate::get_or_create_nostr_keys(&store)?;
+    let entries = relay_list_entries(&store.config.lock().unwrap());
+
+    let mut relays = Vec::new();
+    for entry in &entries {
+        let url = RelayUrl::parse(&entry.url)
+            .map_err(|e| format!("Invalid relay URL {}: {}", entry.url, e))?;
+        let metadata = match (entry.read, entry.write) {
+            (true, false) => Some(RelayMetadata::Read),
+            (false, true) => Some(RelayMetadata::Write),
+            _ => None,
+        };
+        relays.push((url, metadata));
+    }
+
+    let event = EventBuilder::relay_list(relays)
+        .sign(&keys)
+        .await
+        .map_err(|e| format!("Failed to create relay list: {}", e))?;
+
+    crate::outbox::publish_event_to(&event, lookup_relay_urls(&store), false, &store).await?;
+    println!("Published relay list with {} relays", entries.len());
+    Ok(event.id.to_hex())
+}
+
+// Replaces the configured relays and their roles with our newest published
+// relay list, e.g. on a new device. Returns the imported relays.
+#[tauri::command]
+pub async fn import_relay_list(
+    store: State<'_, Arc<DiaryStore>>,
+) -> Result<Vec<RelayListEntry>, String> {
+    let keys = crate::get_or_create_nostr_keys(&store)?;
+    let relay_list = fetch_relay_list(&store, keys.public_key(), &lookup_relay_urls(&store))
+        .await?
+        .ok_or_else(|| "No relay list found for this key".to_string())?;
+
+    let mut relay_urls = Vec::new();
+    let mut relay_roles = BTreeMap::new();
+    for (url, metadata) in nip65::extract_relay_list(&relay_list) {
+        let url = url.to_string();
+        match metadata {
+            Some(RelayMetadata::Read) => relay_roles.insert(url.clone(), RelayRole::Read),
+            Some(RelayMetadata::Write) => relay_roles.insert(url.clone(), RelayRole::Write),
+            None => None,
+        };
+        if !relay_urls.contains(&url) {
+            relay_urls.push(url);
+        }
+    }
+    if relay_urls.is_empty() {
+        return Err("The published relay list is empty".to_string());
+    }
+
+    let mut config = store.config.lock().unwrap();
+    let mut new_config = config.clone();
+    new_config.relay_urls = relay_urls;
+    new_config.relay_roles = relay_roles;
+    crate::save_config(&new_config)?;
+    *config = new_config;
+    crate::nostr_client::spawn_sync_relays(store.inner().clone());
+
+    println!(
+        "Imported relay list with {} relays",
+        config.relay_urls.len()
+    );
+    Ok(relay_list_entries(&config))
+}
+
+#[tauri::command]
+pub fn get_relay_list(store: State<Arc<DiaryStore>>) -> Vec<RelayListEntry> {
+    relay_list_entries(&store.config.lock().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_roles(roles: &[(&str, RelayRole)]) -> Config {
+        Config {
+            relay_urls: vec![
+                "wss://a.example.com".to_string(),
+                "wss://b.example.com".to_string(),
+            ],
+            relay_roles: roles
+                .iter()
+                .map(|(url, role)| (url.to_string(), *role))
+                .collect(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_relays_without_a_role_are_used_both_ways() {
+        let config = config_with_roles(&[("wss://a.example.com", RelayRole::Read)]);
+        assert_eq!(
+            relays_without_role(&config, RelayRole::Read),
+            vec!["wss://b.example.com"]
+        );
+        assert_eq!(
+            relays_without_role(&config, RelayRole::Write),
+            vec!["wss://a.example.com", "wss://b.example.com"]
+        );
+    }
+
+    #[test]
+    fn test_no_fallback_when_every_relay_has_the_other_role() {
+        let config = config_with_roles(&[
+            ("wss://a.example.com", RelayRole::Read),
+            ("wss://b.example.com", RelayRole::Read),
+        ]);
+        assert!(relays_without_role(&config, RelayRole::Read).is_empty());
+
+        let config = config_with_roles(&[
+            ("wss://a.example.com", RelayRole::Write),
+            ("wss://b.example.com", RelayRole::Write),
+        ]);
+        assert!(relays_without_role(&config, RelayRole::Write).is_empty());
+    }
+}
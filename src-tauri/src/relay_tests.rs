@@ -0,0 +1,368 @@
+// End-to-end tests of the Nostr flows against an in-process mock relay, so
+// they run without a network connection
+use nostr_relay_builder::MockRelay;
+use nostr_sdk::nips::nip19::Nip19Profile;
+use nostr_sdk::nips::nip21::ToNostrUri;
+use nostr_sdk::nips::nip65::RelayMetadata;
+use nostr_sdk::{Client, Event, EventBuilder, Filter, Keys, Kind, RelayUrl, Tag};
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
+
+use crate::gift_wrap_service::GiftWrapRequest;
+use crate::restore_service::{RestoreOutcome, RestoreProgress};
+use crate::{Config, DiaryEntry, DiaryStore};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Restores back up the database first, keep those backups out of the real
+// data directory
+fn use_temp_data_dir() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let dir =
+            std::env::temp_dir().join(format!("luxun-dairy-relay-tests-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_var(crate::data_dir::DATA_DIR_ENV, &dir);
+    });
+}
+
+// A store with an empty database that publishes to `relay_urls` only
+fn test_store(keys: &Keys, relay_urls: &[&str]) -> Arc<DiaryStore> {
+    use_temp_data_dir();
+    let mut conn = Connection::open_in_memory().unwrap();
+    crate::migrations::migrate(&mut conn, None).unwrap();
+    let db = Arc::new(crate::database::Database::start(conn));
+
+    let config = Config {
+        relay_urls: relay_urls.iter().map(|url| url.to_string()).collect(),
+        default_relay_urls: Vec::new(),
+        ..Config::default()
+    };
+    Arc::new(DiaryStore {
+        nostr_keys: Mutex::new(Some(keys.clone())),
+        config: Mutex::new(config),
+        repository: Box::new(crate::repository::SqliteDiaryRepository::new(db.clone())),
+        db,
+        relay_information: Mutex::new(HashMap::new()),
+        client: crate::nostr_client::new_client(),
+    })
+}
+
+// What a relay holds, seen through a client of its own
+async fn fetch_from(relay_url: &str, filter: Filter) -> Vec<Event> {
+    let client = Client::default();
+    client.add_relay(relay_url).await.unwrap();
+    client.connect().await;
+    let events = client
+        .fetch_events(filter, FETCH_TIMEOUT)
+        .await
+        .unwrap()
+        .into_iter()
+        .collect();
+    client.disconnect().await;
+    events
+}
+
+async fn publish_directly(relay_url: &str, event: &Event) {
+    let client = Client::default();
+    client.add_relay(relay_url).await.unwrap();
+    client.connect().await;
+    client.send_event(event).await.unwrap();
+    client.disconnect().await;
+}
+
+// Signs, stores and publishes an entry the way save_diary_entry does
+async fn write_entry(
+    store: &Arc<DiaryStore>,
+    day: &str,
+    content: &str,
+    private: bool,
+) -> DiaryEntry {
+    let keys = crate::get_or_create_nostr_keys(store).unwrap();
+    let event = crate::sign_diary_event(
+        &keys,
+        content,
+        "晴",
+        day,
+        vec![Tag::hashtag("读书")],
+        private,
+    )
+    .await
+    .unwrap();
+    let event_json = serde_json::to_string(&event).unwrap();
+
+    let entry = DiaryEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        content: content.to_string(),
+        weather: "晴".to_string(),
+        created_at: chrono::Utc::now(),
+        nostr_id: Some(event.id.to_string()),
+        day: day.to_string(),
+        updated_at: None,
+        utc_offset: Some("+08:00".to_string()),
+        private,
+    };
+    store
+        .repository
+        .save_entry(&entry, &event_json, &[])
+        .await
+        .unwrap();
+    crate::publish_saved_event(&event, false, store).await;
+    entry
+}
+
+fn no_progress(_: RestoreProgress) {}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_created_event_is_published_to_the_relay() {
+    let relay = MockRelay::run().await.unwrap();
+    let keys = Keys::generate();
+    let store = test_store(&keys, &[&relay.url()]);
+
+    let entry = write_entry(&store, "2024-03-01", "今天读书。", false).await;
+    let nostr_id = entry.nostr_id.unwrap();
+
+    let events = fetch_from(
+        &relay.url(),
+        Filter::new()
+            .author(keys.public_key())
+            .kind(Kind::from(30027)),
+    )
+    .await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].id.to_hex(), nostr_id);
+    assert_eq!(events[0].content, "今天读书。");
+    assert_eq!(events[0].tags.identifier(), Some("2024-03-01"));
+
+    // The outbox delivered it and kept the relay's answer
+    let (status, accepted): (String, bool) = {
+        let nostr_id = nostr_id.clone();
+        store
+            .db
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT o.status, r.accepted FROM outbox o
+                     JOIN publish_receipts r ON r.event_id = o.event_id AND r.relay_url = o.relay_url
+                     WHERE o.event_id = ?1",
+                    params![nostr_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .map_err(|e| e.to_string())
+            })
+            .await
+            .unwrap()
+    };
+    assert_eq!(status, "sent");
+    assert!(accepted);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_private_event_reaches_the_relay_encrypted() {
+    let relay = MockRelay::run().await.unwrap();
+    let keys = Keys::generate();
+    let store = test_store(&keys, &[&relay.url()]);
+
+    write_entry(&store, "2024-03-02", "只有自己能读。", true).await;
+
+    let events = fetch_from(
+        &relay.url(),
+        Filter::new()
+            .author(keys.public_key())
+            .kind(Kind::from(30027)),
+    )
+    .await;
+    assert_eq!(events.len(), 1);
+    assert!(crate::private_event::is_private(&events[0]));
+    assert!(!events[0].content.contains("只有自己能读"));
+    assert_eq!(events[0].tags.hashtags().count(), 0);
+
+    let opened = crate::private_event::open_event(&keys, &events[0]).unwrap();
+    assert_eq!(opened.content, "只有自己能读。");
+    assert_eq!(opened.tags.hashtags().collect::<Vec<_>>(), vec!["读书"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_gift_wrap_is_shared_and_unwrapped_by_the_recipient() {
+    let relay = MockRelay::run().await.unwrap();
+    let (sender_keys, recipient_keys) = (Keys::generate(), Keys::generate());
+    let sender = test_store(&sender_keys, &[&relay.url()]);
+    let recipient = test_store(&recipient_keys, &[&relay.url()]);
+
+    let entry = write_entry(&sender, "2024-03-03", "给朋友看的一天。", true).await;
+    let wrapped = crate::gift_wrap_service::wrap_entry(
+        &sender,
+        GiftWrapRequest {
+            nostr_id: entry.nostr_id.unwrap(),
+            recipient_pubkey: recipient_keys.public_key().to_hex(),
+        },
+    )
+    .await
+    .unwrap();
+    let gift_wrap: Event = serde_json::from_str(&wrapped.gift_wrap_event).unwrap();
+    assert_eq!(gift_wrap.kind, Kind::GiftWrap);
+    assert_ne!(gift_wrap.pubkey, sender_keys.public_key());
+
+    crate::gift_wrap_service::send_gift_wrap(&sender, &gift_wrap, Some(relay.url()), &[])
+        .await
+        .unwrap();
+
+    let received = crate::gift_wrap_service::receive_gift_wraps(&recipient)
+        .await
+        .unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].sender_pubkey, sender_keys.public_key().to_hex());
+    // A private entry is shared decrypted, the recipient cannot read our seal
+    let rumor: serde_json::Value = serde_json::from_str(&received[0].gift_wrap_event).unwrap();
+    assert_eq!(rumor["content"], "给朋友看的一天。");
+
+    // Nothing is waiting for the sender
+    let sender_received = crate::gift_wrap_service::receive_gift_wraps(&sender)
+        .await
+        .unwrap();
+    assert!(sender_received.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_gift_wrap_goes_to_the_inbox_relays_of_the_recipient() {
+    let our_relay = MockRelay::run().await.unwrap();
+    let inbox_relay = MockRelay::run().await.unwrap();
+    let (sender_keys, recipient_keys) = (Keys::generate(), Keys::generate());
+    let sender = test_store(&sender_keys, &[&our_relay.url()]);
+    let recipient = test_store(&recipient_keys, &[&inbox_relay.url()]);
+
+    // The recipient reads from the inbox relay and says so in a relay list
+    // found on our relay
+    let relay_list = EventBuilder::relay_list([(
+        RelayUrl::parse(&inbox_relay.url()).unwrap(),
+        Some(RelayMetadata::Read),
+    )])
+    .sign_with_keys(&recipient_keys)
+    .unwrap();
+    publish_directly(&our_relay.url(), &relay_list).await;
+
+    let entry = write_entry(&sender, "2024-03-04", "寄往收件箱。", false).await;
+    let wrapped = crate::gift_wrap_service::wrap_entry(
+        &sender,
+        GiftWrapRequest {
+            nostr_id: entry.nostr_id.unwrap(),
+            recipient_pubkey: recipient_keys.public_key().to_hex(),
+        },
+    )
+    .await
+    .unwrap();
+    let gift_wrap: Event = serde_json::from_str(&wrapped.gift_wrap_event).unwrap();
+
+    crate::gift_wrap_service::send_gift_wrap(&sender, &gift_wrap, None, &[])
+        .await
+        .unwrap();
+
+    let on_our_relay = fetch_from(&our_relay.url(), Filter::new().kind(Kind::GiftWrap)).await;
+    assert!(on_our_relay.is_empty());
+    // The inbox relay was reached without joining the shared pool
+    let pool = sender.client.relays().await;
+    assert!(!pool.contains_key(&RelayUrl::parse(&inbox_relay.url()).unwrap()));
+    let received = crate::gift_wrap_service::receive_gift_wraps(&recipient)
+        .await
+        .unwrap();
+    assert_eq!(received.len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_gift_wrap_follows_the_relay_hints_of_an_nprofile() {
+    let our_relay = MockRelay::run().await.unwrap();
+    let hinted_relay = MockRelay::run().await.unwrap();
+    let (sender_keys, recipient_keys) = (Keys::generate(), Keys::generate());
+    let sender = test_store(&sender_keys, &[&our_relay.url()]);
+    let recipient = test_store(&recipient_keys, &[&hinted_relay.url()]);
+
+    // No relay list anywhere, only the hint in the shared profile
+    let nprofile = Nip19Profile::new(recipient_keys.public_key(), [hinted_relay.url()])
+        .unwrap()
+        .to_nostr_uri()
+        .unwrap();
+
+    let entry = write_entry(&sender, "2024-03-08", "按提示寄出。", false).await;
+    let wrapped = crate::gift_wrap_service::wrap_entry(
+        &sender,
+        GiftWrapRequest {
+            nostr_id: entry.nostr_id.unwrap(),
+            recipient_pubkey: nprofile,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(wrapped.relay_hints, vec![hinted_relay.url()]);
+    let gift_wrap: Event = serde_json::from_str(&wrapped.gift_wrap_event).unwrap();
+
+    crate::gift_wrap_service::send_gift_wrap(&sender, &gift_wrap, None, &wrapped.relay_hints)
+        .await
+        .unwrap();
+
+    let received = crate::gift_wrap_service::receive_gift_wraps(&recipient)
+        .await
+        .unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].sender_pubkey, sender_keys.public_key().to_hex());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_restore_recovers_entries_from_the_relay() {
+    let relay = MockRelay::run().await.unwrap();
+    let keys = Keys::generate();
+    let original = test_store(&keys, &[&relay.url()]);
+    write_entry(&original, "2024-03-05", "公开的一天。", false).await;
+    write_entry(&original, "2024-03-06", "加密的一天。", true).await;
+
+    // The same key on a new device with an empty diary
+    let restored = test_store(&keys, &[&relay.url()]);
+
+    let preview = crate::restore_service::restore(&restored, true, &no_progress)
+        .await
+        .unwrap();
+    assert_eq!(preview.fetched_events, 2);
+    assert!(preview
+        .days
+        .iter()
+        .all(|day| day.outcome == RestoreOutcome::Restored));
+    assert!(restored.repository.load_entries().await.unwrap().is_empty());
+
+    let report = crate::restore_service::restore(&restored, false, &no_progress)
+        .await
+        .unwrap();
+    assert_eq!(report.days.len(), 2);
+
+    let mut entries = restored.repository.load_entries().await.unwrap();
+    entries.sort_by(|a, b| a.day.cmp(&b.day));
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].content, "公开的一天。");
+    assert!(!entries[0].private);
+    assert_eq!(entries[1].content, "加密的一天。");
+    assert!(entries[1].private);
+    assert_eq!(entries[1].weather, "晴");
+
+    // A second restore finds nothing new
+    let again = crate::restore_service::restore(&restored, false, &no_progress)
+        .await
+        .unwrap();
+    assert!(again
+        .days
+        .iter()
+        .all(|day| day.outcome == RestoreOutcome::Unchanged));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_restore_ignores_events_of_other_keys() {
+    let relay = MockRelay::run().await.unwrap();
+    let (keys, other_keys) = (Keys::generate(), Keys::generate());
+    let other = test_store(&other_keys, &[&relay.url()]);
+    write_entry(&other, "2024-03-07", "别人的日记。", false).await;
+
+    let store = test_store(&keys, &[&relay.url()]);
+    let report = crate::restore_service::restore(&store, false, &no_progress)
+        .await
+        .unwrap();
+    assert_eq!(report.fetched_events, 0);
+    assert!(store.repository.load_entries().await.unwrap().is_empty());
+}
@@ -0,0 +1,816 @@
+// Basic storage of diary entries behind the DiaryRepository trait
+//
+// The trait covers saving, loading, trashing and restoring entries and
+// looking up their events. DiaryStore holds it as a trait object; the app
+// uses the SQLite implementation and the in-memory one keeps the same rules
+// without any files, which is what the tests below run against alongside
+// SQLite. Revisions, tags, attachments, search, the outbox and the other
+// services query the database directly through DiaryStore::db.
+use async_trait::async_trait;
+use chrono::{Datelike, NaiveDate, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use crate::database::Database;
+use crate::{entry_from_row, DiaryEntry, ENTRY_COLUMNS};
+
+#[async_trait]
+pub trait DiaryRepository: Send + Sync {
+    // Inserts a new entry or replaces the entry with the same id, which stays
+    // in the trash if it is there, together with its manual tags. There is at
+    // most one entry per day: saving fails if another entry occupies the day,
+    // including an entry in the trash.
+    async fn save_entry(
+        &self,
+        entry: &DiaryEntry,
+        nostr_event_json: &str,
+        manual_tags: &[String],
+    ) -> Result<(), String>;
+
+    // All entries outside the trash, newest day first
+    async fn load_entries(&self) -> Result<Vec<DiaryEntry>, String>;
+
+    // Entries outside the trash between two days (inclusive), oldest first
+    async fn load_entries_in_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<DiaryEntry>, String>;
+
+    // Whether the day is taken, by an entry in the trash or not
+    async fn entry_exists_for_day(&self, day: &str) -> Result<bool, String>;
+
+    async fn is_day_trashed(&self, day: &str) -> Result<bool, String>;
+
+    // Moves the entry of a day to the trash. Returns false if there is none.
+    async fn trash_entry(&self, day: &str) -> Result<bool, String>;
+
+    // Takes the entry of a day out of the trash. Returns false if there is none.
+    async fn restore_entry(&self, day: &str) -> Result<bool, String>;
+
+    // A signed event by ID, current or superseded by an edit
+    async fn get_nostr_event(&self, nostr_id: &str) -> Result<Option<String>, String>;
+}
+
+fn parse_entry_day(day: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(day, "%Y-%m-%d").map_err(|_| format!("Invalid day: {}", day))
+}
+
+// A trashed entry still holds its day, which the user cannot see in the
+// diary, so say where it is
+pub fn day_taken_error(day: &str, trashed: bool) -> String {
+    if trashed {
+        format!(
+            "The entry for day {} is in the trash, restore or purge it first",
+            day
+        )
+    } else {
+        format!("An entry already exists for day: {}", day)
+    }
+}
+
+pub struct SqliteDiaryRepository {
+    db: Arc<Database>,
+}
+
+impl SqliteDiaryRepository {
+    pub fn new(db: Arc<Database>) -> Self {
+        SqliteDiaryRepository { db }
+    }
+}
+
+fn save_entry_to_db(
+    conn: &mut Connection,
+    entry: &DiaryEntry,
+    date: NaiveDate,
+    nostr_event_json: &str,
+    manual_tags: &[String],
+) -> Result<(), String> {
+    let save = |conn: &mut Connection| -> SqlResult<Option<String>> {
+        let tx = conn.transaction()?;
+
+        // Check if an entry already exists for this day
+        let existing: Option<(String, bool)> = tx
+            .query_row(
+                "SELECT id, deleted_at IS NOT NULL FROM diary_entries WHERE day = ?1",
+                params![entry.day],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        if let Some((existing_id, trashed)) = existing {
+            if existing_id != entry.id {
+                return Ok(Some(day_taken_error(&entry.day, trashed)));
+            }
+        }
+        let previous_nostr_id: Option<Option<String>> = tx
+            .query_row(
+                "SELECT nostr_id FROM diary_entries WHERE id = ?1",
+                params![entry.id],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        // An upsert rather than INSERT OR REPLACE, which would delete the row
+        // and lose updated_at and deleted_at
+        tx.execute(
+            "INSERT INTO diary_entries
+                (id, content, weather, created_at, nostr_id, day, nostr_event,
+                 year, month, day_of_month, utc_offset, private)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+             ON CONFLICT (id) DO UPDATE SET
+                content = excluded.content,
+                weather = excluded.weather,
+                created_at = excluded.created_at,
+                nostr_id = excluded.nostr_id,
+                day = excluded.day,
+                nostr_event = excluded.nostr_event,
+                year = excluded.year,
+                month = excluded.month,
+                day_of_month = excluded.day_of_month,
+                utc_offset = excluded.utc_offset,
+                private = excluded.private",
+            params![
+                entry.id,
+                entry.content,
+                entry.weather,
+                entry.created_at.to_rfc3339(),
+                entry.nostr_id,
+                entry.day,
+                nostr_event_json,
+                date.year(),
+                date.month(),
+                date.day(),
+                entry.utc_offset,
+                entry.private
+            ],
+        )?;
+
+        // The first signed event of an entry is its first revision. Saving
+        // the same event again adds none.
+        if previous_nostr_id.as_ref() != Some(&entry.nostr_id) {
+            crate::revision_service::record_revision(
+                &tx,
+                &entry.id,
+                entry.nostr_id.as_deref(),
+                nostr_event_json,
+                &entry.content,
+                &entry.weather,
+                &entry.created_at,
+            )?;
+        }
+
+        // Keep the full-text index in sync with the entry
+        crate::search_service::index_entry(&tx, &entry.id, &entry.content, &entry.weather)?;
+        crate::tag_service::save_entry_tags(&tx, &entry.id, &entry.content, manual_tags)?;
+        tx.commit()?;
+        Ok(None)
+    };
+
+    match save(conn) {
+        Ok(None) => {
+            println!("Saved entry to database with ID: {}", entry.id);
+            Ok(())
+        }
+        Ok(Some(rejected)) => Err(rejected),
+        Err(e) => Err(format!("Failed to save entry to database: {}", e)),
+    }
+}
+
+// Adds entries that were inserted by other tools, such as scripts/migrate-jrnl,
+// to the full-text index and the tags. Run after opening the database.
+pub fn index_external_entries(conn: &Connection) -> SqlResult<usize> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content, weather FROM diary_entries
+         WHERE id NOT IN (SELECT entry_id FROM diary_entries_fts)",
+    )?;
+    let entries = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+    for (id, content, weather) in &entries {
+        crate::search_service::index_entry(conn, id, content, weather)?;
+        crate::tag_service::save_entry_tags(conn, id, content, &[])?;
+    }
+    if !entries.is_empty() {
+        println!("Indexed {} entries added outside the app", entries.len());
+    }
+    Ok(entries.len())
+}
+
+fn load_entries_from_db(conn: &Connection) -> SqlResult<Vec<DiaryEntry>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM diary_entries
+         WHERE deleted_at IS NULL
+         ORDER BY year DESC, month DESC, day_of_month DESC, created_at DESC",
+        ENTRY_COLUMNS
+    ))?;
+
+    let entries_iter = stmt.query_map([], entry_from_row)?;
+
+    let mut entries = Vec::new();
+    for entry_result in entries_iter {
+        entries.push(entry_result?);
+    }
+    Ok(entries)
+}
+
+// Uses the indexed year/month/day keys
+fn load_entries_in_range_from_db(
+    conn: &Connection,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> SqlResult<Vec<DiaryEntry>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM diary_entries
+         WHERE (year, month, day_of_month) BETWEEN (?1, ?2, ?3) AND (?4, ?5, ?6)
+           AND deleted_at IS NULL
+         ORDER BY year, month, day_of_month",
+        ENTRY_COLUMNS
+    ))?;
+
+    let entries_iter = stmt.query_map(
+        params![
+            start.year(),
+            start.month(),
+            start.day(),
+            end.year(),
+            end.month(),
+            end.day()
+        ],
+        entry_from_row,
+    )?;
+
+    let mut entries = Vec::new();
+    for entry_result in entries_iter {
+        entries.push(entry_result?);
+    }
+    Ok(entries)
+}
+
+fn get_nostr_event_from_db(conn: &Connection, nostr_id: &str) -> SqlResult<Option<String>> {
+    let current: Option<String> = conn
+        .query_row(
+            "SELECT nostr_event FROM diary_entries WHERE nostr_id = ?1",
+            params![nostr_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if current.is_some() {
+        return Ok(current);
+    }
+
+    // Events superseded by an edit are still kept as revisions
+    conn.query_row(
+        "SELECT nostr_event FROM diary_entry_revisions WHERE nostr_id = ?1",
+        params![nostr_id],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+fn entry_exists_for_day(conn: &Connection, day: &str) -> SqlResult<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM diary_entries WHERE day = ?1",
+        params![day],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn is_day_trashed(conn: &Connection, day: &str) -> SqlResult<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM diary_entries WHERE day = ?1 AND deleted_at IS NOT NULL",
+        params![day],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+#[async_trait]
+impl DiaryRepository for SqliteDiaryRepository {
+    async fn save_entry(
+        &self,
+        entry: &DiaryEntry,
+        nostr_event_json: &str,
+        manual_tags: &[String],
+    ) -> Result<(), String> {
+        let date = parse_entry_day(&entry.day)?;
+        let entry = entry.clone();
+        let nostr_event_json = nostr_event_json.to_string();
+        let manual_tags = manual_tags.to_vec();
+        self.db
+            .call(move |conn| save_entry_to_db(conn, &entry, date, &nostr_event_json, &manual_tags))
+            .await
+    }
+
+    async fn load_entries(&self) -> Result<Vec<DiaryEntry>, String> {
+        self.db
+            .call(|conn| {
+                load_entries_from_db(conn)
+                    .map_err(|e| format!("Failed to load entries from database: {}", e))
+            })
+            .await
+    }
+
+    async fn load_entries_in_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<DiaryEntry>, String> {
+        self.db
+            .call(move |conn| {
+                load_entries_in_range_from_db(conn, start, end)
+                    .map_err(|e| format!("Failed to load entries: {}", e))
+            })
+            .await
+    }
+
+    async fn entry_exists_for_day(&self, day: &str) -> Result<bool, String> {
+        let day = day.to_string();
+        self.db
+            .call(move |conn| {
+                entry_exists_for_day(conn, &day)
+                    .map_err(|e| format!("Failed to check if day has entry: {}", e))
+            })
+            .await
+    }
+
+    async fn is_day_trashed(&self, day: &str) -> Result<bool, String> {
+        let day = day.to_string();
+        self.db
+            .call(move |conn| {
+                is_day_trashed(conn, &day).map_err(|e| format!("Failed to check the trash: {}", e))
+            })
+            .await
+    }
+
+    async fn trash_entry(&self, day: &str) -> Result<bool, String> {
+        let day = day.to_string();
+        self.db
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE diary_entries SET deleted_at = ?1 WHERE day = ?2 AND deleted_at IS NULL",
+                    params![Utc::now().to_rfc3339(), day],
+                )
+                .map(|updated| updated > 0)
+                .map_err(|e| format!("Failed to move entry to trash: {}", e))
+            })
+            .await
+    }
+
+    async fn restore_entry(&self, day: &str) -> Result<bool, String> {
+        let day = day.to_string();
+        self.db
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE diary_entries SET deleted_at = NULL WHERE day = ?1 AND deleted_at IS NOT NULL",
+                    params![day],
+                )
+                .map(|updated| updated > 0)
+                .map_err(|e| format!("Failed to restore entry: {}", e))
+            })
+            .await
+    }
+
+    async fn get_nostr_event(&self, nostr_id: &str) -> Result<Option<String>, String> {
+        let nostr_id = nostr_id.to_string();
+        self.db
+            .call(move |conn| {
+                get_nostr_event_from_db(conn, &nostr_id)
+                    .map_err(|e| format!("Failed to get Nostr event: {}", e))
+            })
+            .await
+    }
+}
+
+struct StoredEntry {
+    entry: DiaryEntry,
+    trashed: bool,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    entries: BTreeMap<NaiveDate, StoredEntry>,
+    events: HashMap<String, String>, // every signed event by ID
+}
+
+// Keeps entries in memory only
+#[derive(Default)]
+pub struct MemoryDiaryRepository {
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryDiaryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl DiaryRepository for MemoryDiaryRepository {
+    // Tags are not kept in memory
+    async fn save_entry(
+        &self,
+        entry: &DiaryEntry,
+        nostr_event_json: &str,
+        _manual_tags: &[String],
+    ) -> Result<(), String> {
+        let date = parse_entry_day(&entry.day)?;
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(existing) = state.entries.get(&date) {
+            if existing.entry.id != entry.id {
+                return Err(day_taken_error(&entry.day, existing.trashed));
+            }
+        }
+
+        // Saving with the same id replaces the entry, even on another day
+        let trashed = state
+            .entries
+            .values()
+            .any(|stored| stored.entry.id == entry.id && stored.trashed);
+        state
+            .entries
+            .retain(|_, stored| stored.entry.id != entry.id);
+        state.entries.insert(
+            date,
+            StoredEntry {
+                entry: entry.clone(),
+                trashed,
+            },
+        );
+        if let Some(nostr_id) = &entry.nostr_id {
+            state
+                .events
+                .insert(nostr_id.clone(), nostr_event_json.to_string());
+        }
+        Ok(())
+    }
+
+    async fn load_entries(&self) -> Result<Vec<DiaryEntry>, String> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .entries
+            .values()
+            .rev()
+            .filter(|stored| !stored.trashed)
+            .map(|stored| stored.entry.clone())
+            .collect())
+    }
+
+    async fn load_entries_in_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<DiaryEntry>, String> {
+        if start > end {
+            return Ok(Vec::new());
+        }
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .entries
+            .range(start..=end)
+            .map(|(_, stored)| stored)
+            .filter(|stored| !stored.trashed)
+            .map(|stored| stored.entry.clone())
+            .collect())
+    }
+
+    async fn entry_exists_for_day(&self, day: &str) -> Result<bool, String> {
+        let state = self.state.lock().unwrap();
+        Ok(state.entries.values().any(|stored| stored.entry.day == day))
+    }
+
+    async fn is_day_trashed(&self, day: &str) -> Result<bool, String> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .entries
+            .values()
+            .any(|stored| stored.entry.day == day && stored.trashed))
+    }
+
+    async fn trash_entry(&self, day: &str) -> Result<bool, String> {
+        let mut state = self.state.lock().unwrap();
+        match state
+            .entries
+            .values_mut()
+            .find(|stored| stored.entry.day == day && !stored.trashed)
+        {
+            Some(stored) => {
+                stored.trashed = true;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn restore_entry(&self, day: &str) -> Result<bool, String> {
+        let mut state = self.state.lock().unwrap();
+        match state
+            .entries
+            .values_mut()
+            .find(|stored| stored.entry.day == day && stored.trashed)
+        {
+            Some(stored) => {
+                stored.trashed = false;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn get_nostr_event(&self, nostr_id: &str) -> Result<Option<String>, String> {
+        let state = self.state.lock().unwrap();
+        Ok(state.events.get(nostr_id).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn sqlite_repository() -> Box<dyn DiaryRepository> {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        Box::new(SqliteDiaryRepository::new(Arc::new(Database::start(conn))))
+    }
+
+    // Every test runs against each backend
+    fn repositories() -> Vec<(&'static str, Box<dyn DiaryRepository>)> {
+        vec![
+            ("sqlite", sqlite_repository()),
+            ("memory", Box::new(MemoryDiaryRepository::new())),
+        ]
+    }
+
+    fn entry(id: &str, day: &str, content: &str) -> DiaryEntry {
+        DiaryEntry {
+            id: id.to_string(),
+            content: content.to_string(),
+            weather: "晴".to_string(),
+            created_at: Utc::now(),
+            nostr_id: Some(format!("event-{}", id)),
+            day: day.to_string(),
+            updated_at: None,
+            utc_offset: Some("+08:00".to_string()),
+            private: false,
+        }
+    }
+
+    fn event_json(id: &str) -> String {
+        format!("{{\"id\":\"event-{}\"}}", id)
+    }
+
+    async fn save(repo: &dyn DiaryRepository, entry: &DiaryEntry) -> Result<(), String> {
+        repo.save_entry(entry, &event_json(&entry.id), &[]).await
+    }
+
+    #[test]
+    fn test_second_entry_for_a_day_is_rejected() {
+        for (name, repo) in repositories() {
+            block_on(async {
+                save(&*repo, &entry("a", "2024-03-01", "first"))
+                    .await
+                    .unwrap();
+
+                let result = save(&*repo, &entry("b", "2024-03-01", "second")).await;
+                assert_eq!(
+                    result,
+                    Err("An entry already exists for day: 2024-03-01".to_string()),
+                    "{}",
+                    name
+                );
+
+                let entries = repo.load_entries().await.unwrap();
+                assert_eq!(entries.len(), 1, "{}", name);
+                assert_eq!(entries[0].content, "first", "{}", name);
+            });
+        }
+    }
+
+    #[test]
+    fn test_saving_the_same_entry_again_replaces_it() {
+        for (name, repo) in repositories() {
+            block_on(async {
+                save(&*repo, &entry("a", "2024-03-01", "first"))
+                    .await
+                    .unwrap();
+                save(&*repo, &entry("a", "2024-03-01", "edited"))
+                    .await
+                    .unwrap();
+
+                let entries = repo.load_entries().await.unwrap();
+                assert_eq!(entries.len(), 1, "{}", name);
+                assert_eq!(entries[0].content, "edited", "{}", name);
+            });
+        }
+    }
+
+    #[test]
+    fn test_saving_again_keeps_trash_and_revisions() {
+        for (name, repo) in repositories() {
+            block_on(async {
+                save(&*repo, &entry("a", "2024-03-01", "first"))
+                    .await
+                    .unwrap();
+                repo.trash_entry("2024-03-01").await.unwrap();
+                save(&*repo, &entry("a", "2024-03-01", "first"))
+                    .await
+                    .unwrap();
+
+                assert!(repo.is_day_trashed("2024-03-01").await.unwrap(), "{}", name);
+                assert!(repo.load_entries().await.unwrap().is_empty(), "{}", name);
+            });
+        }
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        let first = entry("a", "2024-03-01", "first");
+        let date = parse_entry_day(&first.day).unwrap();
+        save_entry_to_db(&mut conn, &first, date, &event_json("a"), &[]).unwrap();
+        conn.execute(
+            "UPDATE diary_entries SET updated_at = 'edited' WHERE id = 'a'",
+            [],
+        )
+        .unwrap();
+        save_entry_to_db(&mut conn, &first, date, &event_json("a"), &[]).unwrap();
+
+        let (updated_at, revisions): (Option<String>, i64) = conn
+            .query_row(
+                "SELECT updated_at, (SELECT COUNT(*) FROM diary_entry_revisions)
+                 FROM diary_entries WHERE id = 'a'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(updated_at.as_deref(), Some("edited"));
+        assert_eq!(revisions, 1);
+    }
+
+    #[test]
+    fn test_manual_tags_are_saved_with_the_entry() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        let first = entry("a", "2024-03-01", "#读书");
+        let date = parse_entry_day(&first.day).unwrap();
+        let tags = vec!["散步".to_string()];
+        save_entry_to_db(&mut conn, &first, date, &event_json("a"), &tags).unwrap();
+        assert_eq!(
+            crate::tag_service::load_manual_tags(&conn, "a").unwrap(),
+            tags
+        );
+
+        // A rejected entry leaves no tags behind
+        let second = entry("b", "2024-03-01", "second");
+        assert!(save_entry_to_db(&mut conn, &second, date, &event_json("b"), &tags).is_err());
+        assert!(crate::tag_service::load_entry_tags(&conn, "b")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_entries_on_different_days_are_independent() {
+        for (name, repo) in repositories() {
+            block_on(async {
+                save(&*repo, &entry("a", "2024-03-01", "one"))
+                    .await
+                    .unwrap();
+                save(&*repo, &entry("b", "2024-03-02", "two"))
+                    .await
+                    .unwrap();
+                save(&*repo, &entry("c", "2023-12-31", "three"))
+                    .await
+                    .unwrap();
+
+                assert!(repo.entry_exists_for_day("2024-03-02").await.unwrap());
+                assert!(!repo.entry_exists_for_day("2024-03-03").await.unwrap());
+
+                let days: Vec<String> = repo
+                    .load_entries()
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|e| e.day)
+                    .collect();
+                assert_eq!(
+                    days,
+                    vec!["2024-03-02", "2024-03-01", "2023-12-31"],
+                    "{}",
+                    name
+                );
+
+                let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+                let end = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+                let in_range: Vec<String> = repo
+                    .load_entries_in_range(start, end)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|e| e.day)
+                    .collect();
+                assert_eq!(in_range, vec!["2024-03-01"], "{}", name);
+            });
+        }
+    }
+
+    #[test]
+    fn test_trashed_entry_keeps_its_day() {
+        for (name, repo) in repositories() {
+            block_on(async {
+                save(&*repo, &entry("a", "2024-03-01", "first"))
+                    .await
+                    .unwrap();
+                assert!(repo.trash_entry("2024-03-01").await.unwrap(), "{}", name);
+
+                assert!(repo.load_entries().await.unwrap().is_empty(), "{}", name);
+                assert!(repo.entry_exists_for_day("2024-03-01").await.unwrap());
+                assert!(repo.is_day_trashed("2024-03-01").await.unwrap());
+                assert_eq!(
+                    save(&*repo, &entry("b", "2024-03-01", "second")).await,
+                    Err(
+                        "The entry for day 2024-03-01 is in the trash, restore or purge it first"
+                            .to_string()
+                    ),
+                    "{}",
+                    name
+                );
+
+                assert!(repo.restore_entry("2024-03-01").await.unwrap(), "{}", name);
+                assert!(!repo.is_day_trashed("2024-03-01").await.unwrap());
+                assert_eq!(repo.load_entries().await.unwrap().len(), 1, "{}", name);
+                assert!(!repo.restore_entry("2024-03-01").await.unwrap(), "{}", name);
+            });
+        }
+    }
+
+    #[test]
+    fn test_external_entries_are_indexed() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        conn.execute(
+            "INSERT INTO diary_entries (id, content, weather, created_at, day)
+             VALUES ('jrnl-id', '往图书馆 #读书', '晴', '2024-03-01T00:00:00+00:00', '2024-03-01')",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(index_external_entries(&conn).unwrap(), 1);
+        assert_eq!(index_external_entries(&conn).unwrap(), 0);
+
+        let found = crate::search_service::search_entries(&conn, "图书", 10).unwrap();
+        assert_eq!(found.len(), 1);
+        let tags = crate::tag_service::load_entry_tags(&conn, "jrnl-id").unwrap();
+        assert_eq!(tags[0].name, "读书");
+        let day = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        assert_eq!(
+            load_entries_in_range_from_db(&conn, day, day)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_invalid_day_is_rejected() {
+        for (name, repo) in repositories() {
+            block_on(async {
+                let result = save(&*repo, &entry("a", "2024-02-30", "nope")).await;
+                assert_eq!(
+                    result,
+                    Err("Invalid day: 2024-02-30".to_string()),
+                    "{}",
+                    name
+                );
+                assert!(repo.load_entries().await.unwrap().is_empty(), "{}", name);
+            });
+        }
+    }
+
+    #[test]
+    fn test_stored_events_are_found_by_id() {
+        for (name, repo) in repositories() {
+            block_on(async {
+                save(&*repo, &entry("a", "2024-03-01", "first"))
+                    .await
+                    .unwrap();
+
+                assert_eq!(
+                    repo.get_nostr_event("event-a").await.unwrap(),
+                    Some(event_json("a")),
+                    "{}",
+                    name
+                );
+                assert_eq!(repo.get_nostr_event("missing").await.unwrap(), None);
+            });
+        }
+    }
+}
@@ -0,0 +1,355 @@
+// Rebuilds diary entries from the signed events kept on relays
+//
+// Every kind 30027 event of our pubkey is fetched, checked and reduced to the
+// newest version per `d` tag, minus the entries our deletion requests
+// withdrew. Days that already have a local entry are never overwritten.
+// Attachments are not on relays, so restored entries come back without them.
+// Private events are decrypted with our own key.
+use chrono::{DateTime, Utc};
+use nostr_sdk::{Event, EventId, Filter, Keys, Kind, PublicKey, Timestamp};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, State};
+use uuid::Uuid;
+
+use crate::{DiaryEntry, DiaryStore};
+
+const DIARY_KIND: u16 = 30027;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+// Relays cap the events of one request, so older events are paged in with
+// `until` until a page brings nothing new
+const MAX_PAGES: usize = 100;
+const PROGRESS_EVENT: &str = "restore-progress";
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RestoreStage {
+    Connecting,
+    Fetching,
+    Saving,
+    Done,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RestoreProgress {
+    pub stage: RestoreStage,
+    pub current: usize,
+    pub total: usize,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RestoreOutcome {
+    Restored,
+    Unchanged, // the local entry already is this event
+    Conflict,  // a different local entry exists for the day and was kept
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RestoredDay {
+    pub day: String,
+    pub event_id: String,
+    pub created_at: DateTime<Utc>,
+    pub outcome: RestoreOutcome,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RestoreReport {
+    pub dry_run: bool,
+    pub relays: Vec<String>, // relays that could be reached
+    pub fetched_events: usize,
+    pub invalid_events: usize,
+    pub deleted_days: usize, // withdrawn by our own deletion requests
+    pub days: Vec<RestoredDay>,
+}
+
+// The newest valid event per day, following NIP-01: the later created_at
+// wins, and the lower id breaks ties
+struct RestorePlan {
+    latest: BTreeMap<String, Event>,
+    invalid_events: usize,
+    deleted_days: usize,
+}
+
+fn is_newer(candidate: &Event, current: &Event) -> bool {
+    candidate.created_at > current.created_at
+        || (candidate.created_at == current.created_at && candidate.id < current.id)
+}
+
+fn plan_restore(events: &[Event], keys: &Keys) -> RestorePlan {
+    let pubkey = &keys.public_key();
+    let mut invalid_events = 0;
+    let mut latest: BTreeMap<String, Event> = BTreeMap::new();
+    // Withdrawn ids, and the newest deletion time per day
+    let mut deleted_ids: HashSet<EventId> = HashSet::new();
+    let mut deleted_until: HashMap<String, Timestamp> = HashMap::new();
+
+    for event in events {
+        if event.verify().is_err()
+            || event.pubkey != *pubkey
+            || crate::private_event::open_event(keys, event).is_err()
+        {
+            invalid_events += 1;
+            continue;
+        }
+
+        if event.kind == Kind::EventDeletion {
+            deleted_ids.extend(event.tags.event_ids().copied());
+            for coordinate in event.tags.coordinates() {
+                if coordinate.kind == Kind::from(DIARY_KIND) && coordinate.public_key == *pubkey {
+                    let until = deleted_until
+                        .entry(coordinate.identifier.clone())
+                        .or_insert(event.created_at);
+                    *until = (*until).max(event.created_at);
+                }
+            }
+            continue;
+        }
+
+        let day = match event.tags.identifier() {
+            Some(day) if crate::parse_day(day).is_ok() => day.to_string(),
+            _ => {
+                invalid_events += 1;
+                continue;
+            }
+        };
+        match latest.get(&day) {
+            Some(current) if !is_newer(event, current) => {}
+            _ => {
+                latest.insert(day, event.clone());
+            }
+        }
+    }
+
+    // A deletion request removes every version up to its own creation time
+    let before = latest.len();
+    latest.retain(|day, event| {
+        !deleted_ids.contains(&event.id)
+            && deleted_until
+                .get(day)
+                .map(|until| event.created_at > *until)
+                .unwrap_or(true)
+    });
+
+    RestorePlan {
+        deleted_days: before - latest.len(),
+        latest,
+        invalid_events,
+    }
+}
+
+fn tag_value(event: &Event, name: &str) -> Option<String> {
+    event
+        .tags
+        .find(nostr_sdk::TagKind::custom(name))
+        .and_then(|tag| tag.content())
+        .map(|value| value.to_string())
+}
+
+// The entry an event describes, with the tags that did not come from
+// hashtags in the content
+fn entry_from_event(
+    keys: &Keys,
+    signed: &Event,
+    day: &str,
+) -> Result<(DiaryEntry, Vec<String>), String> {
+    let event = &crate::private_event::open_event(keys, signed)?;
+    let hashtags = crate::tag_service::extract_hashtags(&event.content);
+    let manual_tags: Vec<String> = event
+        .tags
+        .hashtags()
+        .filter_map(crate::tag_service::normalize_tag)
+        .filter(|tag| !hashtags.contains(tag))
+        .collect();
+
+    let entry = DiaryEntry {
+        id: Uuid::new_v4().to_string(),
+        content: event.content.clone(),
+        weather: tag_value(event, "weather").unwrap_or_default(),
+        created_at: DateTime::from_timestamp(event.created_at.as_u64() as i64, 0)
+            .unwrap_or_else(Utc::now),
+        nostr_id: Some(event.id.to_hex()),
+        day: day.to_string(),
+        updated_at: None,
+        utc_offset: tag_value(event, "utc_offset"),
+        private: crate::private_event::is_private(signed),
+    };
+    Ok((entry, manual_tags))
+}
+
+// Receives the progress of a restore, the command emits it to the frontend
+type ProgressFn<'a> = &'a (dyn Fn(RestoreProgress) + Send + Sync);
+
+fn emit_progress(on_progress: ProgressFn, stage: RestoreStage, current: usize, total: usize) {
+    on_progress(RestoreProgress {
+        stage,
+        current,
+        total,
+    });
+}
+
+// All diary events and deletion requests of `pubkey` on the configured relays
+async fn fetch_diary_events(
+    on_progress: ProgressFn<'_>,
+    store: &DiaryStore,
+    relay_urls: &[String],
+    pubkey: PublicKey,
+) -> Result<(Vec<String>, Vec<Event>), String> {
+    emit_progress(on_progress, RestoreStage::Connecting, 0, relay_urls.len());
+    let connections = crate::nostr_client::connect_relays(store, relay_urls).await;
+    let connected = connections.connected()?;
+    let relays: Vec<String> = connected.iter().map(|url| url.to_string()).collect();
+
+    let mut events: HashMap<EventId, Event> = HashMap::new();
+    let mut until: Option<Timestamp> = None;
+    for _ in 0..MAX_PAGES {
+        let mut filter = Filter::new()
+            .author(pubkey)
+            .kinds([Kind::from(DIARY_KIND), Kind::EventDeletion]);
+        if let Some(until) = until {
+            filter = filter.until(until);
+        }
+
+        let page = connections
+            .client
+            .fetch_events_from(connected.clone(), filter, FETCH_TIMEOUT)
+            .await
+            .map_err(|e| format!("Failed to fetch events: {}", e))?;
+
+        let before = events.len();
+        for event in page {
+            until = Some(until.map_or(event.created_at, |t| t.min(event.created_at)));
+            events.insert(event.id, event);
+        }
+        emit_progress(on_progress, RestoreStage::Fetching, events.len(), 0);
+        if events.len() == before {
+            break;
+        }
+    }
+
+    Ok((relays, events.into_values().collect()))
+}
+
+// Fetches the diary from the configured relays and saves the days that have
+// no local entry. With `dry_run` nothing is written and the report shows what
+// would be restored. Progress is emitted as `restore-progress` events.
+#[tauri::command]
+pub async fn restore_from_relays(
+    app: AppHandle,
+    store: State<'_, Arc<DiaryStore>>,
+    dry_run: bool,
+) -> Result<RestoreReport, String> {
+    let on_progress = |progress: RestoreProgress| {
+        if let Err(e) = app.emit(PROGRESS_EVENT, progress) {
+            println!("Failed to emit restore progress: {}", e);
+        }
+    };
+    restore(&store, dry_run, &on_progress).await
+}
+
+pub async fn restore(
+    store: &Arc<DiaryStore>,
+    dry_run: bool,
+    on_progress: ProgressFn<'_>,
+) -> Result<RestoreReport, String> {
+    let keys = crate::get_or_create_nostr_keys(store)?;
+    // Our own events are published to, and so read back from, the write relays
+    let relay_urls = crate::relay_list::write_relay_urls(store);
+    if relay_urls.is_empty() {
+        return Err("No write relays configured, mark a relay for writing".to_string());
+    }
+
+    let (relays, events) =
+        fetch_diary_events(on_progress, store, &relay_urls, keys.public_key()).await?;
+    let plan = plan_restore(&events, &keys);
+    println!(
+        "Fetched {} events from {} relays, {} days to consider",
+        events.len(),
+        relays.len(),
+        plan.latest.len()
+    );
+
+    // Event ids of the local entries, trashed ones included
+    let local: HashMap<String, Option<String>> = {
+        let days: Vec<String> = plan.latest.keys().cloned().collect();
+        store
+            .db
+            .call(move |conn| {
+                let mut local = HashMap::new();
+                for day in days {
+                    let nostr_id: Option<Option<String>> = conn
+                        .query_row(
+                            "SELECT nostr_id FROM diary_entries WHERE day = ?1",
+                            params![day],
+                            |row| row.get(0),
+                        )
+                        .optional()
+                        .map_err(|e| format!("Failed to load entry: {}", e))?;
+                    if let Some(nostr_id) = nostr_id {
+                        local.insert(day, nostr_id);
+                    }
+                }
+                Ok(local)
+            })
+            .await?
+    };
+
+    let mut days = Vec::new();
+    for (day, event) in &plan.latest {
+        let outcome = match local.get(day) {
+            None => RestoreOutcome::Restored,
+            Some(nostr_id) if nostr_id.as_deref() == Some(event.id.to_hex().as_str()) => {
+                RestoreOutcome::Unchanged
+            }
+            Some(_) => RestoreOutcome::Conflict,
+        };
+        days.push(RestoredDay {
+            day: day.clone(),
+            event_id: event.id.to_hex(),
+            created_at: DateTime::from_timestamp(event.created_at.as_u64() as i64, 0)
+                .unwrap_or_else(Utc::now),
+            outcome,
+        });
+    }
+
+    let to_restore: Vec<&RestoredDay> = days
+        .iter()
+        .filter(|d| d.outcome == RestoreOutcome::Restored)
+        .collect();
+    if !dry_run && !to_restore.is_empty() {
+        crate::backup_service::backup_before(store, "restore").await?;
+
+        for (index, restored) in to_restore.iter().enumerate() {
+            emit_progress(on_progress, RestoreStage::Saving, index, to_restore.len());
+            let event = &plan.latest[&restored.day];
+            let event_json = serde_json::to_string(event)
+                .map_err(|e| format!("Failed to serialize Nostr event: {}", e))?;
+            let (entry, manual_tags) = entry_from_event(&keys, event, &restored.day)?;
+
+            store
+                .repository
+                .save_entry(&entry, &event_json, &manual_tags)
+                .await?;
+        }
+        println!("Restored {} entries from relays", to_restore.len());
+    }
+    emit_progress(
+        on_progress,
+        RestoreStage::Done,
+        to_restore.len(),
+        to_restore.len(),
+    );
+
+    Ok(RestoreReport {
+        dry_run,
+        relays,
+        fetched_events: events.len(),
+        invalid_events: plan.invalid_events,
+        deleted_days: plan.deleted_days,
+        days,
+    })
+}
@@ -0,0 +1,551 @@
+use chrono::{DateTime, Utc};
+use nostr_sdk::{Event, JsonUtil, Tag};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult, Transaction};
+use serde::{Deserialize, Serialize};
+use similar::{Algorithm, ChangeTag, TextDiff};
+use std::sync::Arc;
+use tauri::State;
+
+use crate::{DiaryEntry, DiaryStore};
+
+// One signed version of a diary entry. Revision 1 is the original entry.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct EntryRevision {
+    pub revision: u32,
+    pub nostr_id: Option<String>,
+    pub content: String,
+    pub weather: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct DiffSegment {
+    pub kind: String, // "equal", "insert" or "delete"
+    pub text: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RevisionDiff {
+    pub from_revision: u32,
+    pub to_revision: u32,
+    pub weather_before: String,
+    pub weather_after: String,
+    pub changes: Vec<DiffSegment>,
+}
+
+// Appends a new revision for an entry and returns its number
+pub fn record_revision(
+    conn: &Connection,
+    entry_id: &str,
+    nostr_id: Option<&str>,
+    nostr_event_json: &str,
+    content: &str,
+    weather: &str,
+    created_at: &DateTime<Utc>,
+) -> SqlResult<u32> {
+    let revision: u32 = conn.query_row(
+        "SELECT COALESCE(MAX(revision), 0) + 1 FROM diary_entry_revisions WHERE entry_id = ?1",
+        params![entry_id],
+        |row| row.get(0),
+    )?;
+
+    conn.execute(
+        "INSERT INTO diary_entry_revisions
+            (entry_id, revision, nostr_id, nostr_event, content, weather, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            entry_id,
+            revision,
+            nostr_id,
+            nostr_event_json,
+            content,
+            weather,
+            created_at.to_rfc3339()
+        ],
+    )?;
+
+    Ok(revision)
+}
+
+pub fn find_entry_for_day(conn: &Connection, day: &str) -> SqlResult<Option<DiaryEntry>> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM diary_entries WHERE day = ?1 AND deleted_at IS NULL",
+            crate::ENTRY_COLUMNS
+        ),
+        params![day],
+        crate::entry_from_row,
+    )
+    .optional()
+}
+
+fn load_revisions(conn: &Connection, entry_id: &str) -> SqlResult<Vec<EntryRevision>> {
+    let mut stmt = conn.prepare(
+        "SELECT revision, nostr_id, content, weather, created_at
+         FROM diary_entry_revisions WHERE entry_id = ?1
+         ORDER BY revision",
+    )?;
+
+    let revisions_iter = stmt.query_map(params![entry_id], |row| {
+        let created_at_str: String = row.get(4)?;
+        let created_at = DateTime::parse_from_rfc3339(&created_at_str)
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now());
+
+        Ok(EntryRevision {
+            revision: row.get(0)?,
+            nostr_id: row.get(1)?,
+            content: row.get(2)?,
+            weather: row.get(3)?,
+            created_at,
+        })
+    })?;
+
+    let mut revisions = Vec::new();
+    for revision in revisions_iter {
+        revisions.push(revision?);
+    }
+    Ok(revisions)
+}
+
+// Replaces the stored entry with a newly signed version and keeps the new
+// event as the latest revision. Runs inside the caller's transaction so that
+// related rows, e.g. attachments, change together with the entry. Fails with
+// QueryReturnedNoRows if the entry was trashed while it was being signed.
+pub fn save_updated_entry(
+    tx: &Transaction,
+    entry: &DiaryEntry,
+    nostr_event_json: &str,
+    manual_tags: &[String],
+) -> SqlResult<()> {
+    let updated_at = entry.updated_at.unwrap_or_else(Utc::now);
+
+    let updated = tx.execute(
+        "UPDATE diary_entries
+         SET content = ?1, weather = ?2, nostr_id = ?3, nostr_event = ?4, updated_at = ?5,
+             private = ?6
+         WHERE id = ?7 AND deleted_at IS NULL",
+        params![
+            entry.content,
+            entry.weather,
+            entry.nostr_id,
+            nostr_event_json,
+            updated_at.to_rfc3339(),
+            entry.private,
+            entry.id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+
+    record_revision(
+        tx,
+        &entry.id,
+        entry.nostr_id.as_deref(),
+        nostr_event_json,
+        &entry.content,
+        &entry.weather,
+        &updated_at,
+    )?;
+    crate::search_service::index_entry(tx, &entry.id, &entry.content, &entry.weather)?;
+    crate::tag_service::save_entry_tags(tx, &entry.id, &entry.content, manual_tags)
+}
+
+// Error message for a failed save_updated_entry
+pub fn save_error(context: &str, e: rusqlite::Error) -> String {
+    match e {
+        rusqlite::Error::QueryReturnedNoRows => {
+            format!("{}: the entry was moved to the trash", context)
+        }
+        e => format!("{}: {}", context, e),
+    }
+}
+
+// Signs a new version of an existing entry with its tags and the imeta tags
+// of its attachments. Returns the updated entry and its event JSON, which
+// the caller stores with save_updated_entry and then hands to
+// publish_revision.
+pub async fn sign_revision(
+    store: &Arc<DiaryStore>,
+    existing: DiaryEntry,
+    content: String,
+    weather: String,
+    attachments: &[crate::attachment_service::Attachment],
+    manual_tags: &[String],
+) -> Result<(DiaryEntry, String), String> {
+    let keys = crate::get_or_create_nostr_keys(store)?;
+    let mut extra_tags: Vec<Tag> = crate::tag_service::combine_tags(&content, manual_tags)
+        .iter()
+        .map(Tag::hashtag)
+        .collect();
+    extra_tags.extend(crate::attachment_service::imeta_tags(attachments)?);
+    // The entry keeps the offset it was first written at
+    if let Some(utc_offset) = &existing.utc_offset {
+        extra_tags.push(crate::timezone::offset_tag(utc_offset)?);
+    }
+    let event = crate::sign_diary_event(
+        &keys,
+        &content,
+        &weather,
+        &existing.day,
+        extra_tags,
+        existing.private,
+    )
+    .await?;
+    let nostr_event_json = serde_json::to_string(&event)
+        .map_err(|e| format!("Failed to serialize Nostr event: {}", e))?;
+
+    let entry = DiaryEntry {
+        content,
+        weather,
+        nostr_id: Some(event.id.to_string()),
+        updated_at: Some(Utc::now()),
+        ..existing
+    };
+    Ok((entry, nostr_event_json))
+}
+
+// Publishes a revision once save_updated_entry has stored it. While the
+// entry is held for publish_pending_entries, the revision is held as well,
+// so editing it does not publish it early.
+pub async fn publish_revision(store: &DiaryStore, nostr_event_json: &str) {
+    let event = match Event::from_json(nostr_event_json) {
+        Ok(event) => event,
+        Err(e) => {
+            println!("Failed to parse revision event: {}", e);
+            return;
+        }
+    };
+    let queued = event.clone();
+    let held = store
+        .db
+        .call(move |conn| {
+            crate::outbox::is_held(conn, &queued)
+                .map_err(|e| format!("Failed to load outbox: {}", e))
+        })
+        .await;
+    match held {
+        Ok(held) => crate::publish_saved_event(&event, held, store).await,
+        Err(e) => println!("Failed to publish revision: {}", e),
+    }
+}
+
+// Edits the entry of a day. Kind 30027 is replaceable on the `d` tag, so the
+// re-signed event supersedes the previous one on relays, while every prior
+// signed event stays in diary_entry_revisions.
+#[tauri::command]
+pub async fn update_diary_entry(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+    content: String,
+    weather: String,
+) -> Result<DiaryEntry, String> {
+    if content.trim().is_empty() {
+        return Err("Content cannot be empty".to_string());
+    }
+
+    let existing = {
+        let day = day.clone();
+        store
+            .db
+            .call(move |conn| {
+                find_entry_for_day(conn, &day)
+                    .map_err(|e| format!("Failed to load entry: {}", e))?
+                    .ok_or_else(|| format!("No entry exists for day: {}", day))
+            })
+            .await?
+    };
+
+    if existing.content == content && existing.weather == weather {
+        return Ok(existing);
+    }
+
+    let (attachments, manual_tags) = {
+        let entry_id = existing.id.clone();
+        store
+            .db
+            .call(move |conn| {
+                let attachments = crate::attachment_service::load_attachments(conn, &entry_id)
+                    .map_err(|e| format!("Failed to load attachments: {}", e))?;
+                let manual_tags = crate::tag_service::load_manual_tags(conn, &entry_id)
+                    .map_err(|e| format!("Failed to load tags: {}", e))?;
+                Ok((attachments, manual_tags))
+            })
+            .await?
+    };
+    let (entry, nostr_event_json) = sign_revision(
+        &store,
+        existing,
+        content,
+        weather,
+        &attachments,
+        &manual_tags,
+    )
+    .await?;
+
+    let (updated, event_json) = (entry.clone(), nostr_event_json.clone());
+    store
+        .db
+        .call(move |conn| {
+            let tx = conn
+                .transaction()
+                .map_err(|e| format!("Failed to start transaction: {}", e))?;
+            save_updated_entry(&tx, &updated, &event_json, &manual_tags)
+                .and_then(|_| tx.commit())
+                .map_err(|e| save_error("Failed to save updated entry", e))
+        })
+        .await?;
+    publish_revision(&store, &nostr_event_json).await;
+
+    println!("Updated diary entry for day: {}", day);
+    Ok(entry)
+}
+
+// Switches the entry of a day between public and private and re-signs it.
+// The new event replaces the old one on relays that honour kind 30027
+// replacement, but copies of a public event may have been kept elsewhere.
+#[tauri::command]
+pub async fn set_entry_visibility(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+    private: bool,
+) -> Result<DiaryEntry, String> {
+    let (existing, attachments, manual_tags) = {
+        let day = day.clone();
+        store
+            .db
+            .call(move |conn| {
+                let entry = find_entry_for_day(conn, &day)
+                    .map_err(|e| format!("Failed to load entry: {}", e))?
+                    .ok_or_else(|| format!("No entry exists for day: {}", day))?;
+                let attachments = crate::attachment_service::load_attachments(conn, &entry.id)
+                    .map_err(|e| format!("Failed to load attachments: {}", e))?;
+                let manual_tags = crate::tag_service::load_manual_tags(conn, &entry.id)
+                    .map_err(|e| format!("Failed to load tags: {}", e))?;
+                Ok((entry, attachments, manual_tags))
+            })
+            .await?
+    };
+
+    if existing.private == private {
+        return Ok(existing);
+    }
+
+    let (content, weather) = (existing.content.clone(), existing.weather.clone());
+    let existing = DiaryEntry {
+        private,
+        ..existing
+    };
+    let (entry, nostr_event_json) = sign_revision(
+        &store,
+        existing,
+        content,
+        weather,
+        &attachments,
+        &manual_tags,
+    )
+    .await?;
+
+    let (updated, event_json) = (entry.clone(), nostr_event_json.clone());
+    store
+        .db
+        .call(move |conn| {
+            let tx = conn
+                .transaction()
+                .map_err(|e| format!("Failed to start transaction: {}", e))?;
+            save_updated_entry(&tx, &updated, &event_json, &manual_tags)
+                .and_then(|_| tx.commit())
+                .map_err(|e| save_error("Failed to save entry visibility", e))
+        })
+        .await?;
+    publish_revision(&store, &nostr_event_json).await;
+
+    println!(
+        "Made entry for day {} {}",
+        day,
+        if private { "private" } else { "public" }
+    );
+    Ok(entry)
+}
+
+fn load_revisions_for_day(conn: &Connection, day: &str) -> Result<Vec<EntryRevision>, String> {
+    let entry = find_entry_for_day(conn, day)
+        .map_err(|e| format!("Failed to load entry: {}", e))?
+        .ok_or_else(|| format!("No entry exists for day: {}", day))?;
+
+    load_revisions(conn, &entry.id).map_err(|e| format!("Failed to load revisions: {}", e))
+}
+
+#[tauri::command]
+pub async fn list_entry_revisions(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+) -> Result<Vec<EntryRevision>, String> {
+    store
+        .db
+        .call(move |conn| load_revisions_for_day(conn, &day))
+        .await
+}
+
+// Character-level diff of two contents, merged into runs of the same kind
+fn diff_contents(before: &str, after: &str) -> Vec<DiffSegment> {
+    let diff = TextDiff::configure()
+        .algorithm(Algorithm::Patience)
+        .diff_chars(before, after);
+
+    let mut changes: Vec<DiffSegment> = Vec::new();
+    for change in diff.iter_all_changes() {
+        let kind = match change.tag() {
+            ChangeTag::Equal => "equal",
+            ChangeTag::Insert => "insert",
+            ChangeTag::Delete => "delete",
+        };
+        match changes.last_mut() {
+            Some(last) if last.kind == kind => last.text.push_str(change.value()),
+            _ => changes.push(DiffSegment {
+                kind: kind.to_string(),
+                text: change.value().to_string(),
+            }),
+        }
+    }
+
+    changes
+}
+
+// Character-level diff of the content of two revisions
+#[tauri::command]
+pub async fn diff_entry_revisions(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+    from_revision: u32,
+    to_revision: u32,
+) -> Result<RevisionDiff, String> {
+    let revisions = {
+        let day = day.clone();
+        store
+            .db
+            .call(move |conn| load_revisions_for_day(conn, &day))
+            .await?
+    };
+    let find = |revision: u32| {
+        revisions
+            .iter()
+            .find(|r| r.revision == revision)
+            .ok_or_else(|| format!("Revision {} not found for day: {}", revision, day))
+    };
+    let from = find(from_revision)?;
+    let to = find(to_revision)?;
+
+    Ok(RevisionDiff {
+        from_revision,
+        to_revision,
+        weather_before: from.weather.clone(),
+        weather_after: to.weather.clone(),
+        changes: diff_contents(&from.content, &to.content),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO diary_entries (id, content, weather, created_at, nostr_id, day)
+             VALUES ('a', '早上下雨', '雨', '2024-03-01T08:00:00+00:00', 'event-1', '2024-03-01');
+             INSERT INTO diary_entry_revisions
+                (entry_id, revision, nostr_id, content, weather, created_at)
+             VALUES ('a', 1, 'event-1', '早上下雨', '雨', '2024-03-01T08:00:00+00:00');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn edit(conn: &mut Connection, entry: DiaryEntry, content: &str, id: &str) -> SqlResult<()> {
+        let entry = DiaryEntry {
+            content: content.to_string(),
+            weather: "晴".to_string(),
+            nostr_id: Some(id.to_string()),
+            updated_at: Some(Utc::now()),
+            ..entry
+        };
+        let tx = conn.transaction()?;
+        save_updated_entry(&tx, &entry, "{}", &["散步".to_string()])?;
+        tx.commit()
+    }
+
+    #[test]
+    fn test_update_appends_a_revision() {
+        let mut conn = setup();
+        let entry = find_entry_for_day(&conn, "2024-03-01").unwrap().unwrap();
+        edit(&mut conn, entry, "下午放晴 #心情", "event-2").unwrap();
+
+        let entry = find_entry_for_day(&conn, "2024-03-01").unwrap().unwrap();
+        assert_eq!(entry.content, "下午放晴 #心情");
+        assert_eq!(entry.nostr_id.as_deref(), Some("event-2"));
+        let revisions = load_revisions_for_day(&conn, "2024-03-01").unwrap();
+        let numbers: Vec<u32> = revisions.iter().map(|r| r.revision).collect();
+        assert_eq!(numbers, vec![1, 2]);
+        assert_eq!(revisions[0].content, "早上下雨");
+        assert_eq!(revisions[1].content, "下午放晴 #心情");
+        assert_eq!(revisions[1].nostr_id.as_deref(), Some("event-2"));
+
+        let tags = crate::tag_service::load_entry_tags(&conn, "a").unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(
+            crate::tag_service::load_manual_tags(&conn, "a").unwrap(),
+            vec!["散步"]
+        );
+    }
+
+    #[test]
+    fn test_trashed_entry_is_not_updated() {
+        let mut conn = setup();
+        let entry = find_entry_for_day(&conn, "2024-03-01").unwrap().unwrap();
+        // Trashed while the new version was being signed
+        conn.execute("UPDATE diary_entries SET deleted_at = 'now'", [])
+            .unwrap();
+
+        let e = edit(&mut conn, entry, "下午放晴", "event-2").unwrap_err();
+        assert_eq!(
+            save_error("Failed to save updated entry", e),
+            "Failed to save updated entry: the entry was moved to the trash"
+        );
+        let (content, revisions): (String, i64) = conn
+            .query_row(
+                "SELECT content, (SELECT COUNT(*) FROM diary_entry_revisions)
+                 FROM diary_entries WHERE id = 'a'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(content, "早上下雨");
+        assert_eq!(revisions, 1);
+        assert!(load_revisions_for_day(&conn, "2024-03-01").is_err());
+    }
+
+    #[test]
+    fn test_diff_merges_changes_into_runs() {
+        let changes: Vec<(String, String)> = diff_contents("今天下雨，很冷", "今天放晴，很冷")
+            .into_iter()
+            .map(|c| (c.kind, c.text))
+            .collect();
+        let runs: Vec<(&str, &str)> = changes
+            .iter()
+            .map(|(kind, text)| (kind.as_str(), text.as_str()))
+            .collect();
+        assert_eq!(
+            runs,
+            vec![
+                ("equal", "今天"),
+                ("delete", "下雨"),
+                ("insert", "放晴"),
+                ("equal", "，很冷")
+            ]
+        );
+        assert!(diff_contents("", "").is_empty());
+    }
+}
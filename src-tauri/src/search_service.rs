@@ -0,0 +1,475 @@
+use chrono::{Datelike, NaiveDate};
+use rusqlite::{params_from_iter, types::Value, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tauri::State;
+
+use crate::{DiaryEntry, DiaryStore};
+
+// Maximum number of characters shown on each side of the first match
+const SNIPPET_CONTEXT_CHARS: usize = 30;
+const DEFAULT_SEARCH_LIMIT: u32 = 50;
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SearchResult {
+    pub entry: DiaryEntry,
+    pub snippet: String, // HTML-escaped excerpt with matches wrapped in <mark>
+    pub rank: f64,       // bm25 score, lower is more relevant
+}
+
+// Parsed form of a search string such as `上午 图书馆 weather:雨 from:2024-01-01`
+#[derive(Debug, Default, PartialEq)]
+struct SearchQuery {
+    terms: Vec<String>,
+    weather: Option<String>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF     // Hiragana, Katakana
+        | 0x3400..=0x4DBF   // CJK Extension A
+        | 0x4E00..=0x9FFF   // CJK Unified Ideographs
+        | 0xAC00..=0xD7AF   // Hangul syllables
+        | 0xF900..=0xFAFF   // CJK Compatibility Ideographs
+        | 0x20000..=0x2FA1F // CJK Extensions B-F and supplements
+    )
+}
+
+// Splits text into index tokens. Runs of CJK characters become overlapping
+// bigrams followed by the last character on its own, so that any substring of
+// two or more characters is a phrase of consecutive bigrams and any single
+// character is a prefix of some token. Other alphanumeric runs become
+// lowercase words. Everything else is treated as a separator.
+pub fn tokenize(text: &str) -> Vec<String> {
+    tokenize_with(text, true)
+}
+
+// Query-side tokenization drops the trailing single character of longer CJK
+// runs, since it is already covered by the last bigram and would break
+// phrase matching when the run continues in the indexed text.
+fn tokenize_with(text: &str, keep_run_tail: bool) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut cjk_run: Vec<char> = Vec::new();
+    let mut word = String::new();
+
+    let flush_cjk = |run: &mut Vec<char>, tokens: &mut Vec<String>| {
+        for pair in run.windows(2) {
+            tokens.push(pair.iter().collect());
+        }
+        if run.len() == 1 || (keep_run_tail && !run.is_empty()) {
+            tokens.push(run[run.len() - 1].to_string());
+        }
+        run.clear();
+    };
+
+    fn flush_word(word: &mut String, tokens: &mut Vec<String>) {
+        if !word.is_empty() {
+            tokens.push(word.to_lowercase());
+            word.clear();
+        }
+    }
+
+    for c in text.chars() {
+        if is_cjk(c) {
+            flush_word(&mut word, &mut tokens);
+            cjk_run.push(c);
+        } else if c.is_alphanumeric() {
+            flush_cjk(&mut cjk_run, &mut tokens);
+            word.push(c);
+        } else {
+            flush_cjk(&mut cjk_run, &mut tokens);
+            flush_word(&mut word, &mut tokens);
+        }
+    }
+    flush_cjk(&mut cjk_run, &mut tokens);
+    flush_word(&mut word, &mut tokens);
+
+    tokens
+}
+
+fn tokenized_text(text: &str) -> String {
+    tokenize(text).join(" ")
+}
+
+// Builds an FTS5 expression matching `term` as a substring. A lone CJK
+// character is matched as a token prefix since it is never a full bigram.
+fn term_expression(term: &str) -> Option<String> {
+    let chars: Vec<char> = term.chars().collect();
+    if chars.len() == 1 && is_cjk(chars[0]) {
+        return Some(format!("\"{}\" *", term));
+    }
+
+    let tokens = tokenize_with(term, false);
+    if tokens.is_empty() {
+        None
+    } else {
+        Some(format!("\"{}\"", tokens.join(" ")))
+    }
+}
+
+fn parse_query(query: &str) -> Result<SearchQuery, String> {
+    let mut parsed = SearchQuery::default();
+
+    for part in query.split_whitespace() {
+        if let Some(weather) = part.strip_prefix("weather:") {
+            if !weather.is_empty() {
+                parsed.weather = Some(weather.to_string());
+            }
+        } else if let Some(day) = part.strip_prefix("from:") {
+            parsed.from = Some(parse_filter_day(day)?);
+        } else if let Some(day) = part.strip_prefix("to:") {
+            parsed.to = Some(parse_filter_day(day)?);
+        } else {
+            parsed.terms.push(part.to_string());
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn parse_filter_day(day: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .map_err(|e| format!("Invalid date filter '{}': {}", day, e))
+}
+
+fn build_match_expression(query: &SearchQuery) -> Option<String> {
+    let mut clauses: Vec<String> = query
+        .terms
+        .iter()
+        .filter_map(|term| term_expression(term))
+        .collect();
+
+    if let Some(weather) = &query.weather {
+        if let Some(expression) = term_expression(weather) {
+            clauses.push(format!("weather : {}", expression));
+        }
+    }
+
+    if clauses.is_empty() {
+        None
+    } else {
+        Some(clauses.join(" AND "))
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Builds a highlighted excerpt around the first occurrence of any term.
+// Matching is done on the original content so the snippet reads naturally.
+fn build_snippet(content: &str, terms: &[String]) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let lowered: Vec<char> = content.to_lowercase().chars().collect();
+    // Lowercasing can change the length of some scripts; fall back to the
+    // original characters so the offsets stay aligned.
+    let haystack = if lowered.len() == chars.len() {
+        &lowered
+    } else {
+        &chars
+    };
+
+    let needles: Vec<Vec<char>> = terms
+        .iter()
+        .map(|term| term.to_lowercase().chars().collect::<Vec<char>>())
+        .filter(|needle| !needle.is_empty())
+        .collect();
+
+    let mut matches: Vec<(usize, usize)> = Vec::new();
+    let mut i = 0;
+    while i < haystack.len() {
+        let found = needles
+            .iter()
+            .filter(|needle| haystack[i..].starts_with(needle))
+            .map(|needle| needle.len())
+            .max();
+        match found {
+            Some(len) => {
+                matches.push((i, i + len));
+                i += len;
+            }
+            None => i += 1,
+        }
+    }
+
+    let (first_start, first_end) = matches.first().copied().unwrap_or((0, 0));
+    let start = first_start.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let end = (first_end + SNIPPET_CONTEXT_CHARS).min(chars.len());
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut cursor = start;
+    for (match_start, match_end) in matches.iter().filter(|(s, e)| *s >= start && *e <= end) {
+        snippet.push_str(&escape_html(
+            &chars[cursor..*match_start].iter().collect::<String>(),
+        ));
+        snippet.push_str("<mark>");
+        snippet.push_str(&escape_html(
+            &chars[*match_start..*match_end].iter().collect::<String>(),
+        ));
+        snippet.push_str("</mark>");
+        cursor = *match_end;
+    }
+    snippet.push_str(&escape_html(&chars[cursor..end].iter().collect::<String>()));
+    if end < chars.len() {
+        snippet.push('…');
+    }
+
+    snippet
+}
+
+// Replaces the index row of an entry. Call this whenever an entry is written.
+pub fn index_entry(
+    conn: &Connection,
+    entry_id: &str,
+    content: &str,
+    weather: &str,
+) -> SqlResult<()> {
+    remove_entry_from_index(conn, entry_id)?;
+    conn.execute(
+        "INSERT INTO diary_entries_fts (entry_id, content, weather) VALUES (?1, ?2, ?3)",
+        [entry_id, &tokenized_text(content), &tokenized_text(weather)],
+    )?;
+    Ok(())
+}
+
+pub fn remove_entry_from_index(conn: &Connection, entry_id: &str) -> SqlResult<()> {
+    conn.execute(
+        "DELETE FROM diary_entries_fts WHERE entry_id = ?1",
+        [entry_id],
+    )?;
+    Ok(())
+}
+
+// Rebuilds the whole index from diary_entries
+pub fn rebuild_index(conn: &Connection) -> SqlResult<()> {
+    conn.execute("DELETE FROM diary_entries_fts", [])?;
+
+    let mut stmt = conn.prepare("SELECT id, content, weather FROM diary_entries")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    })?;
+
+    for row in rows {
+        let (id, content, weather) = row?;
+        index_entry(conn, &id, &content, &weather)?;
+    }
+    Ok(())
+}
+
+pub fn search_entries(
+    conn: &Connection,
+    query: &str,
+    limit: u32,
+) -> Result<Vec<SearchResult>, String> {
+    let parsed = parse_query(query)?;
+    let match_expression = build_match_expression(&parsed);
+
+    if match_expression.is_none() && parsed.from.is_none() && parsed.to.is_none() {
+        return Err("Search query is empty".to_string());
+    }
+
+    let mut conditions = vec!["e.deleted_at IS NULL".to_string()];
+    let mut values: Vec<Value> = Vec::new();
+
+    if let Some(expression) = &match_expression {
+        conditions.push("diary_entries_fts MATCH ?".to_string());
+        values.push(Value::Text(expression.clone()));
+    }
+    if let Some(from) = parsed.from {
+        conditions.push("(e.year, e.month, e.day_of_month) >= (?, ?, ?)".to_string());
+        values.extend(date_values(from));
+    }
+    if let Some(to) = parsed.to {
+        conditions.push("(e.year, e.month, e.day_of_month) <= (?, ?, ?)".to_string());
+        values.extend(date_values(to));
+    }
+    values.push(Value::Integer(limit as i64));
+
+    // bm25 is only defined when a MATCH is part of the query
+    let rank_column = if match_expression.is_some() {
+        "bm25(diary_entries_fts)"
+    } else {
+        "0.0"
+    };
+
+    let sql = format!(
+        "SELECT e.id, e.content, e.weather, e.created_at, e.nostr_id, e.day, e.updated_at,
+                e.utc_offset, e.private, {} AS rank
+         FROM diary_entries_fts
+         JOIN diary_entries e ON e.id = diary_entries_fts.entry_id
+         WHERE {}
+         ORDER BY rank, e.year DESC, e.month DESC, e.day_of_month DESC
+         LIMIT ?",
+        rank_column,
+        conditions.join(" AND ")
+    );
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| format!("Failed to prepare search: {}", e))?;
+    let rows = stmt
+        .query_map(params_from_iter(values), |row| {
+            Ok((crate::entry_from_row(row)?, row.get::<_, f64>(9)?))
+        })
+        .map_err(|e| format!("Failed to search entries: {}", e))?;
+
+    let mut highlight_terms = parsed.terms.clone();
+    if let Some(weather) = &parsed.weather {
+        highlight_terms.push(weather.clone());
+    }
+
+    let mut results = Vec::new();
+    for row in rows {
+        let (entry, rank) = row.map_err(|e| format!("Failed to read search result: {}", e))?;
+        let snippet = build_snippet(&entry.content, &highlight_terms);
+        results.push(SearchResult {
+            entry,
+            snippet,
+            rank,
+        });
+    }
+
+    Ok(results)
+}
+
+fn date_values(date: NaiveDate) -> [Value; 3] {
+    [
+        Value::Integer(date.year() as i64),
+        Value::Integer(date.month() as i64),
+        Value::Integer(date.day() as i64),
+    ]
+}
+
+// Full-text search over diary content and weather.
+// Supports `weather:雨`, `from:YYYY-MM-DD` and `to:YYYY-MM-DD` filters.
+#[tauri::command]
+pub async fn search_diary_entries(
+    store: State<'_, Arc<DiaryStore>>,
+    query: String,
+    limit: Option<u32>,
+) -> Result<Vec<SearchResult>, String> {
+    store
+        .db
+        .call(move |conn| search_entries(conn, &query, limit.unwrap_or(DEFAULT_SEARCH_LIMIT)))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indexed_connection(entries: &[(&str, &str, &str)]) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        for (id, day, content) in entries {
+            conn.execute(
+                "INSERT INTO diary_entries (id, content, weather, created_at, day)
+                 VALUES (?1, ?2, '晴', '2024-01-01T00:00:00+00:00', ?3)",
+                [id, content, day],
+            )
+            .unwrap();
+            index_entry(&conn, id, content, "晴").unwrap();
+        }
+        conn
+    }
+
+    fn found_ids(conn: &Connection, query: &str) -> Vec<String> {
+        search_entries(conn, query, 10)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.entry.id)
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize_mixed_cjk_and_ascii() {
+        assert_eq!(
+            tokenize("今天读Rust书, ok-42"),
+            vec!["今天", "天读", "读", "rust", "书", "ok", "42"]
+        );
+        assert_eq!(tokenize_with("图书馆", false), vec!["图书", "书馆"]);
+        assert!(tokenize("，。！ ...").is_empty());
+    }
+
+    #[test]
+    fn test_term_expression_quotes_every_term() {
+        assert_eq!(term_expression("雨"), Some("\"雨\" *".to_string()));
+        assert_eq!(term_expression("图书馆"), Some("\"图书 书馆\"".to_string()));
+        assert_eq!(term_expression("say\"hi\""), Some("\"say hi\"".to_string()));
+        // FTS5 operators and syntax end up as plain words or separators
+        assert_eq!(term_expression("NEAR(a"), Some("\"near a\"".to_string()));
+        assert_eq!(term_expression("rust*"), Some("\"rust\"".to_string()));
+        assert_eq!(term_expression("-"), None);
+        assert_eq!(term_expression("\"\""), None);
+    }
+
+    #[test]
+    fn test_search_with_operators_and_quotes() {
+        let conn = indexed_connection(&[
+            ("a", "2024-01-01", "上午往图书馆 AND read"),
+            ("b", "2024-01-02", "下午大雨"),
+        ]);
+
+        assert_eq!(found_ids(&conn, "图书"), vec!["a"]);
+        assert_eq!(found_ids(&conn, "雨"), vec!["b"]);
+        assert_eq!(found_ids(&conn, "AND"), vec!["a"]);
+        assert_eq!(found_ids(&conn, "\"图书馆\" OR"), Vec::<String>::new());
+        assert_eq!(found_ids(&conn, "read* NOT"), Vec::<String>::new());
+        assert_eq!(found_ids(&conn, "from:2024-01-02"), vec!["b"]);
+    }
+
+    #[test]
+    fn test_empty_query_is_rejected() {
+        let conn = indexed_connection(&[]);
+        for query in ["", "   ", "*", "\"\" -", "weather:"] {
+            assert_eq!(
+                search_entries(&conn, query, 10).unwrap_err(),
+                "Search query is empty"
+            );
+        }
+        assert!(search_entries(&conn, "from:2024-13-01", 10).is_err());
+    }
+
+    #[test]
+    fn test_snippet_cuts_on_character_boundaries() {
+        let content = format!("{}图书馆{}", "晴".repeat(40), "雨".repeat(40));
+        let snippet = build_snippet(&content, &["图书馆".to_string()]);
+        assert_eq!(
+            snippet,
+            format!(
+                "…{}<mark>图书馆</mark>{}…",
+                "晴".repeat(SNIPPET_CONTEXT_CHARS),
+                "雨".repeat(SNIPPET_CONTEXT_CHARS)
+            )
+        );
+
+        // No ellipsis when the match is near the start or end
+        assert_eq!(
+            build_snippet("图书馆<b>", &["图书".to_string()]),
+            "<mark>图书</mark>馆&lt;b&gt;"
+        );
+        // Without a match the snippet is the start of the content
+        let snippet = build_snippet(&"晴".repeat(100), &["雪".to_string()]);
+        assert_eq!(snippet, format!("{}…", "晴".repeat(SNIPPET_CONTEXT_CHARS)));
+    }
+
+    #[test]
+    fn test_snippet_matches_ignore_case() {
+        assert_eq!(
+            build_snippet("Read Rust 书", &["rust".to_string(), "书".to_string()]),
+            "Read <mark>Rust</mark> <mark>书</mark>"
+        );
+    }
+}
@@ -0,0 +1,386 @@
+use rusqlite::{params, Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tauri::State;
+
+use crate::revision_service::{
+    find_entry_for_day, publish_revision, save_error, save_updated_entry, sign_revision,
+};
+use crate::DiaryStore;
+
+const MAX_TAG_CHARS: usize = 64;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct TagCount {
+    pub name: String,
+    pub count: u32,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct EntryTag {
+    pub name: String,
+    pub manual: bool, // set explicitly rather than taken from a #hashtag in the content
+}
+
+// Tags are stored and published in lowercase without the leading `#`, the
+// form NIP-24 expects in `t` tags
+pub fn normalize_tag(raw: &str) -> Option<String> {
+    let tag = raw.trim().trim_matches('#').trim().to_lowercase();
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_CHARS {
+        None
+    } else {
+        Some(tag)
+    }
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+// Finds `#tag` and Weibo-style `#tag#` hashtags. A tag runs until whitespace,
+// punctuation or a closing `#`, so `#壬子日记，` yields 壬子日记. A `#` right
+// after an ASCII letter or digit, as in a URL fragment, does not start a tag,
+// while one after Chinese text does, since it is not separated by spaces.
+// Tags made only of digits are ignored.
+pub fn extract_hashtags(content: &str) -> Vec<String> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut tags: Vec<String> = Vec::new();
+
+    let mut i = 0;
+    while i < chars.len() {
+        let starts_tag = chars[i] == '#'
+            && (i == 0 || !(chars[i - 1].is_ascii_alphanumeric() || chars[i - 1] == '_'));
+        if !starts_tag {
+            i += 1;
+            continue;
+        }
+
+        let start = i + 1;
+        let mut end = start;
+        while end < chars.len() && is_tag_char(chars[end]) {
+            end += 1;
+        }
+
+        let word: String = chars[start..end].iter().collect();
+        if !word.chars().all(|c| c.is_ascii_digit()) {
+            if let Some(tag) = normalize_tag(&word) {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+
+        // Step over the closing `#` of a `#tag#`
+        i = if chars.get(end) == Some(&'#') && end > start {
+            end + 1
+        } else {
+            end.max(i + 1)
+        };
+    }
+
+    tags
+}
+
+// All tags of an entry: the explicit ones first, then hashtags from the content
+pub fn combine_tags(content: &str, manual_tags: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in manual_tags
+        .iter()
+        .filter_map(|tag| normalize_tag(tag))
+        .chain(extract_hashtags(content))
+    {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+// Replaces the tags of an entry with the hashtags in `content` plus
+// `manual_tags`, dropping tag names no entry uses any more
+pub fn save_entry_tags(
+    conn: &Connection,
+    entry_id: &str,
+    content: &str,
+    manual_tags: &[String],
+) -> SqlResult<()> {
+    let manual: Vec<String> = manual_tags
+        .iter()
+        .filter_map(|tag| normalize_tag(tag))
+        .collect();
+
+    conn.execute(
+        "DELETE FROM entry_tags WHERE entry_id = ?1",
+        params![entry_id],
+    )?;
+    for tag in combine_tags(content, &manual) {
+        conn.execute(
+            "INSERT OR IGNORE INTO tags (name) VALUES (?1)",
+            params![tag],
+        )?;
+        conn.execute(
+            "INSERT INTO entry_tags (entry_id, tag_id, manual)
+             SELECT ?1, id, ?2 FROM tags WHERE name = ?3",
+            params![entry_id, manual.contains(&tag), tag],
+        )?;
+    }
+    remove_unused_tags(conn)
+}
+
+pub fn remove_entry_tags(conn: &Connection, entry_id: &str) -> SqlResult<()> {
+    conn.execute(
+        "DELETE FROM entry_tags WHERE entry_id = ?1",
+        params![entry_id],
+    )?;
+    remove_unused_tags(conn)
+}
+
+fn remove_unused_tags(conn: &Connection) -> SqlResult<()> {
+    conn.execute(
+        "DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM entry_tags)",
+        [],
+    )?;
+    Ok(())
+}
+
+// Indexes the hashtags of every entry, for entries written before tags existed
+pub fn rebuild_tags(conn: &Connection) -> SqlResult<()> {
+    let mut stmt = conn.prepare("SELECT id, content FROM diary_entries")?;
+    let entries = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+    for (entry_id, content) in entries {
+        save_entry_tags(conn, &entry_id, &content, &[])?;
+    }
+    Ok(())
+}
+
+pub fn load_entry_tags(conn: &Connection, entry_id: &str) -> SqlResult<Vec<EntryTag>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name, et.manual FROM entry_tags et
+         JOIN tags t ON t.id = et.tag_id
+         WHERE et.entry_id = ?1
+         ORDER BY et.manual DESC, t.name",
+    )?;
+    let tags_iter = stmt.query_map(params![entry_id], |row| {
+        Ok(EntryTag {
+            name: row.get(0)?,
+            manual: row.get(1)?,
+        })
+    })?;
+
+    let mut tags = Vec::new();
+    for tag in tags_iter {
+        tags.push(tag?);
+    }
+    Ok(tags)
+}
+
+pub fn load_manual_tags(conn: &Connection, entry_id: &str) -> SqlResult<Vec<String>> {
+    Ok(load_entry_tags(conn, entry_id)?
+        .into_iter()
+        .filter(|tag| tag.manual)
+        .map(|tag| tag.name)
+        .collect())
+}
+
+// Tags with the number of entries using them, most used first. Trashed
+// entries are not counted.
+fn load_tag_counts(conn: &Connection) -> SqlResult<Vec<TagCount>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name, COUNT(*) AS uses FROM tags t
+         JOIN entry_tags et ON et.tag_id = t.id
+         JOIN diary_entries e ON e.id = et.entry_id
+         WHERE e.deleted_at IS NULL
+         GROUP BY t.id
+         ORDER BY uses DESC, t.name",
+    )?;
+    let counts_iter = stmt.query_map([], |row| {
+        Ok(TagCount {
+            name: row.get(0)?,
+            count: row.get(1)?,
+        })
+    })?;
+
+    let mut counts = Vec::new();
+    for count in counts_iter {
+        counts.push(count?);
+    }
+    Ok(counts)
+}
+
+#[tauri::command]
+pub async fn list_tags(store: State<'_, Arc<DiaryStore>>) -> Result<Vec<TagCount>, String> {
+    store
+        .db
+        .call(|conn| load_tag_counts(conn).map_err(|e| format!("Failed to load tags: {}", e)))
+        .await
+}
+
+#[tauri::command]
+pub async fn get_entry_tags(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+) -> Result<Vec<EntryTag>, String> {
+    store.db.call(move |conn| load_day_tags(conn, &day)).await
+}
+
+// Tags of the entry of a day. Trashed entries are treated as missing.
+fn load_day_tags(conn: &Connection, day: &str) -> Result<Vec<EntryTag>, String> {
+    let entry = find_entry_for_day(conn, day)
+        .map_err(|e| format!("Failed to load entry: {}", e))?
+        .ok_or_else(|| format!("No entry exists for day: {}", day))?;
+    load_entry_tags(conn, &entry.id).map_err(|e| format!("Failed to load tags: {}", e))
+}
+
+// Sets the explicit tags of the entry of a day and re-signs it, since the
+// tags are published as `t` tags on the event. Hashtags in the content are
+// always kept.
+#[tauri::command]
+pub async fn set_entry_tags(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+    tags: Vec<String>,
+) -> Result<Vec<EntryTag>, String> {
+    let manual_tags: Vec<String> = tags.iter().filter_map(|tag| normalize_tag(tag)).collect();
+
+    let (existing, attachments, current_manual) = {
+        let day = day.clone();
+        store
+            .db
+            .call(move |conn| {
+                let entry = find_entry_for_day(conn, &day)
+                    .map_err(|e| format!("Failed to load entry: {}", e))?
+                    .ok_or_else(|| format!("No entry exists for day: {}", day))?;
+                let attachments = crate::attachment_service::load_attachments(conn, &entry.id)
+                    .map_err(|e| format!("Failed to load attachments: {}", e))?;
+                let manual = load_manual_tags(conn, &entry.id)
+                    .map_err(|e| format!("Failed to load tags: {}", e))?;
+                Ok((entry, attachments, manual))
+            })
+            .await?
+    };
+
+    let entry_id = existing.id.clone();
+    let mut sorted_current = current_manual;
+    let mut sorted_new = manual_tags.clone();
+    sorted_current.sort();
+    sorted_new.sort();
+    sorted_new.dedup();
+
+    if sorted_current != sorted_new {
+        let content = existing.content.clone();
+        let weather = existing.weather.clone();
+        let (updated, nostr_event_json) = sign_revision(
+            &store,
+            existing,
+            content,
+            weather,
+            &attachments,
+            &manual_tags,
+        )
+        .await?;
+
+        let event_json = nostr_event_json.clone();
+        store
+            .db
+            .call(move |conn| {
+                let tx = conn
+                    .transaction()
+                    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+                save_updated_entry(&tx, &updated, &event_json, &manual_tags)
+                    .and_then(|_| tx.commit())
+                    .map_err(|e| save_error("Failed to save tags", e))
+            })
+            .await?;
+        publish_revision(&store, &nostr_event_json).await;
+
+        println!("Updated tags for entry of day {}", day);
+    }
+
+    store
+        .db
+        .call(move |conn| {
+            load_entry_tags(conn, &entry_id).map_err(|e| format!("Failed to load tags: {}", e))
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chinese_hashtags() {
+        assert_eq!(
+            extract_hashtags("#壬子日记，晴。今天去了#图书馆#借书"),
+            vec!["壬子日记", "图书馆"]
+        );
+        assert_eq!(extract_hashtags("＃全角不算 #读书"), vec!["读书"]);
+    }
+
+    #[test]
+    fn test_hashtags_stop_at_punctuation() {
+        assert_eq!(
+            extract_hashtags("Read #rust, then #tauri! Maybe #nostr. (#sqlite)"),
+            vec!["rust", "tauri", "nostr", "sqlite"]
+        );
+        assert_eq!(extract_hashtags("#snake_case-tag."), vec!["snake_case-tag"]);
+    }
+
+    #[test]
+    fn test_url_fragments_and_numbers_are_not_tags() {
+        assert_eq!(
+            extract_hashtags("See https://example.com/page#section and issue #42 #a1"),
+            vec!["a1"]
+        );
+        assert!(extract_hashtags("C# and F# and #").is_empty());
+    }
+
+    #[test]
+    fn test_tags_are_case_folded_and_deduplicated() {
+        assert_eq!(
+            extract_hashtags("#Rust #rust #RUST #Tauri"),
+            vec!["rust", "tauri"]
+        );
+        assert_eq!(normalize_tag("  ##Rust# "), Some("rust".to_string()));
+        assert_eq!(normalize_tag("#"), None);
+        assert_eq!(normalize_tag(&"字".repeat(MAX_TAG_CHARS + 1)), None);
+    }
+
+    #[test]
+    fn test_manual_tags_come_first_without_duplicates() {
+        let manual = vec!["Travel".to_string(), "#rust".to_string(), " ".to_string()];
+        assert_eq!(
+            combine_tags("#Rust on the #train", &manual),
+            vec!["travel", "rust", "train"]
+        );
+    }
+
+    #[test]
+    fn test_trashed_entries_have_no_tags() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO diary_entries (id, content, weather, created_at, day, deleted_at)
+             VALUES ('a', '#读书', '晴', 'now', '2024-03-01', NULL),
+                    ('b', '#散步', '晴', 'now', '2024-03-02', 'now');",
+        )
+        .unwrap();
+        save_entry_tags(&conn, "a", "#读书", &[]).unwrap();
+        save_entry_tags(&conn, "b", "#散步", &["trip".to_string()]).unwrap();
+
+        let tags = load_day_tags(&conn, "2024-03-01").unwrap();
+        assert_eq!(
+            tags,
+            vec![EntryTag {
+                name: "读书".to_string(),
+                manual: false,
+            }]
+        );
+        assert!(load_day_tags(&conn, "2024-03-02").is_err());
+    }
+}
@@ -0,0 +1,339 @@
+// The timezone that decides which day an entry belongs to
+//
+// Config.timezone holds an IANA name such as "Asia/Shanghai". When it is not
+// set the system local zone is used. Entries record the UTC offset they were
+// written at, both in the utc_offset column and as a `utc_offset` tag on the
+// signed event.
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use chrono_tz::Tz;
+use nostr_sdk::Tag;
+use rusqlite::{Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tauri::State;
+
+use crate::DiaryStore;
+
+#[derive(Clone, Copy, Debug)]
+pub enum DiaryTimezone {
+    Named(Tz),
+    SystemLocal,
+}
+
+// An entry that was probably filed under its UTC day by older versions,
+// which always used the UTC date when no day was given
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct MisfiledEntry {
+    pub entry_id: String,
+    pub day: String,
+    pub created_at: DateTime<Utc>,
+    pub suggested_day: String,
+    pub suggested_day_taken: bool, // another entry already uses the suggested day
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TimezoneReport {
+    pub timezone: String,
+    pub today: String,
+    pub utc_offset: String,
+    pub misfiled: Vec<MisfiledEntry>,
+}
+
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.trim()
+        .parse::<Tz>()
+        .map_err(|_| format!("Unknown timezone: {}", name))
+}
+
+impl DiaryTimezone {
+    // An unknown name in the config falls back to the system zone rather
+    // than making every save fail
+    pub fn from_config(timezone: Option<&str>) -> DiaryTimezone {
+        match timezone.filter(|name| !name.trim().is_empty()) {
+            Some(name) => match parse_timezone(name) {
+                Ok(tz) => DiaryTimezone::Named(tz),
+                Err(e) => {
+                    println!("{}, using the system timezone", e);
+                    DiaryTimezone::SystemLocal
+                }
+            },
+            None => DiaryTimezone::SystemLocal,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            DiaryTimezone::Named(tz) => tz.name().to_string(),
+            DiaryTimezone::SystemLocal => "system".to_string(),
+        }
+    }
+
+    pub fn date_of(&self, instant: DateTime<Utc>) -> NaiveDate {
+        match self {
+            DiaryTimezone::Named(tz) => instant.with_timezone(tz).date_naive(),
+            DiaryTimezone::SystemLocal => instant.with_timezone(&Local).date_naive(),
+        }
+    }
+
+    // Offset from UTC at the given instant, e.g. "+08:00"
+    pub fn offset_at(&self, instant: DateTime<Utc>) -> String {
+        match self {
+            DiaryTimezone::Named(tz) => instant.with_timezone(tz).format("%:z").to_string(),
+            DiaryTimezone::SystemLocal => instant.with_timezone(&Local).format("%:z").to_string(),
+        }
+    }
+
+    // Today's day string and the current UTC offset
+    pub fn today(&self) -> (String, String) {
+        let now = Utc::now();
+        (
+            self.date_of(now).format("%Y-%m-%d").to_string(),
+            self.offset_at(now),
+        )
+    }
+}
+
+pub fn configured_timezone(store: &DiaryStore) -> DiaryTimezone {
+    let config = store.config.lock().unwrap();
+    DiaryTimezone::from_config(config.timezone.as_deref())
+}
+
+pub fn offset_tag(utc_offset: &str) -> Result<Tag, String> {
+    Tag::parse(vec!["utc_offset".to_string(), utc_offset.to_string()])
+        .map_err(|e| format!("Failed to create utc_offset tag: {}", e))
+}
+
+// Entries without a recorded offset whose day is the UTC date of their
+// creation time but not the local date. Entries filed on a day picked by hand
+// match neither date and are left out.
+pub fn find_misfiled_entries(
+    conn: &Connection,
+    timezone: DiaryTimezone,
+) -> SqlResult<Vec<MisfiledEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, day, created_at FROM diary_entries
+         WHERE utc_offset IS NULL AND deleted_at IS NULL
+         ORDER BY day",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<SqlResult<Vec<_>>>()?;
+
+    // Every stored day counts, including entries with an offset and trashed
+    // ones, which still occupy their day
+    let mut stmt = conn.prepare("SELECT day FROM diary_entries WHERE day IS NOT NULL")?;
+    let taken_days = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<SqlResult<HashSet<String>>>()?;
+
+    let mut misfiled = Vec::new();
+    for (entry_id, day, created_at) in rows {
+        let (Some(day), Ok(created_at)) = (day, DateTime::parse_from_rfc3339(&created_at)) else {
+            continue;
+        };
+        let created_at = created_at.with_timezone(&Utc);
+        let utc_day = created_at.date_naive().format("%Y-%m-%d").to_string();
+        let local_day = timezone.date_of(created_at).format("%Y-%m-%d").to_string();
+
+        if day == utc_day && day != local_day {
+            misfiled.push(MisfiledEntry {
+                entry_id,
+                day,
+                created_at,
+                suggested_day_taken: taken_days.contains(&local_day),
+                suggested_day: local_day,
+            });
+        }
+    }
+    Ok(misfiled)
+}
+
+#[tauri::command]
+pub fn get_today(store: State<Arc<DiaryStore>>) -> String {
+    configured_timezone(&store).today().0
+}
+
+// Lists entries that were probably filed under the wrong day before the
+// timezone setting existed. Nothing is moved automatically.
+#[tauri::command]
+pub async fn get_timezone_report(
+    store: State<'_, Arc<DiaryStore>>,
+) -> Result<TimezoneReport, String> {
+    let timezone = configured_timezone(&store);
+    let misfiled = store
+        .db
+        .call(move |conn| {
+            find_misfiled_entries(conn, timezone)
+                .map_err(|e| format!("Failed to check entry days: {}", e))
+        })
+        .await?;
+
+    let (today, utc_offset) = timezone.today();
+    Ok(TimezoneReport {
+        timezone: timezone.name(),
+        today,
+        utc_offset,
+        misfiled,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggested_day_taken_by_any_entry() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        // Written at 08:00 in Shanghai, filed under the UTC day before
+        conn.execute_batch(
+            "INSERT INTO diary_entries (id, content, weather, created_at, day, utc_offset, deleted_at)
+             VALUES ('a', '', '晴', '2024-03-01T23:00:00+00:00', '2024-03-01', NULL, NULL),
+                    ('b', '', '晴', '2024-03-03T23:00:00+00:00', '2024-03-03', NULL, NULL),
+                    ('c', '', '晴', '2024-03-05T23:00:00+00:00', '2024-03-05', NULL, NULL),
+                    ('d', '', '晴', '2024-03-02T01:00:00+00:00', '2024-03-02', '+08:00', NULL),
+                    ('e', '', '晴', '2024-03-04T01:00:00+00:00', '2024-03-04', NULL, 'now');",
+        )
+        .unwrap();
+
+        let timezone = DiaryTimezone::Named(parse_timezone("Asia/Shanghai").unwrap());
+        let taken: Vec<(String, bool)> = find_misfiled_entries(&conn, timezone)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.suggested_day, entry.suggested_day_taken))
+            .collect();
+        assert_eq!(
+            taken,
+            vec![
+                ("2024-03-02".to_string(), true),
+                ("2024-03-04".to_string(), true),
+                ("2024-03-06".to_string(), false),
+            ]
+        );
+    }
+
+    fn test_connection(rows: &str) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        conn.execute_batch(&format!(
+            "INSERT INTO diary_entries (id, content, weather, created_at, day) VALUES {};",
+            rows
+        ))
+        .unwrap();
+        conn
+    }
+
+    fn instant(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn suggested_days(conn: &Connection, timezone: DiaryTimezone) -> Vec<(String, String)> {
+        find_misfiled_entries(conn, timezone)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.entry_id, entry.suggested_day))
+            .collect()
+    }
+
+    #[test]
+    fn test_days_follow_daylight_saving_time() {
+        let timezone = DiaryTimezone::Named(parse_timezone("America/New_York").unwrap());
+        // Clocks went forward at 07:00 UTC on 2024-03-10 and back at 06:00
+        // UTC on 2024-11-03
+        assert_eq!(
+            timezone.offset_at(instant("2024-03-10T06:59:00Z")),
+            "-05:00"
+        );
+        assert_eq!(
+            timezone.offset_at(instant("2024-03-10T07:00:00Z")),
+            "-04:00"
+        );
+        assert_eq!(
+            timezone.offset_at(instant("2024-11-03T06:00:00Z")),
+            "-05:00"
+        );
+        assert_eq!(
+            timezone
+                .date_of(instant("2024-03-11T03:30:00Z"))
+                .to_string(),
+            "2024-03-10"
+        );
+        assert_eq!(
+            timezone
+                .date_of(instant("2024-11-04T04:30:00Z"))
+                .to_string(),
+            "2024-11-03"
+        );
+
+        // 04:30 UTC is the previous evening in winter but already the same
+        // day in summer
+        let conn = test_connection(
+            "('winter', '', '晴', '2024-03-09T04:30:00+00:00', '2024-03-09'),
+             ('summer', '', '晴', '2024-03-11T04:30:00+00:00', '2024-03-11'),
+             ('evening', '', '晴', '2024-03-12T03:30:00+00:00', '2024-03-12')",
+        );
+        assert_eq!(
+            suggested_days(&conn, timezone),
+            vec![
+                ("winter".to_string(), "2024-03-08".to_string()),
+                ("evening".to_string(), "2024-03-11".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_configured_timezone_overrides_the_system_one() {
+        let at = instant("2024-03-01T12:00:00Z");
+        // Whichever of the two the system zone is not
+        let name = if at.with_timezone(&Local).format("%:z").to_string() == "+14:00" {
+            "Pacific/Pago_Pago"
+        } else {
+            "Pacific/Kiritimati"
+        };
+        let timezone = DiaryTimezone::from_config(Some(name));
+        assert_eq!(timezone.name(), name);
+        assert_ne!(
+            timezone.offset_at(at),
+            DiaryTimezone::SystemLocal.offset_at(at)
+        );
+
+        let expected_day = if name == "Pacific/Kiritimati" {
+            "2024-03-02"
+        } else {
+            "2024-03-01"
+        };
+        assert_eq!(timezone.date_of(at).to_string(), expected_day);
+
+        // Unknown and blank names fall back to the system zone
+        assert_eq!(
+            DiaryTimezone::from_config(Some("Mars/Olympus")).name(),
+            "system"
+        );
+        assert_eq!(DiaryTimezone::from_config(Some(" ")).name(), "system");
+        assert_eq!(DiaryTimezone::from_config(None).name(), "system");
+    }
+
+    #[test]
+    fn test_entries_without_rfc3339_times_are_skipped() {
+        // scripts/migrate-jrnl writes naive ISO timestamps
+        let conn = test_connection(
+            "('naive', '', '晴', '2024-03-01T23:00:00', '2024-03-01'),
+             ('spaced', '', '晴', '2024-03-02 23:00:00', '2024-03-02'),
+             ('aware', '', '晴', '2024-03-03T23:00:00+00:00', '2024-03-03')",
+        );
+        let timezone = DiaryTimezone::Named(parse_timezone("Asia/Shanghai").unwrap());
+        assert_eq!(
+            suggested_days(&conn, timezone),
+            vec![("aware".to_string(), "2024-03-04".to_string())]
+        );
+    }
+}
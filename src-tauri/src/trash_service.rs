@@ -0,0 +1,319 @@
+use nostr_sdk::nips::nip01::Coordinate;
+use nostr_sdk::nips::nip09::EventDeletionRequest;
+use nostr_sdk::{EventBuilder, EventId, Kind};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tauri::State;
+
+use crate::{DiaryEntry, DiaryStore};
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct TrashedEntry {
+    pub entry: DiaryEntry,
+    pub deleted_at: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PurgeResult {
+    pub day: String,
+    pub deletion_event_id: String, // ID of the published NIP-09 kind 5 event
+    pub withdrawn_event_ids: Vec<String>,
+}
+
+fn find_trashed_entry(conn: &Connection, day: &str) -> SqlResult<Option<DiaryEntry>> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM diary_entries WHERE day = ?1 AND deleted_at IS NOT NULL",
+            crate::ENTRY_COLUMNS
+        ),
+        params![day],
+        crate::entry_from_row,
+    )
+    .optional()
+}
+
+// Every event ever signed for an entry, current and superseded revisions
+fn signed_event_ids(conn: &Connection, entry_id: &str) -> SqlResult<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT nostr_id FROM diary_entries WHERE id = ?1 AND nostr_id IS NOT NULL
+         UNION
+         SELECT nostr_id FROM diary_entry_revisions WHERE entry_id = ?1 AND nostr_id IS NOT NULL",
+    )?;
+    let ids_iter = stmt.query_map(params![entry_id], |row| row.get(0))?;
+
+    let mut ids = Vec::new();
+    for id in ids_iter {
+        ids.push(id?);
+    }
+    Ok(ids)
+}
+
+// Gift wraps made of any version of an entry
+fn gift_wrap_ids(conn: &Connection, entry_id: &str) -> SqlResult<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT gift_wrap_id FROM gift_wrap_shares WHERE entry_id = ?1")?;
+    let ids_iter = stmt.query_map(params![entry_id], |row| row.get(0))?;
+
+    let mut ids = Vec::new();
+    for id in ids_iter {
+        ids.push(id?);
+    }
+    Ok(ids)
+}
+
+// Removes the entry with everything recorded about it: revisions,
+// attachments, index rows, tags, gift wrap shares, and the queued deliveries
+// and relay receipts of its events and gift wraps
+fn delete_entry_permanently(conn: &mut Connection, entry_id: &str) -> SqlResult<()> {
+    let tx = conn.transaction()?;
+
+    let mut event_ids = signed_event_ids(&tx, entry_id)?;
+    event_ids.extend(gift_wrap_ids(&tx, entry_id)?);
+    for event_id in event_ids {
+        tx.execute("DELETE FROM outbox WHERE event_id = ?1", params![event_id])?;
+        tx.execute(
+            "DELETE FROM publish_receipts WHERE event_id = ?1",
+            params![event_id],
+        )?;
+    }
+    tx.execute(
+        "DELETE FROM gift_wrap_shares WHERE entry_id = ?1",
+        params![entry_id],
+    )?;
+    tx.execute(
+        "DELETE FROM diary_entry_revisions WHERE entry_id = ?1",
+        params![entry_id],
+    )?;
+    tx.execute(
+        "DELETE FROM attachments WHERE entry_id = ?1",
+        params![entry_id],
+    )?;
+    crate::attachment_service::remove_unreferenced_blobs(&tx)?;
+    crate::search_service::remove_entry_from_index(&tx, entry_id)?;
+    crate::tag_service::remove_entry_tags(&tx, entry_id)?;
+    tx.execute("DELETE FROM diary_entries WHERE id = ?1", params![entry_id])?;
+
+    tx.commit()
+}
+
+// Moves the entry of a day to the trash. It disappears from all listings but
+// still occupies its day until it is purged.
+#[tauri::command]
+pub async fn trash_diary_entry(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+) -> Result<(), String> {
+    if !store.repository.trash_entry(&day).await? {
+        return Err(format!("No entry exists for day: {}", day));
+    }
+
+    println!("Moved entry for day {} to trash", day);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn restore_diary_entry(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+) -> Result<(), String> {
+    if !store.repository.restore_entry(&day).await? {
+        return Err(format!("No trashed entry exists for day: {}", day));
+    }
+
+    println!("Restored entry for day {} from trash", day);
+    Ok(())
+}
+
+fn load_trashed_entries(conn: &Connection) -> Result<Vec<TrashedEntry>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {}, deleted_at FROM diary_entries
+             WHERE deleted_at IS NOT NULL
+             ORDER BY deleted_at DESC",
+            crate::ENTRY_COLUMNS
+        ))
+        .map_err(|e| format!("Failed to list trash: {}", e))?;
+
+    let entries_iter = stmt
+        .query_map([], |row| {
+            Ok(TrashedEntry {
+                entry: crate::entry_from_row(row)?,
+                deleted_at: row.get("deleted_at")?,
+            })
+        })
+        .map_err(|e| format!("Failed to list trash: {}", e))?;
+
+    let mut entries = Vec::new();
+    for entry in entries_iter {
+        entries.push(entry.map_err(|e| format!("Failed to read trashed entry: {}", e))?);
+    }
+    Ok(entries)
+}
+
+#[tauri::command]
+pub async fn list_trashed_entries(
+    store: State<'_, Arc<DiaryStore>>,
+) -> Result<Vec<TrashedEntry>, String> {
+    store.db.call(|conn| load_trashed_entries(conn)).await
+}
+
+// Permanently removes a trashed entry and asks relays to delete every event
+// signed for it, by ID and by its `30027:<pubkey>:<day>` coordinate (NIP-09)
+#[tauri::command]
+pub async fn purge_diary_entry(
+    store: State<'_, Arc<DiaryStore>>,
+    day: String,
+) -> Result<PurgeResult, String> {
+    let (entry, event_ids) = {
+        let day = day.clone();
+        store
+            .db
+            .call(move |conn| {
+                let entry = find_trashed_entry(conn, &day)
+                    .map_err(|e| format!("Failed to load trashed entry: {}", e))?
+                    .ok_or_else(|| format!("No trashed entry exists for day: {}", day))?;
+                let event_ids = signed_event_ids(conn, &entry.id)
+                    .map_err(|e| format!("Failed to load entry events: {}", e))?;
+                Ok((entry, event_ids))
+            })
+            .await?
+    };
+
+    let keys = crate::get_or_create_nostr_keys(&store)?;
+    crate::backup_service::backup_before(&store, "purge").await?;
+
+    // Imported entries may carry IDs that are not real Nostr event IDs
+    let withdrawn: Vec<EventId> = event_ids
+        .iter()
+        .filter_map(|id| EventId::from_hex(id).ok())
+        .collect();
+
+    let coordinate = Coordinate::new(Kind::from(30027), keys.public_key()).identifier(&day);
+    let request = EventDeletionRequest::new()
+        .ids(withdrawn.clone())
+        .coordinate(coordinate)
+        .reason("diary entry deleted");
+
+    let deletion_event = EventBuilder::delete(request)
+        .sign(&keys)
+        .await
+        .map_err(|e| format!("Failed to create deletion request: {}", e))?;
+
+    if let Err(e) = crate::outbox::publish_event(&deletion_event, false, &store).await {
+        println!("Failed to publish deletion request: {}", e);
+    }
+
+    store
+        .db
+        .call(move |conn| {
+            delete_entry_permanently(conn, &entry.id)
+                .map_err(|e| format!("Failed to delete entry from database: {}", e))
+        })
+        .await?;
+
+    println!("Purged entry for day {}", day);
+    Ok(PurgeResult {
+        day,
+        deletion_event_id: deletion_event.id.to_hex(),
+        withdrawn_event_ids: withdrawn.iter().map(|id| id.to_hex()).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_purge_removes_everything_recorded_about_the_entry() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO diary_entries (id, content, weather, created_at, nostr_id, day, deleted_at)
+             VALUES ('a', '#读书', '晴', 'now', 'event-a2', '2024-03-01', 'now'),
+                    ('b', '其他', '晴', 'now', 'event-b', '2024-03-02', NULL);
+             INSERT INTO diary_entry_revisions
+                (entry_id, revision, nostr_id, content, weather, created_at)
+             VALUES ('a', 1, 'event-a1', '', '晴', 'now'), ('a', 2, 'event-a2', '', '晴', 'now');
+             INSERT INTO gift_wrap_shares
+                (gift_wrap_id, entry_id, nostr_id, recipient_pubkey, created_at)
+             VALUES ('wrap-a', 'a', 'event-a1', 'pk', 'now');
+             INSERT INTO outbox (event_id, event_json, kind, relay_url, created_at)
+             VALUES ('event-a1', '{}', 30027, 'wss://r', 'now'),
+                    ('event-a2', '{}', 30027, 'wss://r', 'now'),
+                    ('wrap-a', '{}', 1059, 'wss://r', 'now'),
+                    ('event-b', '{}', 30027, 'wss://r', 'now');
+             INSERT INTO publish_receipts (event_id, relay_url, accepted, received_at)
+             VALUES ('event-a2', 'wss://r', 1, 'now'), ('event-b', 'wss://r', 1, 'now');
+             INSERT INTO attachments
+                (entry_id, sha256, file_name, mime_type, size_bytes, created_at)
+             VALUES ('a', 'only-a', 'a.txt', 'text/plain', 1, 'now'),
+                    ('a', 'shared', 's.txt', 'text/plain', 1, 'now'),
+                    ('b', 'shared', 's.txt', 'text/plain', 1, 'now');
+             INSERT INTO attachment_blobs (sha256, data)
+             VALUES ('only-a', x'61'), ('shared', x'73');",
+        )
+        .unwrap();
+        crate::tag_service::save_entry_tags(&conn, "a", "#读书", &[]).unwrap();
+
+        delete_entry_permanently(&mut conn, "a").unwrap();
+
+        assert_eq!(count(&conn, "diary_entries"), 1);
+        assert_eq!(count(&conn, "diary_entry_revisions"), 0);
+        assert_eq!(count(&conn, "gift_wrap_shares"), 0);
+        assert_eq!(count(&conn, "entry_tags"), 0);
+        assert_eq!(count(&conn, "tags"), 0);
+        let remaining: String = conn
+            .query_row("SELECT event_id FROM outbox", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, "event-b");
+        assert_eq!(count(&conn, "publish_receipts"), 1);
+        let blob: String = conn
+            .query_row("SELECT sha256 FROM attachment_blobs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(blob, "shared");
+    }
+
+    #[test]
+    fn test_trash_lists_legacy_and_new_entries() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn, None).unwrap();
+        conn.execute_batch(
+            "INSERT INTO diary_entries (id, content, weather, created_at, day, deleted_at)
+             VALUES ('old', '旧', '晴', '2020-01-01T00:00:00+00:00', '2020-01-01',
+                     '2024-03-01T10:00:00+00:00');
+             INSERT INTO diary_entries
+                (id, content, weather, created_at, day, utc_offset, deleted_at)
+             VALUES ('new', '新', '晴', '2024-03-02T00:00:00+00:00', '2024-03-02', '+08:00',
+                     '2024-03-02T10:00:00+00:00');",
+        )
+        .unwrap();
+
+        let trashed: Vec<(String, Option<String>, String)> = load_trashed_entries(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.entry.id, t.entry.utc_offset, t.deleted_at))
+            .collect();
+        assert_eq!(
+            trashed,
+            vec![
+                (
+                    "new".to_string(),
+                    Some("+08:00".to_string()),
+                    "2024-03-02T10:00:00+00:00".to_string()
+                ),
+                (
+                    "old".to_string(),
+                    None,
+                    "2024-03-01T10:00:00+00:00".to_string()
+                ),
+            ]
+        );
+    }
+}
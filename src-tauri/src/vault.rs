@@ -0,0 +1,392 @@
+// Encryption at rest for the diary data directory
+//
+// diary.db is encrypted with SQLCipher and the Nostr secret key is stored as
+// a NIP-49 `ncryptsec` string, both protected by the same user passphrase.
+// A diary starts out unencrypted; setting a passphrase converts it in place.
+// Once encrypted, the database stays locked at startup until unlock_diary
+// is called with the right passphrase.
+use nostr_sdk::nips::nip49::{EncryptedSecretKey, KeySecurity};
+use nostr_sdk::{FromBech32, Keys, ToBech32};
+use rusqlite::{Connection, Result as SqlResult};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::State;
+
+use crate::DiaryStore;
+
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+const MIN_PASSPHRASE_CHARS: usize = 8;
+// scrypt cost for NIP-49, 2^16 rounds as recommended by the NIP
+const NCRYPTSEC_LOG_N: u8 = 16;
+
+static DIARY_UNLOCKED: AtomicBool = AtomicBool::new(false);
+// Kept while unlocked so other files can be keyed like the live database
+static SESSION_PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VaultStatus {
+    pub encrypted: bool,
+    pub unlocked: bool,
+}
+
+// An SQLCipher database has no plaintext header, so anything that exists and
+// does not start with the SQLite magic string is treated as encrypted. Only
+// the header is read, since this runs on every key access.
+pub fn is_database_encrypted(path: &Path) -> bool {
+    let mut header = Vec::with_capacity(SQLITE_HEADER.len());
+    let read = fs::File::open(path).and_then(|file| {
+        file.take(SQLITE_HEADER.len() as u64)
+            .read_to_end(&mut header)
+    });
+    match read {
+        Ok(len) if len > 0 => !header.starts_with(SQLITE_HEADER),
+        _ => false,
+    }
+}
+
+pub fn is_unlocked() -> bool {
+    !is_database_encrypted(&crate::get_db_path()) || DIARY_UNLOCKED.load(Ordering::SeqCst)
+}
+
+pub fn session_passphrase() -> Option<String> {
+    SESSION_PASSPHRASE.lock().unwrap().clone()
+}
+
+fn set_session_passphrase(passphrase: &str) {
+    *SESSION_PASSPHRASE.lock().unwrap() = Some(passphrase.to_string());
+}
+
+// Opens an encrypted database and checks that the passphrase is right
+pub fn open_encrypted(path: &Path, passphrase: &str) -> SqlResult<Connection> {
+    let conn = Connection::open(path)?;
+    conn.pragma_update(None, "key", passphrase)?;
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })?;
+    Ok(conn)
+}
+
+fn validate_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(format!(
+            "Passphrase must be at least {} characters",
+            MIN_PASSPHRASE_CHARS
+        ));
+    }
+    Ok(())
+}
+
+// Writes an encrypted copy of the database behind `conn` to `dest`
+fn export_encrypted(conn: &Connection, dest: &Path, passphrase: &str) -> SqlResult<()> {
+    if dest.exists() {
+        let _ = fs::remove_file(dest);
+    }
+
+    conn.execute(
+        "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+        [dest.to_string_lossy().to_string(), passphrase.to_string()],
+    )?;
+    let result = conn
+        .query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))
+        .and_then(|_| {
+            let version = crate::migrations::current_version(conn)?;
+            conn.pragma_update(Some("encrypted"), "user_version", version)
+        });
+    conn.execute_batch("DETACH DATABASE encrypted")?;
+    result
+}
+
+fn remove_sidecar_files(db_path: &Path) {
+    for suffix in ["-wal", "-shm", "-journal"] {
+        let mut name = db_path.as_os_str().to_owned();
+        name.push(suffix);
+        let _ = fs::remove_file(PathBuf::from(name));
+    }
+}
+
+// Pre-migration and rotating backups must not stay readable, nor keep the
+// old passphrase
+fn database_backup_files() -> Vec<PathBuf> {
+    let db_path = crate::get_db_path();
+    let prefix = format!(
+        "{}.",
+        db_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    );
+
+    let backups_dir = crate::backup_service::get_backups_dir();
+    let mut files: Vec<PathBuf> = crate::backup_service::list_backup_files()
+        .into_iter()
+        .map(|backup| backups_dir.join(backup.file_name))
+        .collect();
+
+    let migration_backups = match db_path.parent().map(fs::read_dir) {
+        Some(Ok(entries)) => entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                name.starts_with(&prefix) && name.ends_with(".bak")
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    files.extend(migration_backups);
+    files
+}
+
+fn encrypt_file_in_place(path: &Path, passphrase: &str) -> Result<(), String> {
+    let tmp_path = path.with_extension("encrypting");
+    {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        export_encrypted(&conn, &tmp_path, passphrase).map_err(|e| e.to_string())?;
+    }
+    fs::rename(&tmp_path, path).map_err(|e| e.to_string())
+}
+
+// Converts the live plaintext database into an SQLCipher database
+async fn encrypt_database(store: &DiaryStore, passphrase: &str) -> Result<(), String> {
+    let db_path = crate::get_db_path();
+    let tmp_path = db_path.with_extension("db.encrypting");
+
+    let live_path = db_path.clone();
+    let key = passphrase.to_string();
+    store
+        .db
+        .call(move |conn| {
+            export_encrypted(conn, &tmp_path, &key)
+                .map_err(|e| format!("Failed to encrypt database: {}", e))?;
+
+            // Close the plaintext connection before replacing the file
+            let placeholder = Connection::open_in_memory()
+                .map_err(|e| format!("Failed to swap connection: {}", e))?;
+            drop(std::mem::replace(conn, placeholder));
+
+            fs::rename(&tmp_path, &live_path)
+                .map_err(|e| format!("Failed to replace database file: {}", e))?;
+            remove_sidecar_files(&live_path);
+
+            *conn = open_encrypted(&live_path, &key)
+                .map_err(|e| format!("Failed to open encrypted database: {}", e))?;
+            crate::database::configure(conn)
+                .map_err(|e| format!("Failed to configure encrypted database: {}", e))
+        })
+        .await?;
+
+    for backup in database_backup_files() {
+        if !is_database_encrypted(&backup) {
+            if let Err(e) = encrypt_file_in_place(&backup, passphrase) {
+                println!("Failed to encrypt backup {}: {}", backup.display(), e);
+            }
+        }
+    }
+
+    println!("Database encrypted at: {}", db_path.display());
+    Ok(())
+}
+
+async fn rekey_database(store: &DiaryStore, current: &str, new: &str) -> Result<(), String> {
+    let key = new.to_string();
+    store
+        .db
+        .call(move |conn| {
+            conn.pragma_update(None, "rekey", key)
+                .map_err(|e| format!("Failed to change database passphrase: {}", e))
+        })
+        .await?;
+
+    for backup in database_backup_files() {
+        let rekeyed = open_encrypted(&backup, current)
+            .and_then(|backup_conn| backup_conn.pragma_update(None, "rekey", new));
+        if let Err(e) = rekeyed {
+            println!("Failed to re-encrypt backup {}: {}", backup.display(), e);
+        }
+    }
+    Ok(())
+}
+
+pub fn encrypt_keys(keys: &Keys, passphrase: &str) -> Result<String, String> {
+    let encrypted = EncryptedSecretKey::new(
+        keys.secret_key(),
+        passphrase,
+        NCRYPTSEC_LOG_N,
+        KeySecurity::Medium,
+    )
+    .map_err(|e| format!("Failed to encrypt Nostr keys: {}", e))?;
+    encrypted
+        .to_bech32()
+        .map_err(|e| format!("Failed to encode encrypted Nostr keys: {}", e))
+}
+
+pub fn decrypt_keys(ncryptsec: &str, passphrase: &str) -> Result<Keys, String> {
+    let encrypted = EncryptedSecretKey::from_bech32(ncryptsec)
+        .map_err(|e| format!("Failed to parse encrypted Nostr keys: {}", e))?;
+    let secret_key = encrypted
+        .decrypt(passphrase)
+        .map_err(|e| format!("Failed to decrypt Nostr keys: {}", e))?;
+    Ok(Keys::new(secret_key))
+}
+
+// Opens the encrypted database with the passphrase and brings it up to date
+fn open_unlocked_database(db_path: &Path, passphrase: &str) -> Result<Connection, String> {
+    let mut conn =
+        open_encrypted(db_path, passphrase).map_err(|_| "Incorrect passphrase".to_string())?;
+    crate::database::configure(&conn)
+        .map_err(|e| format!("Failed to configure database: {}", e))?;
+    crate::migrations::migrate(&mut conn, Some(db_path))
+        .map_err(|e| format!("Failed to migrate database: {}", e))?;
+    crate::repository::index_external_entries(&conn)
+        .map_err(|e| format!("Failed to index entries: {}", e))?;
+    Ok(conn)
+}
+
+#[tauri::command]
+pub fn get_vault_status() -> VaultStatus {
+    VaultStatus {
+        encrypted: is_database_encrypted(&crate::get_db_path()),
+        unlocked: is_unlocked(),
+    }
+}
+
+#[tauri::command]
+pub async fn unlock_diary(
+    store: State<'_, Arc<DiaryStore>>,
+    passphrase: String,
+) -> Result<(), String> {
+    let db_path = crate::get_db_path();
+    if !is_database_encrypted(&db_path) {
+        return Ok(());
+    }
+
+    let conn = open_unlocked_database(&db_path, &passphrase)?;
+
+    // Replace the locked connection held by the database worker
+    store
+        .db
+        .call(move |live| {
+            *live = conn;
+            Ok(())
+        })
+        .await?;
+
+    let keys = match crate::load_encrypted_nostr_keys(&passphrase)? {
+        Some(keys) => keys,
+        None => {
+            println!("Generating new Nostr keys");
+            let keys = Keys::generate();
+            crate::save_encrypted_nostr_keys(&keys, &passphrase)?;
+            keys
+        }
+    };
+    *store.nostr_keys.lock().unwrap() = Some(keys);
+
+    set_session_passphrase(&passphrase);
+    DIARY_UNLOCKED.store(true, Ordering::SeqCst);
+    println!("Diary unlocked");
+    Ok(())
+}
+
+// Sets the passphrase of an unencrypted diary, encrypting it, or changes the
+// passphrase of an encrypted one
+#[tauri::command]
+pub async fn change_passphrase(
+    store: State<'_, Arc<DiaryStore>>,
+    current_passphrase: Option<String>,
+    new_passphrase: String,
+) -> Result<(), String> {
+    validate_passphrase(&new_passphrase)?;
+    let db_path = crate::get_db_path();
+
+    if is_database_encrypted(&db_path) {
+        let current = current_passphrase.ok_or("Current passphrase is required")?;
+        open_encrypted(&db_path, &current).map_err(|_| "Incorrect passphrase".to_string())?;
+        if !is_unlocked() {
+            return Err("Unlock the diary before changing its passphrase".to_string());
+        }
+        crate::backup_service::backup_before(&store, "passphrase-change").await?;
+        rekey_database(&store, &current, &new_passphrase).await?;
+    } else {
+        crate::backup_service::backup_before(&store, "passphrase-change").await?;
+        encrypt_database(&store, &new_passphrase).await?;
+        DIARY_UNLOCKED.store(true, Ordering::SeqCst);
+    }
+    set_session_passphrase(&new_passphrase);
+
+    // Keys are encrypted with the same passphrase so a copied data directory
+    // reveals neither the diary nor the identity that signed it
+    let keys = crate::get_or_create_nostr_keys(&store)?;
+    crate::save_encrypted_nostr_keys(&keys, &new_passphrase)?;
+
+    println!("Passphrase changed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    // A plaintext diary with one entry, encrypted the way change_passphrase
+    // encrypts backups
+    fn encrypted_db_path() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("luxun-dairy-vault-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("diary.db");
+        {
+            let mut conn = Connection::open(&path).unwrap();
+            crate::migrations::migrate(&mut conn, None).unwrap();
+            conn.execute(
+                "INSERT INTO diary_entries (id, content, weather, created_at, day)
+                 VALUES ('a', '秘密', '晴', 'now', '2024-03-01')",
+                [],
+            )
+            .unwrap();
+        }
+        assert!(!is_database_encrypted(&path));
+        encrypt_file_in_place(&path, PASSPHRASE).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_encrypted_database_unlocks_with_the_passphrase_only() {
+        let path = encrypted_db_path();
+        assert!(is_database_encrypted(&path));
+
+        // Locked: readable neither without a key nor with the wrong one
+        let plain = Connection::open(&path).unwrap();
+        assert!(plain
+            .query_row("SELECT COUNT(*) FROM diary_entries", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .is_err());
+        assert_eq!(
+            open_unlocked_database(&path, "wrong passphrase").unwrap_err(),
+            "Incorrect passphrase"
+        );
+
+        let conn = open_unlocked_database(&path, PASSPHRASE).unwrap();
+        let content: String = conn
+            .query_row("SELECT content FROM diary_entries", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(content, "秘密");
+        drop(conn);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_short_passphrase_is_rejected() {
+        assert!(validate_passphrase("short").is_err());
+        assert!(validate_passphrase(PASSPHRASE).is_ok());
+    }
+}
@@ -1,8 +1,11 @@
+import { useState, useEffect } from "preact/hooks";
 import { HashRouter, Routes, Route, Navigate } from "react-router-dom";
 import { WritePage } from "./pages/WritePage";
 import { ReadPage } from "./pages/ReadPage";
 import { NostrEventPage } from "./pages/NostrEventPage";
 import { SettingsPage } from "./pages/SettingsPage";
+import { UnlockPage } from "./pages/UnlockPage";
+import * as diaryService from "./utils/diaryService";
 import { ThemeProvider } from "./contexts/ThemeContext";
 
 // 在App组件外初始化主题，确保早期加载
@@ -30,6 +33,27 @@ import { ThemeProvider } from "./contexts/ThemeContext";
 })();
 
 function App() {
+  const [locked, setLocked] = useState<boolean | null>(null);
+
+  useEffect(() => {
+    diaryService
+      .getVaultStatus()
+      .then((status) => setLocked(status.encrypted && !status.unlocked))
+      .catch(() => setLocked(false));
+  }, []);
+
+  if (locked === null) {
+    return null;
+  }
+
+  if (locked) {
+    return (
+      <ThemeProvider>
+        <UnlockPage onUnlocked={() => setLocked(false)} />
+      </ThemeProvider>
+    );
+  }
+
   return (
     <ThemeProvider>
       <HashRouter>
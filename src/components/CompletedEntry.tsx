@@ -2,6 +2,8 @@ import { useState, useEffect } from "preact/hooks";
 import { formatDayDisplay } from "../utils/helpers";
 import { getRandomLuXunDiaryEntry } from "../utils/diaryService";
 import { LuXunDiaryEntry } from "../types";
+import { EntryAttachments } from "./EntryAttachments";
+import { PublishReceipts } from "./PublishReceipts";
 
 interface CompletedEntryProps {
   selectedDay: string;
@@ -45,6 +47,8 @@ export function CompletedEntry({ selectedDay }: CompletedEntryProps) {
       </div>
       <h3 className="m-0 mb-4 text-2xl text-[#3c7d73] dark:text-[#a2e2d8] font-medium">已完成</h3>
       <p className="text-[#6d7a75] dark:text-[#a6a69e] text-lg m-0 leading-relaxed max-w-lg mx-auto">您已记录 {formatDayDisplay(selectedDay)} 之事。</p>
+      <PublishReceipts day={selectedDay} />
+      <EntryAttachments day={selectedDay} />
       <p className="text-[#6d7a75] dark:text-[#a6a69e] text-sm mt-6 mb-2">
         为您随机展示一篇鲁迅先生的日记
       </p>
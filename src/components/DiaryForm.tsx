@@ -4,6 +4,10 @@ interface DiaryFormProps {
   weather: string;
   setWeather: (weather: string) => void;
   errorMessage: string;
+  publishLater: boolean;
+  setPublishLater: (publishLater: boolean) => void;
+  isPrivate: boolean;
+  setIsPrivate: (isPrivate: boolean) => void;
   saveDiaryEntry: () => void;
 }
 
@@ -13,6 +17,10 @@ export function DiaryForm({
   weather, 
   setWeather, 
   errorMessage, 
+  publishLater,
+  setPublishLater,
+  isPrivate,
+  setIsPrivate,
   saveDiaryEntry 
 }: DiaryFormProps) {
   return (
@@ -51,6 +59,24 @@ export function DiaryForm({
           />
         </div>
         
+        <label className="flex items-center gap-2 text-sm text-[#8c8b85] dark:text-[#717b7a] cursor-pointer">
+          <input
+            type="checkbox"
+            checked={publishLater}
+            onChange={(e) => setPublishLater(e.currentTarget.checked)}
+          />
+          稍后发布
+        </label>
+
+        <label className="flex items-center gap-2 text-sm text-[#8c8b85] dark:text-[#717b7a] cursor-pointer">
+          <input
+            type="checkbox"
+            checked={isPrivate}
+            onChange={(e) => setIsPrivate(e.currentTarget.checked)}
+          />
+          加密发布
+        </label>
+
         <button 
           className="bg-gradient-to-r from-[#49b3a1] to-[#3a9e8d] dark:from-[#43a595] dark:to-[#389384] text-white font-medium px-8 py-3 h-11 border-0 rounded-lg shadow-sm transition-all active:translate-y-px flex items-center justify-center tracking-wider disabled:opacity-60 disabled:cursor-not-allowed"
           onClick={saveDiaryEntry} 
@@ -0,0 +1,124 @@
+import { useState, useEffect } from "preact/hooks";
+import * as diaryService from "../utils/diaryService";
+import { Attachment } from "../types";
+
+interface EntryAttachmentsProps {
+  day: string;
+}
+
+export function EntryAttachments({ day }: EntryAttachmentsProps) {
+  const [attachments, setAttachments] = useState<Attachment[]>([]);
+  const [thumbnails, setThumbnails] = useState<Record<string, string>>({});
+  const [uploading, setUploading] = useState(false);
+
+  useEffect(() => {
+    loadAttachments();
+  }, [day]);
+
+  // 释放缩略图占用的对象 URL
+  useEffect(() => {
+    return () => Object.values(thumbnails).forEach((url) => URL.revokeObjectURL(url));
+  }, [thumbnails]);
+
+  async function loadAttachments() {
+    try {
+      const list = await diaryService.listAttachments(day);
+      setAttachments(list);
+
+      const urls: Record<string, string> = {};
+      for (const attachment of list.filter((a) => a.mime_type.startsWith("image/"))) {
+        const data = await diaryService.getAttachment(attachment.sha256, true);
+        urls[attachment.sha256] = URL.createObjectURL(new Blob([data]));
+      }
+      setThumbnails(urls);
+    } catch (error) {
+      console.error("Failed to load attachments:", error);
+    }
+  }
+
+  async function addFiles(files: FileList | null) {
+    if (!files || uploading) return;
+
+    setUploading(true);
+    try {
+      for (const file of Array.from(files)) {
+        const data = new Uint8Array(await file.arrayBuffer());
+        await diaryService.addAttachment(day, file.name, data);
+      }
+      await loadAttachments();
+    } catch (error) {
+      alert(`添加附件失败：${error}`);
+    } finally {
+      setUploading(false);
+    }
+  }
+
+  async function removeAttachment(attachment: Attachment) {
+    if (!confirm(`确定要删除附件 ${attachment.file_name} 吗？`)) return;
+
+    try {
+      await diaryService.removeAttachment(day, attachment.sha256);
+      await loadAttachments();
+    } catch (error) {
+      alert("删除附件失败");
+    }
+  }
+
+  async function openAttachment(attachment: Attachment) {
+    try {
+      const data = await diaryService.getAttachment(attachment.sha256);
+      const url = URL.createObjectURL(new Blob([data], { type: attachment.mime_type }));
+      window.open(url, "_blank");
+    } catch (error) {
+      alert("打开附件失败");
+    }
+  }
+
+  return (
+    <div className="mt-8 max-w-lg mx-auto text-left">
+      <div className="flex justify-between items-center mb-3">
+        <span className="text-[#3c7d73] dark:text-[#a2e2d8] font-medium">附件</span>
+        <label className={`text-sm text-[#49b3a1] dark:text-[#43a595] hover:text-[#3a9e8d] dark:hover:text-[#389384] cursor-pointer ${
+          uploading ? 'opacity-50 cursor-not-allowed' : ''
+        }`}>
+          {uploading ? "上传中..." : "添加附件"}
+          <input
+            type="file"
+            multiple
+            className="hidden"
+            disabled={uploading}
+            onChange={(e) => addFiles((e.target as HTMLInputElement).files)}
+          />
+        </label>
+      </div>
+      <div className="grid grid-cols-3 gap-3">
+        {attachments.map((attachment) => (
+          <div
+            key={attachment.sha256}
+            className="relative p-2 border border-[#e9e4d9] dark:border-[#2c2c32] rounded-lg bg-[#fafaf8] dark:bg-[#1d1d20]"
+          >
+            <button onClick={() => openAttachment(attachment)} className="block w-full">
+              {thumbnails[attachment.sha256] ? (
+                <img src={thumbnails[attachment.sha256]} alt={attachment.file_name} className="w-full h-20 object-cover rounded" />
+              ) : (
+                <div className="w-full h-20 flex items-center justify-center text-xs text-[#6d7a75] dark:text-[#a6a69e]">
+                  {attachment.mime_type}
+                </div>
+              )}
+              <div className="mt-1 text-xs truncate text-[#4d5a55] dark:text-[#b6b6be]">{attachment.file_name}</div>
+            </button>
+            <button
+              onClick={() => removeAttachment(attachment)}
+              className="absolute top-1 right-1 text-xs px-1 rounded bg-white/80 dark:bg-[#2a2a2e]/80 text-[#8c7c67] dark:text-[#a6a69e] hover:text-red-500"
+            >
+              删除
+            </button>
+          </div>
+        ))}
+      </div>
+      {attachments.length === 0 && (
+        <div className="text-sm text-[#6d7a75] dark:text-[#a6a69e]">暂无附件</div>
+      )}
+    </div>
+  );
+}
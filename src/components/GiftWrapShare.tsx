@@ -1,6 +1,7 @@
 import { invoke } from '@tauri-apps/api/core';
 import { useState } from 'preact/hooks';
 import { DiaryEntry } from '../types/DiaryEntry';
+import { PublicKeyInfo } from '../types';
 import {Modal} from './Modal';
 import { JSX } from 'preact';
 import { useRelayUrls } from '../hooks/useRelayUrls';
@@ -17,7 +18,7 @@ export function GiftWrapShare({ entry, onClose, isOpen }: GiftWrapShareProps) {
   const [isSharing, setIsSharing] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const [success, setSuccess] = useState<string | null>(null);
-  const [giftWrapData, setGiftWrapData] = useState<{ event: string; id: string } | null>(null);
+  const [giftWrapData, setGiftWrapData] = useState<{ event: string; id: string; relayHints: string[] } | null>(null);
   const { relayUrls, isLoading: isLoadingRelays, error: relayError } = useRelayUrls();
   const [selectedRelayUrl, setSelectedRelayUrl] = useState<string>('');
 
@@ -33,15 +34,9 @@ export function GiftWrapShare({ entry, onClose, isOpen }: GiftWrapShareProps) {
     onClose();
   };
 
-  // Validate pubkey format
-  const validatePubkey = async (pubkey: string): Promise<boolean> => {
-    try {
-      const isValid = await invoke<boolean>('validate_pubkey', { pubkey });
-      return isValid;
-    } catch (err) {
-      console.error('Failed to validate pubkey:', err);
-      return false;
-    }
+    // Validate pubkey format, accepts hex, npub, nprofile and nostr: URIs
+  const validatePubkey = async (pubkey: string): Promise<PublicKeyInfo | null> => {
+    return await invoke<PublicKeyInfo | null>('validate_pubkey', { pubkey });
   };
 
   // Create gift wrap
@@ -54,17 +49,24 @@ export function GiftWrapShare({ entry, onClose, isOpen }: GiftWrapShareProps) {
       return;
     }
     
-    // Validate pubkey format
-    const pubkeyValid = await validatePubkey(recipientPubkey);
-    if (!pubkeyValid) {
-      setError('接收者公钥格式无效，请输入有效的Nostr十六进制公钥');
+        // Validate pubkey format
+    let pubkeyInfo: PublicKeyInfo | null;
+    try {
+      pubkeyInfo = await validatePubkey(recipientPubkey);
+    } catch (err) {
+      // A pasted nsec is rejected with its own warning
+      setError(`请勿分享私钥（nsec），请输入接收者的 npub 公钥: ${err}`);
+      return;
+    }
+    if (!pubkeyInfo) {
+      setError('接收者公钥格式无效，请输入有效的 npub、nprofile 或十六进制公钥');
       return;
     }
     
     setIsWrapping(true);
     
     try {
-      const result = await invoke<{ gift_wrap_event: string, gift_wrap_id: string }>('gift_wrap_diary', {
+      const result = await invoke<{ gift_wrap_event: string, gift_wrap_id: string, relay_hints: string[] }>('gift_wrap_diary', {
         request: {
           nostr_id: entry.nostr_id,
           recipient_pubkey: recipientPubkey,
@@ -73,7 +75,8 @@ export function GiftWrapShare({ entry, onClose, isOpen }: GiftWrapShareProps) {
       
       setGiftWrapData({
         event: result.gift_wrap_event,
-        id: result.gift_wrap_id
+                id: result.gift_wrap_id,
+        relayHints: result.relay_hints
       });
       
       setSuccess('日记已成功加密分享！现在可以分享了。');
@@ -92,10 +95,7 @@ export function GiftWrapShare({ entry, onClose, isOpen }: GiftWrapShareProps) {
       return;
     }
 
-    if (!selectedRelayUrl) {
-      setError('请选择一个中继服务器');
-      return;
-    }
+    
     
     setIsSharing(true);
     setError(null);
@@ -103,7 +103,10 @@ export function GiftWrapShare({ entry, onClose, isOpen }: GiftWrapShareProps) {
     try {
       const result = await invoke<string>('share_gift_wrap', {
         giftWrapJson: giftWrapData.event,
-        relayUrl: selectedRelayUrl,
+                // Without a relay the backend uses the recipient's NIP-65 relays
+                relayUrl: selectedRelayUrl || null,
+        // Relays of an nprofile help find the recipient's relay list
+        relayHints: giftWrapData.relayHints,
       });
       
       setSuccess(result);
@@ -146,7 +149,7 @@ export function GiftWrapShare({ entry, onClose, isOpen }: GiftWrapShareProps) {
 
             <div className="mb-6">
               <label className="block text-sm font-medium text-gray-700 mb-2">
-                接收者公钥（十六进制格式）
+                                接收者公钥（npub、nprofile 或十六进制）
               </label>
               <input
                 type="text"
@@ -154,7 +157,7 @@ export function GiftWrapShare({ entry, onClose, isOpen }: GiftWrapShareProps) {
                 onChange={(e: JSX.TargetedEvent<HTMLInputElement, Event>) => setRecipientPubkey(e.currentTarget.value)}
                 disabled={isWrapping || !!giftWrapData}
                 className="w-full p-3 border border-gray-300 rounded-lg focus:ring-2 focus:ring-blue-500 focus:border-blue-500 break-all"
-                placeholder="输入接收者的 Nostr 公钥（十六进制格式）"
+                placeholder="输入接收者的 npub、nprofile 或十六进制公钥"
               />
             </div>
 
@@ -183,7 +186,7 @@ export function GiftWrapShare({ entry, onClose, isOpen }: GiftWrapShareProps) {
                       disabled={isSharing}
                       className="w-full p-3 border border-gray-300 rounded-lg focus:ring-2 focus:ring-blue-500 focus:border-blue-500"
                     >
-                      <option value="">选择中继服务器</option>
+                      <option value="">接收者的中继（NIP-65）</option>
                       {relayUrls.map((url) => (
                         <option key={url} value={url}>
                           {url}
@@ -195,7 +198,7 @@ export function GiftWrapShare({ entry, onClose, isOpen }: GiftWrapShareProps) {
                 
                 <button
                   onClick={handleShare}
-                  disabled={isSharing || !selectedRelayUrl}
+                  disabled={isSharing}
                   className="w-full bg-green-600 text-white py-3 px-4 rounded-lg hover:bg-green-700 disabled:bg-gray-400 transition-colors duration-200"
                 >
                   {isSharing ? '正在分享...' : '分享到中继服务器'}
@@ -1,12 +1,14 @@
-import { DiaryEntry } from "../types";
+import { DiaryEntry, SearchResult } from "../types";
 import { useNavigate } from "react-router-dom";
 import { useState, useRef, useEffect } from "preact/hooks";
+import * as diaryService from "../utils/diaryService";
 
 interface MonthCalendarProps {
   entries: DiaryEntry[];
+  totalCount?: number;
 }
 
-export function MonthCalendar({ entries }: MonthCalendarProps) {
+export function MonthCalendar({ entries, totalCount }: MonthCalendarProps) {
   const navigate = useNavigate();
   const [hoveredDay, setHoveredDay] = useState<string | null>(null);
   const tooltipRef = useRef<HTMLDivElement>(null);
@@ -15,7 +17,7 @@ export function MonthCalendar({ entries }: MonthCalendarProps) {
   const searchInputRef = useRef<HTMLInputElement>(null);
   const searchResultsRef = useRef<HTMLDivElement>(null);
   const [searchQuery, setSearchQuery] = useState("");
-  const [searchResults, setSearchResults] = useState<DiaryEntry[]>([]);
+  const [searchResults, setSearchResults] = useState<SearchResult[]>([]);
   const [isSearching, setIsSearching] = useState(false);
   const [showSearchResults, setShowSearchResults] = useState(false);
   
@@ -27,10 +29,20 @@ export function MonthCalendar({ entries }: MonthCalendarProps) {
   // Get current viewed month and year
   const currentMonth = viewDate.getMonth();
   const currentYear = viewDate.getFullYear();
+
+  // Entries of the viewed month, queried by year/month instead of scanning all entries
+  const [monthEntries, setMonthEntries] = useState<DiaryEntry[]>([]);
+
+  useEffect(() => {
+    diaryService
+      .getEntriesForMonth(currentYear, currentMonth + 1)
+      .then(setMonthEntries)
+      .catch(() => setMonthEntries([]));
+  }, [currentYear, currentMonth, entries]);
   
   // 计算历史累计记录天数
   const getTotalEntriesCount = (): number => {
-    return entries.length;
+    return totalCount ?? entries.length;
   };
   
   // 计算连续记录天数
@@ -129,7 +141,7 @@ export function MonthCalendar({ entries }: MonthCalendarProps) {
   
   // Check if a day has an entry
   const getDayEntry = (day: string) => {
-    return entries.find(entry => entry.day === day);
+    return monthEntries.find(entry => entry.day === day);
   };
   
   // Check if a date is today
@@ -256,7 +268,7 @@ export function MonthCalendar({ entries }: MonthCalendarProps) {
   };
   
   // Search function
-  const handleSearch = () => {
+  const handleSearch = async () => {
     if (!searchQuery.trim()) {
       setSearchResults([]);
       setShowSearchResults(false);
@@ -266,13 +278,15 @@ export function MonthCalendar({ entries }: MonthCalendarProps) {
     setIsSearching(true);
     setShowSearchResults(true);
     
-    // Filter entries that contain the search query (case insensitive)
-    const results = entries.filter(entry => 
-      entry.content.toLowerCase().includes(searchQuery.toLowerCase())
-    );
-    
-    setSearchResults(results);
-    setIsSearching(false);
+    // Full-text search in the backend, supports weather:雨 and from:/to: filters
+    try {
+      const results = await diaryService.searchDiaryEntries(searchQuery.trim());
+      setSearchResults(results);
+    } catch (error) {
+      setSearchResults([]);
+    } finally {
+      setIsSearching(false);
+    }
   };
   
   // Handle search input change
@@ -420,11 +434,7 @@ export function MonthCalendar({ entries }: MonthCalendarProps) {
           {(() => {
             // Count entries for current month only
             const daysInMonth = new Date(currentYear, currentMonth + 1, 0).getDate();
-            const entriesThisMonth = entries.filter(entry => {
-              const entryDate = new Date(entry.day);
-              return entryDate.getMonth() === currentMonth && entryDate.getFullYear() === currentYear;
-            });
-            return `本月记录了 ${entriesThisMonth.length}/${daysInMonth} 天`;
+            return `本月记录了 ${monthEntries.length}/${daysInMonth} 天`;
           })()}
         </div>
       </div>
@@ -470,7 +480,7 @@ export function MonthCalendar({ entries }: MonthCalendarProps) {
                     搜索中...
                   </div>
                 ) : searchResults.length > 0 ? (
-                  searchResults.map(entry => (
+                  searchResults.map(({ entry, snippet }) => (
                     <div 
                       key={entry.id}
                       onClick={() => navigateToEntry(entry)}
@@ -480,9 +490,10 @@ export function MonthCalendar({ entries }: MonthCalendarProps) {
                         <span className="text-[#5ba2ae]">{new Date(entry.day).toLocaleDateString('zh-CN', {year: 'numeric', month: 'long', day: 'numeric'})}</span>
                         <span className="text-[#a8c555]">{entry.weather}</span>
                       </div>
-                      <div className="text-xs mt-1 line-clamp-1">
-                        {getShortPreview(entry)}
-                      </div>
+                      <div
+                        className="text-xs mt-1 line-clamp-2 [&_mark]:bg-[#a8c555]/40 [&_mark]:text-inherit"
+                        dangerouslySetInnerHTML={{ __html: snippet }}
+                      />
                     </div>
                   ))
                 ) : (
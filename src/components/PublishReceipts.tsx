@@ -0,0 +1,102 @@
+import { useState, useEffect } from "preact/hooks";
+import * as diaryService from "../utils/diaryService";
+import { PublishStatus } from "../types";
+
+interface PublishReceiptsProps {
+  day: string;
+}
+
+export function PublishReceipts({ day }: PublishReceiptsProps) {
+  const [status, setStatus] = useState<PublishStatus | null>(null);
+  const [expanded, setExpanded] = useState(false);
+  const [switching, setSwitching] = useState(false);
+
+  useEffect(() => {
+    loadStatus();
+  }, [day]);
+
+  async function loadStatus() {
+    try {
+      setStatus(await diaryService.getPublishStatus(day));
+    } catch (error) {
+      console.error("Failed to load publish status:", error);
+    }
+  }
+
+  async function toggleVisibility() {
+    if (!status || switching) return;
+    const message = status.private
+      ? "改为公开发布后，任何人都能在中继上读到这篇日记，确定吗？"
+      : "改为加密发布吗？已被他人保存的公开版本无法撤回。";
+    if (!confirm(message)) return;
+
+    setSwitching(true);
+    try {
+      await diaryService.setEntryVisibility(day, !status.private);
+      await loadStatus();
+    } catch (error) {
+      alert(`修改发布方式失败：${error}`);
+    } finally {
+      setSwitching(false);
+    }
+  }
+
+  if (!status) return null;
+
+  const visibility = (
+    <button
+      onClick={toggleVisibility}
+      disabled={switching}
+      className="ml-3 hover:text-[#3c7d73] dark:hover:text-[#a2e2d8]"
+    >
+      {status.private ? "加密发布" : "公开发布"}
+    </button>
+  );
+
+  // 旧日记没有发布记录
+  if (status.total === 0) {
+    return (
+      <div className="mt-4 max-w-lg mx-auto text-sm text-[#6d7a75] dark:text-[#a6a69e]">
+        {visibility}
+      </div>
+    );
+  }
+
+  if (status.held) {
+    return (
+      <div className="mt-4 max-w-lg mx-auto text-sm text-[#6d7a75] dark:text-[#a6a69e]">
+        已保存在本地，尚未发布到中继
+        {visibility}
+      </div>
+    );
+  }
+
+  return (
+    <div className="mt-4 max-w-lg mx-auto text-sm text-[#6d7a75] dark:text-[#a6a69e]">
+      <button
+        onClick={() => setExpanded(!expanded)}
+        className="hover:text-[#3c7d73] dark:hover:text-[#a2e2d8]"
+      >
+        已被 {status.accepted}/{status.total} 个中继接收
+      </button>
+      {visibility}
+      {expanded && (
+        <div className="mt-2 space-y-1 text-left">
+          {status.relays.map((relay) => (
+            <div
+              key={relay.relay_url}
+              className="flex justify-between gap-2 p-2 bg-[#fafaf8] dark:bg-[#1d1d20] rounded"
+            >
+              <span className="font-mono break-all">{relay.relay_url}</span>
+              <span className={relay.accepted ? "text-[#3c7d73] dark:text-[#a2e2d8]" : "text-red-500"}>
+                {relay.accepted
+                  ? "已接收"
+                  : `${relay.message ?? "尚未发送"}${relay.retrying ? "（稍后重试）" : ""}`}
+              </span>
+            </div>
+          ))}
+        </div>
+      )}
+    </div>
+  );
+}
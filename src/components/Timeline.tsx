@@ -1,4 +1,4 @@
-import { useState } from "preact/hooks";
+import { useState, useEffect, useRef } from "preact/hooks";
 import { DiaryEntry } from "../types";
 import { formatShortDate, groupEntriesByYear, shortenKey } from "../utils/helpers";
 import GiftWrapShare from "./GiftWrapShare";
@@ -6,11 +6,28 @@ import GiftWrapShare from "./GiftWrapShare";
 interface TimelineProps {
   entries: DiaryEntry[];
   viewNostrEvent: (nostrId: string) => void;
+  hasMore?: boolean;
+  onLoadMore?: () => void;
 }
 
-export function Timeline({ entries, viewNostrEvent }: TimelineProps) {
+export function Timeline({ entries, viewNostrEvent, hasMore, onLoadMore }: TimelineProps) {
   const [isGiftWrapOpen, setIsGiftWrapOpen] = useState(false);
   const [selectedEntry, setSelectedEntry] = useState<DiaryEntry | null>(null);
+  const sentinelRef = useRef<HTMLDivElement>(null);
+
+  // Load the next page when the end of the timeline scrolls into view
+  useEffect(() => {
+    if (!hasMore || !onLoadMore || !sentinelRef.current) return;
+
+    const observer = new IntersectionObserver((observed) => {
+      if (observed.some(item => item.isIntersecting)) {
+        onLoadMore();
+      }
+    }, { rootMargin: "200px" });
+
+    observer.observe(sentinelRef.current);
+    return () => observer.disconnect();
+  }, [hasMore, onLoadMore, entries.length]);
 
   const handleGiftWrapOpen = (entry: DiaryEntry) => {
     setSelectedEntry(entry);
@@ -83,6 +100,8 @@ export function Timeline({ entries, viewNostrEvent }: TimelineProps) {
           ))}
         </div>
       ))}
+
+      {hasMore && <div ref={sentinelRef} className="h-8" />}
       
       {/* Gift Wrap Modal */}
       {selectedEntry && (
@@ -9,6 +9,10 @@ interface WriteContainerProps {
   weather: string;
   setWeather: (weather: string) => void;
   errorMessage: string;
+  publishLater: boolean;
+  setPublishLater: (publishLater: boolean) => void;
+  isPrivate: boolean;
+  setIsPrivate: (isPrivate: boolean) => void;
   saveDiaryEntry: () => void;
 }
 
@@ -20,6 +24,10 @@ export function WriteContainer({
   weather,
   setWeather,
   errorMessage,
+  publishLater,
+  setPublishLater,
+  isPrivate,
+  setIsPrivate,
   saveDiaryEntry
 }: WriteContainerProps) {
   return (
@@ -33,6 +41,10 @@ export function WriteContainer({
           weather={weather}
           setWeather={setWeather}
           errorMessage={errorMessage}
+          publishLater={publishLater}
+          setPublishLater={setPublishLater}
+          isPrivate={isPrivate}
+          setIsPrivate={setIsPrivate}
           saveDiaryEntry={saveDiaryEntry}
         />
       )}
@@ -4,7 +4,7 @@ import { Header } from "../components/Header";
 import { MonthCalendar } from "../components/MonthCalendar";
 import * as diaryService from "../utils/diaryService";
 import { useNavigate } from "react-router-dom";
-import { DiaryEntry, CommonDiary } from "../types";
+import { DiaryEntry, CommonDiary, TagCount } from "../types";
 import { Modal } from "../components/Modal";
 import { NostrEventViewer } from "../components/NostrEventViewer";
 import { CommonDiaryReader } from "../components/CommonDiaryReader";
@@ -13,6 +13,11 @@ import { FriendDiaryReader } from "../components/FriendDiaryReader";
 export function ReadPage() {
   const navigate = useNavigate();
   const [entries, setEntries] = useState<DiaryEntry[]>([]);
+  const [totalEntries, setTotalEntries] = useState(0);
+  const [nextCursor, setNextCursor] = useState<string | undefined>(undefined);
+  const [isLoadingMore, setIsLoadingMore] = useState(false);
+  const [tags, setTags] = useState<TagCount[]>([]);
+  const [selectedTag, setSelectedTag] = useState<string | undefined>(undefined);
   const selectedDay = new Date().toLocaleDateString('en-CA');
   const [isModalOpen, setIsModalOpen] = useState(false);
   const [selectedNostrEvent, setSelectedNostrEvent] = useState<string | null>(null);
@@ -25,19 +30,48 @@ export function ReadPage() {
   const [loadingSource, setLoadingSource] = useState<string>("");
 
   useEffect(() => {
-    loadEntries();
     loadCommonDiaries();
+    loadTags();
   }, []);
 
+  useEffect(() => {
+    loadEntries();
+  }, [selectedTag]);
+
+  async function loadTags() {
+    try {
+      setTags(await diaryService.listTags());
+    } catch (error) {
+      console.error("Failed to load tags:", error);
+    }
+  }
+
   async function loadEntries() {
     try {
-      const entriesData = await diaryService.loadEntries();
-      setEntries(entriesData);
+      const page = await diaryService.queryDiaryEntries({ tag: selectedTag });
+      setEntries(page.entries);
+      setTotalEntries(page.total);
+      setNextCursor(page.next_cursor);
     } catch (error) {
       console.error("Failed to load entries:", error);
     }
   }
 
+  async function loadMoreEntries() {
+    if (!nextCursor || isLoadingMore) return;
+    setIsLoadingMore(true);
+    try {
+      const page = await diaryService.queryDiaryEntries({ cursor: nextCursor, tag: selectedTag });
+      setEntries(previous => [...previous, ...page.entries]);
+      setTotalEntries(page.total);
+      setNextCursor(page.next_cursor);
+    } catch (error) {
+      console.error("Failed to load more entries:", error);
+    } finally {
+      setIsLoadingMore(false);
+    }
+  }
+
   async function loadCommonDiaries() {
     setIsLoading(true);
     setLoadingSource("");
@@ -159,8 +193,30 @@ export function ReadPage() {
           </div>
         ) : activeTab === "my-diary" ? (
           <>
-            <MonthCalendar entries={entries} />
-            <Timeline entries={entries} viewNostrEvent={viewNostrEvent} />
+            <MonthCalendar entries={entries} totalCount={totalEntries} />
+            {tags.length > 0 && (
+              <div className="flex flex-wrap gap-2 mb-4">
+                {tags.map((tag) => (
+                  <button
+                    key={tag.name}
+                    onClick={() => setSelectedTag(selectedTag === tag.name ? undefined : tag.name)}
+                    className={`px-2 py-0.5 text-xs rounded-full border transition-colors duration-150 ${
+                      selectedTag === tag.name
+                        ? "bg-[#49b3a1] dark:bg-[#43a595] border-[#49b3a1] dark:border-[#43a595] text-white"
+                        : "border-[#e6e1d5] dark:border-[#323237] text-[#8c7c67] dark:text-[#a6a69e] hover:border-[#49b3a1] dark:hover:border-[#43a595]"
+                    }`}
+                  >
+                    #{tag.name} · {tag.count}
+                  </button>
+                ))}
+              </div>
+            )}
+            <Timeline
+              entries={entries}
+              viewNostrEvent={viewNostrEvent}
+              hasMore={!!nextCursor}
+              onLoadMore={loadMoreEntries}
+            />
           </>
         ) : activeTab === "friend-diaries" ? (
           <FriendDiaryReader />
@@ -1,18 +1,26 @@
 import { useState, useEffect } from "preact/hooks";
+import { listen } from "@tauri-apps/api/event";
 import { useNavigate } from "react-router-dom";
 import * as diaryService from "../utils/diaryService";
 import { useTheme } from "../contexts/ThemeContext";
 import { invoke } from "@tauri-apps/api/core";
+import { AuditProblem, AuditReport, BackupInfo, DataDirInfo, OutboxItem, PublicKeyInfo, RelayConnection, RelayHealth, RelayRole, RestoreProgress, RestoreReport, TimezoneReport } from "../types";
 
 interface Config {
   relay_urls: string[];
   default_relay_urls: string[];
+  backup_keep_count?: number;
+  backup_interval_hours?: number;
+  timezone?: string | null;
+  offline_mode?: boolean;
+    private_by_default?: boolean;
+  relay_roles?: Record<string, RelayRole>;
 }
 
 export function SettingsPage() {
   const navigate = useNavigate();
   const { theme, setTheme } = useTheme();
-  const [nostrPublicKey, setNostrPublicKey] = useState<string>("");
+  const [nostrPublicKey, setNostrPublicKey] = useState<PublicKeyInfo | null>(null);
   const [selectedDay, setSelectedDay] = useState<string>(
     new Date().toLocaleDateString('en-CA')
   );
@@ -27,22 +35,309 @@ export function SettingsPage() {
   });
   const [newRelayUrl, setNewRelayUrl] = useState("");
   const [error, setError] = useState("");
+  const [encrypted, setEncrypted] = useState(false);
+  const [currentPassphrase, setCurrentPassphrase] = useState("");
+  const [newPassphrase, setNewPassphrase] = useState("");
+  const [confirmPassphrase, setConfirmPassphrase] = useState("");
+  const [passphraseMessage, setPassphraseMessage] = useState("");
+  const [changingPassphrase, setChangingPassphrase] = useState(false);
+  const [backups, setBackups] = useState<BackupInfo[]>([]);
+  const [backingUp, setBackingUp] = useState(false);
+  const [dataDir, setDataDir] = useState<DataDirInfo | null>(null);
+  const [newDataDir, setNewDataDir] = useState("");
+  const [movingDataDir, setMovingDataDir] = useState(false);
+  const [auditReport, setAuditReport] = useState<AuditReport | null>(null);
+  const [auditing, setAuditing] = useState(false);
+  const [outbox, setOutbox] = useState<OutboxItem[]>([]);
+    const [retryingOutbox, setRetryingOutbox] = useState(false);
+    const [syncingRelayList, setSyncingRelayList] = useState(false);
+  const [relayHealth, setRelayHealth] = useState<RelayHealth[] | null>(null);
+    const [checkingRelays, setCheckingRelays] = useState(false);
+  const [relayConnections, setRelayConnections] = useState<RelayConnection[]>([]);
+  const [restoreReport, setRestoreReport] = useState<RestoreReport | null>(null);
+  const [restoreProgress, setRestoreProgress] = useState<RestoreProgress | null>(null);
+  const [restoring, setRestoring] = useState(false);
+  const [timezoneInput, setTimezoneInput] = useState("");
+  const [timezoneReport, setTimezoneReport] = useState<TimezoneReport | null>(null);
 
   useEffect(() => {
     loadNostrPublicKey();
     getCacheStatus();
     loadConfig();
+    loadVaultStatus();
+    loadBackups();
+    loadDataDir();
+        loadOutbox();
   }, []);
 
+  // Live connection state of the shared client, refreshed while the page is open
+  useEffect(() => {
+    loadRelayConnections();
+    const timer = setInterval(loadRelayConnections, 5000);
+    return () => clearInterval(timer);
+  }, []);
+
+  async function loadRelayConnections() {
+    try {
+      setRelayConnections(await diaryService.getRelayConnectionStatus());
+    } catch (error) {
+      console.error("Failed to load relay connections:", error);
+    }
+  }
+
+
+  async function loadOutbox() {
+    try {
+      const [held, pending, failed] = await Promise.all([
+        diaryService.listOutbox("held"),
+        diaryService.listOutbox("pending"),
+        diaryService.listOutbox("failed"),
+      ]);
+      setOutbox([...failed, ...pending, ...held]);
+    } catch (error) {
+      console.error("Failed to load outbox:", error);
+    }
+  }
+
+  async function retryOutbox() {
+    if (retryingOutbox) return;
+
+    setRetryingOutbox(true);
+    try {
+      const sent = await diaryService.retryOutbox();
+      await loadOutbox();
+      alert(`已发送到 ${sent} 个中继`);
+    } catch (error) {
+      alert(`重新发布失败：${error}`);
+    } finally {
+      setRetryingOutbox(false);
+    }
+  }
+
+  async function restoreFromRelays(dryRun: boolean) {
+    if (restoring) return;
+    if (!dryRun && !confirm("确定要从中继恢复日记吗？本地已有的日记不会被覆盖。")) return;
+
+    setRestoring(true);
+    const unlisten = await listen<RestoreProgress>("restore-progress", (event) => {
+      setRestoreProgress(event.payload);
+    });
+    try {
+      setRestoreReport(await diaryService.restoreFromRelays(dryRun));
+    } catch (error) {
+      alert(`从中继恢复失败：${error}`);
+    } finally {
+      unlisten();
+      setRestoreProgress(null);
+      setRestoring(false);
+    }
+  }
+
+  function describeRestoreProgress(progress: RestoreProgress): string {
+    switch (progress.stage) {
+      case "connecting": return `正在连接 ${progress.total} 个中继...`;
+      case "fetching": return `已获取 ${progress.current} 个事件...`;
+      case "saving": return `正在保存 ${progress.current + 1}/${progress.total}...`;
+      case "done": return "完成";
+    }
+  }
+
+  async function auditDiary() {
+    if (auditing) return;
+
+    setAuditing(true);
+    try {
+      setAuditReport(await diaryService.auditDiary());
+    } catch (error) {
+      alert("校验日记失败");
+    } finally {
+      setAuditing(false);
+    }
+  }
+
+  function describeAuditProblem(problem: AuditProblem): string {
+    switch (problem.kind) {
+      case "missing_event": return "缺少 Nostr 事件";
+      case "unparseable_event": return "事件无法解析";
+      case "placeholder_signature": return "导入的占位签名";
+      case "invalid_id": return "事件 ID 不正确";
+      case "invalid_signature": return "签名无效";
+      case "foreign_pubkey": return "不是本人的公钥";
+      case "wrong_kind": return `事件类型为 ${problem.event_kind}`;
+      case "nostr_id_mismatch": return "事件 ID 与记录不符";
+      case "content_mismatch": return "内容与事件不符";
+      case "day_tag_mismatch": return "日期标签不符";
+      case "weather_tag_mismatch": return "天气标签不符";
+      case "decryption_failed": return "加密事件无法解密";
+      case "visibility_mismatch": return problem.event_private ? "事件已加密但记录为公开" : "事件未加密但记录为私密";
+    }
+  }
+
+  async function loadDataDir() {
+    try {
+      setDataDir(await diaryService.getDataDirInfo());
+    } catch (error) {
+      console.error("Failed to load data directory:", error);
+    }
+  }
+
+  async function moveDataDir() {
+    const target = newDataDir.trim();
+    if (!target || movingDataDir) return;
+    if (!confirm(`确定要把日记数据复制到 ${target} 吗？原目录的数据会保留，复制完成后应用会自动重启。`)) return;
+
+    setMovingDataDir(true);
+    try {
+      const result = await diaryService.moveDataDir(target);
+      setNewDataDir("");
+      alert(`数据已复制到 ${result.new_path}，应用即将重启并使用新位置。确认无误后可手动删除 ${result.old_path}`);
+    } catch (error) {
+      alert(`迁移数据目录失败：${error}`);
+    } finally {
+      setMovingDataDir(false);
+    }
+  }
+
+  async function loadBackups() {
+    try {
+      setBackups(await diaryService.listBackups());
+    } catch (error) {
+      console.error("Failed to load backups:", error);
+    }
+  }
+
+  async function createBackup() {
+    if (backingUp) return;
+
+    setBackingUp(true);
+    try {
+      await diaryService.createBackup();
+      await loadBackups();
+    } catch (error) {
+      alert("创建备份失败");
+    } finally {
+      setBackingUp(false);
+    }
+  }
+
+  async function restoreBackup(backup: BackupInfo) {
+    const time = new Date(backup.created_at).toLocaleString();
+    if (!confirm(`确定要恢复到 ${time} 的备份吗？当前数据会先自动备份。`)) return;
+
+    try {
+      await diaryService.restoreBackup(backup.file_name);
+      await loadBackups();
+      alert("已从备份恢复");
+    } catch (error) {
+      alert("恢复备份失败");
+    }
+  }
+
+  async function updateBackupSettings(changes: Partial<Config>) {
+    const newConfig = { ...config, ...changes };
+    try {
+      setConfig(newConfig);
+      await invoke("update_config", { newConfig });
+    } catch (error) {
+      console.error("Failed to save backup settings:", error);
+      setConfig(config);
+    }
+  }
+
+  async function loadVaultStatus() {
+    try {
+      const status = await diaryService.getVaultStatus();
+      setEncrypted(status.encrypted);
+    } catch (error) {
+      console.error("Failed to load vault status:", error);
+    }
+  }
+
+  async function changePassphrase() {
+    if (changingPassphrase) return;
+
+    if (newPassphrase.length < 8) {
+      setPassphraseMessage("新密码至少需要 8 个字符");
+      return;
+    }
+    if (newPassphrase !== confirmPassphrase) {
+      setPassphraseMessage("两次输入的新密码不一致");
+      return;
+    }
+
+    setChangingPassphrase(true);
+    try {
+      await diaryService.changePassphrase(encrypted ? currentPassphrase : null, newPassphrase);
+      setPassphraseMessage(encrypted ? "密码已修改" : "日记已加密，下次启动时需要输入密码");
+      setEncrypted(true);
+      setCurrentPassphrase("");
+      setNewPassphrase("");
+      setConfirmPassphrase("");
+    } catch (error) {
+      setPassphraseMessage(String(error) === "Incorrect passphrase" ? "当前密码错误" : "修改密码失败");
+    } finally {
+      setChangingPassphrase(false);
+    }
+  }
+
   async function loadConfig() {
     try {
       const config = await invoke<Config>("get_config");
       setConfig(config);
+      setTimezoneInput(config.timezone ?? "");
+      setTimezoneReport(await diaryService.getTimezoneReport());
     } catch (error) {
       console.error("Failed to load config:", error);
     }
   }
 
+  async function saveTimezone() {
+    const timezone = timezoneInput.trim() || null;
+    const newConfig = { ...config, timezone };
+    try {
+      await invoke("update_config", { newConfig });
+      setConfig(newConfig);
+      setTimezoneReport(await diaryService.getTimezoneReport());
+    } catch (error) {
+      alert(`保存时区失败：${error}`);
+    }
+  }
+
+  async function toggleOfflineMode() {
+    const newConfig = { ...config, offline_mode: !config.offline_mode };
+    try {
+      await invoke("update_config", { newConfig });
+      setConfig(newConfig);
+    } catch (error) {
+      alert(`保存设置失败：${error}`);
+    }
+  }
+
+  async function togglePrivateByDefault() {
+    const newConfig = { ...config, private_by_default: !(config.private_by_default ?? true) };
+    try {
+      await invoke("update_config", { newConfig });
+      setConfig(newConfig);
+    } catch (error) {
+      alert(`保存设置失败：${error}`);
+    }
+  }
+
+  async function publishPendingEntries() {
+    if (retryingOutbox) return;
+
+    setRetryingOutbox(true);
+    try {
+      const sent = await diaryService.publishPendingEntries();
+      await loadOutbox();
+      alert(`已发送到 ${sent} 个中继`);
+    } catch (error) {
+      alert(`发布失败：${error}`);
+    } finally {
+      setRetryingOutbox(false);
+    }
+  }
+
   async function addRelayUrl() {
     if (!newRelayUrl) return;
     
@@ -91,6 +386,68 @@ export function SettingsPage() {
     }
   }
 
+  // An empty role means the relay is used for both reading and writing
+  async function setRelayRole(url: string, role: RelayRole | "") {
+    const relayRoles = { ...(config.relay_roles ?? {}) };
+    if (role) {
+      relayRoles[url] = role;
+    } else {
+      delete relayRoles[url];
+    }
+    const newConfig = { ...config, relay_roles: relayRoles };
+
+    try {
+      await invoke("update_config", { newConfig });
+      setConfig(newConfig);
+    } catch (error) {
+      setError("保存中继设置失败");
+      console.error("Failed to save relay role:", error);
+    }
+  }
+
+  async function publishRelayList() {
+    if (syncingRelayList) return;
+
+    setSyncingRelayList(true);
+    try {
+      await diaryService.publishRelayList();
+      alert("中继列表已发布");
+    } catch (error) {
+      alert(`发布中继列表失败：${error}`);
+    } finally {
+      setSyncingRelayList(false);
+    }
+  }
+
+  async function importRelayList() {
+    if (syncingRelayList) return;
+    if (!confirm("用已发布的中继列表替换当前的中继设置？")) return;
+
+    setSyncingRelayList(true);
+    try {
+      const relays = await diaryService.importRelayList();
+      await loadConfig();
+      alert(`已导入 ${relays.length} 个中继`);
+    } catch (error) {
+      alert(`导入中继列表失败：${error}`);
+    } finally {
+      setSyncingRelayList(false);
+    }
+  }
+
+    async function checkRelays() {
+    if (checkingRelays) return;
+
+    setCheckingRelays(true);
+    try {
+      setRelayHealth(await diaryService.checkRelays());
+    } catch (error) {
+      alert(`检查中继失败：${error}`);
+    } finally {
+      setCheckingRelays(false);
+    }
+  }
+
   async function loadNostrPublicKey() {
     try {
       const publicKey = await diaryService.loadNostrPublicKey();
@@ -211,6 +568,38 @@ export function SettingsPage() {
                 跳转
               </button>
             </div>
+            <div className="flex items-center gap-3">
+              <input
+                type="text"
+                value={timezoneInput}
+                onInput={(e) => setTimezoneInput((e.target as HTMLInputElement).value)}
+                placeholder="时区，如 Asia/Shanghai，留空使用系统时区"
+                className="flex-1 px-3 py-1.5 text-sm rounded bg-[#f7f5f0] dark:bg-[#262630] text-[#5d5a4c] dark:text-[#a2e2d8] border border-[#e6e1d5] dark:border-[#323237] focus:outline-none focus:border-[#49b3a1] dark:focus:border-[#43a595]"
+              />
+              <button
+                className="text-[#49b3a1] dark:text-[#43a595] hover:text-[#3a9e8d] dark:hover:text-[#389384] text-sm px-3 py-1.5"
+                onClick={saveTimezone}
+              >
+                保存
+              </button>
+            </div>
+            {timezoneReport && (
+              <div className="text-sm text-[#8c7c67] dark:text-[#a6a69e] space-y-1">
+                <div>
+                  今天是 {timezoneReport.today}（{timezoneReport.timezone === "system" ? "系统时区" : timezoneReport.timezone}，UTC{timezoneReport.utc_offset}）
+                </div>
+                {timezoneReport.misfiled.length > 0 && (
+                  <div>
+                    <div>以下日记可能因按 UTC 计算日期而记在了前一天：</div>
+                    {timezoneReport.misfiled.map((entry) => (
+                      <div key={entry.entry_id}>
+                        {entry.day} → {entry.suggested_day}{entry.suggested_day_taken && "（该日已有日记）"}
+                      </div>
+                    ))}
+                  </div>
+                )}
+              </div>
+            )}
           </div>
         </section>
 
@@ -223,8 +612,13 @@ export function SettingsPage() {
                 Nostr 公钥
               </label>
               <div className="px-3 py-2 rounded bg-[#f7f5f0] dark:bg-[#262630] text-[#5d5a4c] dark:text-[#a2e2d8] border border-[#e6e1d5] dark:border-[#323237] font-mono text-sm break-all">
-                {nostrPublicKey ?? "未设置 Nostr 公钥"}
+                                {nostrPublicKey?.npub ?? "未设置 Nostr 公钥"}
               </div>
+              {nostrPublicKey && (
+                <p className="mt-1 text-xs text-[#8c7c67] dark:text-[#a6a69e] font-mono break-all">
+                  十六进制：{nostrPublicKey.hex}
+                </p>
+              )}
             </div>
 
             <div>
@@ -257,19 +651,411 @@ export function SettingsPage() {
                     key={url}
                     className="flex items-center justify-between p-2 bg-[#f9f6f0] dark:bg-[#2a2a28] rounded"
                   >
-                    <span className="text-sm text-[#8c7c67] dark:text-[#a6a69e]">
+                                                            <span className="flex items-center gap-2 text-sm text-[#8c7c67] dark:text-[#a6a69e]">
+                      {(() => {
+                        const connection = relayConnections.find((c) => c.url === url);
+                        const connected = connection?.status === "connected";
+                        return (
+                          <span
+                            className={`inline-block w-2 h-2 rounded-full ${connected ? "bg-green-500" : "bg-gray-400"}`}
+                            title={connection ? `${connection.status}${connection.latency_ms != null ? ` · ${connection.latency_ms} ms` : ""}` : "未连接"}
+                          />
+                        );
+                      })()}
                       {url}
                     </span>
-                    <button
-                      onClick={() => removeRelayUrl(url)}
-                      className="text-red-500 hover:text-red-700 dark:hover:text-red-400"
+                    <div className="flex items-center gap-3">
+                      <select
+                        value={config.relay_roles?.[url] ?? ""}
+                        onChange={(e) => setRelayRole(url, (e.target as HTMLSelectElement).value as RelayRole | "")}
+                        className="text-sm rounded border border-[#e9e4d9] dark:border-[#2c2c32] px-2 py-1 bg-white dark:bg-[#1a1a1e] text-[#8c7c67] dark:text-[#a6a69e]"
+                      >
+                        <option value="">读写</option>
+                        <option value="read">只读</option>
+                        <option value="write">只写</option>
+                      </select>
+                      <button
+                        onClick={() => removeRelayUrl(url)}
+                        className="text-red-500 hover:text-red-700 dark:hover:text-red-400"
+                      >
+                        删除
+                      </button>
+                    </div>
+                  </div>
+                ))}
+              </div>
+              <p className="text-xs text-[#8c7c67] dark:text-[#a6a69e] mt-2">
+                日记发布到可写的中继，加密分享从可读的中继接收。中继列表（NIP-65）让其他设备和联系人找到这些中继。
+              </p>
+              {config.relay_urls.length > 0 && config.relay_urls.every((url) => config.relay_roles?.[url] === "read") && (
+                <p className="text-xs text-amber-600 dark:text-amber-400 mt-1">没有可写的中继，日记不会发布。</p>
+              )}
+              {config.relay_urls.length > 0 && config.relay_urls.every((url) => config.relay_roles?.[url] === "write") && (
+                <p className="text-xs text-amber-600 dark:text-amber-400 mt-1">没有可读的中继，收不到加密分享。</p>
+              )}
+              <div className="flex gap-2 mt-2">
+                <button
+                  onClick={publishRelayList}
+                  disabled={syncingRelayList}
+                  className="px-3 py-1 text-sm rounded border border-[#e9e4d9] dark:border-[#2c2c32] text-[#8c7c67] dark:text-[#a6a69e] hover:bg-[#f9f6f0] dark:hover:bg-[#2a2a28] disabled:opacity-50"
+                >
+                  发布中继列表
+                </button>
+                <button
+                  onClick={importRelayList}
+                  disabled={syncingRelayList}
+                  className="px-3 py-1 text-sm rounded border border-[#e9e4d9] dark:border-[#2c2c32] text-[#8c7c67] dark:text-[#a6a69e] hover:bg-[#f9f6f0] dark:hover:bg-[#2a2a28] disabled:opacity-50"
+                >
+                  导入中继列表
+                </button>
+                <button
+                  onClick={checkRelays}
+                  disabled={checkingRelays}
+                  className="px-3 py-1 text-sm rounded border border-[#e9e4d9] dark:border-[#2c2c32] text-[#8c7c67] dark:text-[#a6a69e] hover:bg-[#f9f6f0] dark:hover:bg-[#2a2a28] disabled:opacity-50"
+                >
+                  {checkingRelays ? "检查中..." : "检查中继"}
+                </button>
+              </div>
+              {relayHealth && (
+                <div className="mt-2 space-y-2">
+                  {relayHealth.map((relay) => (
+                    <div
+                      key={relay.url}
+                      className="p-2 bg-[#f9f6f0] dark:bg-[#2a2a28] rounded text-xs text-[#8c7c67] dark:text-[#a6a69e]"
+                    >
+                      <div className="flex justify-between">
+                        <span className="font-mono break-all">{relay.url}</span>
+                        <span className={relay.reachable ? "text-green-600 dark:text-green-400" : "text-red-500"}>
+                          {relay.reachable ? `${relay.latency_ms} ms` : "无法连接"}
+                        </span>
+                      </div>
+                      {relay.error && <div className="text-red-500">{relay.error}</div>}
+                      {relay.information_error ? (
+                        <div>无法获取中继信息（NIP-11）</div>
+                      ) : (
+                        <>
+                          {relay.name && <div>{relay.name}{relay.software ? ` · ${relay.software}` : ""}</div>}
+                          <div>支持的 NIP：{relay.supported_nips.length > 0 ? relay.supported_nips.join(", ") : "未知"}</div>
+                          <div>
+                            最大消息长度：{relay.max_message_length ?? "未限制"}
+                            {relay.max_content_length != null && `，最大内容长度：${relay.max_content_length}`}
+                          </div>
+                          {(relay.auth_required || relay.payment_required) && (
+                            <div className="text-amber-600 dark:text-amber-400">
+                              {[relay.auth_required && "需要认证", relay.payment_required && "需要付费"].filter(Boolean).join("，")}
+                            </div>
+                          )}
+                        </>
+                      )}
+                    </div>
+                  ))}
+                </div>
+              )}
+            </div>
+
+            <div className="flex items-center justify-between">
+              <div>
+                <label className="block text-sm font-medium text-[#8c7c67] dark:text-[#a6a69e]">
+                  默认加密发布
+                </label>
+                <p className="text-xs text-[#8c7c67] dark:text-[#a6a69e] mt-1">
+                  新日记用自己的公钥加密（NIP-44）后再发布，中继只保存密文
+                </p>
+              </div>
+              <input
+                type="checkbox"
+                checked={config.private_by_default ?? true}
+                onChange={togglePrivateByDefault}
+              />
+            </div>
+
+            <div className="flex items-center justify-between">
+              <div>
+                <label className="block text-sm font-medium text-[#8c7c67] dark:text-[#a6a69e]">
+                  离线模式
+                </label>
+                <p className="text-xs text-[#8c7c67] dark:text-[#a6a69e] mt-1">
+                  保存日记时不连接中继，签名后的事件留在本地待发布
+                </p>
+              </div>
+              <input
+                type="checkbox"
+                checked={config.offline_mode ?? false}
+                onChange={toggleOfflineMode}
+              />
+            </div>
+
+            <div>
+              <div className="flex items-center justify-between mb-2">
+                <label className="block text-sm font-medium text-[#8c7c67] dark:text-[#a6a69e]">
+                  待发布
+                </label>
+                <div className="flex">
+                  <button
+                    onClick={publishPendingEntries}
+                    disabled={retryingOutbox || !outbox.some((item) => item.status === "held")}
+                    className={`text-[#49b3a1] dark:text-[#43a595] hover:text-[#3a9e8d] dark:hover:text-[#389384] px-3 py-1.5 text-sm ${
+                      retryingOutbox || !outbox.some((item) => item.status === "held") ? 'opacity-50 cursor-not-allowed' : ''
+                    }`}
+                  >
+                    全部发布
+                  </button>
+                  <button
+                    onClick={retryOutbox}
+                    disabled={retryingOutbox || outbox.length === 0}
+                    className={`text-[#49b3a1] dark:text-[#43a595] hover:text-[#3a9e8d] dark:hover:text-[#389384] px-3 py-1.5 text-sm ${
+                      retryingOutbox || outbox.length === 0 ? 'opacity-50 cursor-not-allowed' : ''
+                    }`}
+                  >
+                    {retryingOutbox ? "发布中..." : "立即重试"}
+                  </button>
+                </div>
+              </div>
+              {outbox.length === 0 ? (
+                <p className="text-sm text-[#8c7c67] dark:text-[#a6a69e]">所有日记都已发布到中继</p>
+              ) : (
+                <div className="space-y-2 max-h-64 overflow-y-auto">
+                  {outbox.map((item) => (
+                    <div
+                      key={item.id}
+                      className="p-2 bg-[#f9f6f0] dark:bg-[#2a2a28] rounded text-sm text-[#8c7c67] dark:text-[#a6a69e]"
                     >
-                      删除
-                    </button>
+                      <div className="font-mono break-all">{item.relay_url}</div>
+                      <div>
+                        {item.status === "held"
+                          ? "稍后发布"
+                          : `${item.status === "failed" ? "已放弃" : "等待重试"}，已尝试 ${item.attempts} 次`}
+                        {item.last_error && `：${item.last_error}`}
+                      </div>
+                    </div>
+                  ))}
+                </div>
+              )}
+            </div>
+          </div>
+        </section>
+
+        {/* 加密设置 */}
+        <section className="bg-white dark:bg-[#1e1e24] rounded-lg p-4 shadow-sm">
+          <h2 className="text-base font-medium mb-3 text-[#42403a] dark:text-[#e6e1d5]">加密设置</h2>
+          <div className="space-y-3">
+            <p className="text-sm text-[#8c7c67] dark:text-[#a6a69e]">
+              {encrypted
+                ? "日记数据库和 Nostr 私钥已使用密码加密"
+                : "设置密码后，日记数据库和 Nostr 私钥将被加密保存。忘记密码将无法恢复数据"}
+            </p>
+            {passphraseMessage && (
+              <div className="p-2 bg-[#f9f6f0] dark:bg-[#2a2a28] text-[#8c7c67] dark:text-[#a6a69e] rounded text-sm">
+                {passphraseMessage}
+              </div>
+            )}
+            {encrypted && (
+              <input
+                type="password"
+                value={currentPassphrase}
+                onInput={(e) => setCurrentPassphrase((e.target as HTMLInputElement).value)}
+                placeholder="当前密码"
+                className="w-full rounded-md border border-[#e9e4d9] dark:border-[#2c2c32] px-3 py-2 bg-white dark:bg-[#1a1a1e] text-[#8c7c67] dark:text-[#a6a69e] focus:outline-none focus:ring-2 focus:ring-[#49b3a1] dark:focus:ring-[#43a595]"
+              />
+            )}
+            <input
+              type="password"
+              value={newPassphrase}
+              onInput={(e) => setNewPassphrase((e.target as HTMLInputElement).value)}
+              placeholder="新密码"
+              className="w-full rounded-md border border-[#e9e4d9] dark:border-[#2c2c32] px-3 py-2 bg-white dark:bg-[#1a1a1e] text-[#8c7c67] dark:text-[#a6a69e] focus:outline-none focus:ring-2 focus:ring-[#49b3a1] dark:focus:ring-[#43a595]"
+            />
+            <input
+              type="password"
+              value={confirmPassphrase}
+              onInput={(e) => setConfirmPassphrase((e.target as HTMLInputElement).value)}
+              placeholder="确认新密码"
+              className="w-full rounded-md border border-[#e9e4d9] dark:border-[#2c2c32] px-3 py-2 bg-white dark:bg-[#1a1a1e] text-[#8c7c67] dark:text-[#a6a69e] focus:outline-none focus:ring-2 focus:ring-[#49b3a1] dark:focus:ring-[#43a595]"
+            />
+            <button
+              onClick={changePassphrase}
+              disabled={changingPassphrase}
+              className={`bg-gradient-to-r from-[#49b3a1] to-[#3a9e8d] dark:from-[#43a595] dark:to-[#389384] text-white text-sm py-2 px-4 rounded-full hover:shadow-md ${
+                changingPassphrase ? 'opacity-50 cursor-not-allowed' : ''
+              }`}
+            >
+              {changingPassphrase ? "处理中..." : encrypted ? "修改密码" : "设置密码"}
+            </button>
+          </div>
+        </section>
+
+        {/* 备份 */}
+        <section className="bg-white dark:bg-[#1e1e24] rounded-lg p-4 shadow-sm">
+          <h2 className="text-base font-medium mb-3 text-[#42403a] dark:text-[#e6e1d5]">备份</h2>
+          <div className="space-y-3">
+            <div className="flex flex-wrap items-center gap-3 text-sm text-[#8c7c67] dark:text-[#a6a69e]">
+              <label className="flex items-center gap-2">
+                自动备份间隔
+                <select
+                  value={config.backup_interval_hours ?? 24}
+                  onChange={(e) => updateBackupSettings({ backup_interval_hours: Number((e.target as HTMLSelectElement).value) })}
+                  className="px-2 py-1 rounded bg-[#f7f5f0] dark:bg-[#262630] text-[#5d5a4c] dark:text-[#a2e2d8] border border-[#e6e1d5] dark:border-[#323237] focus:outline-none focus:border-[#49b3a1] dark:focus:border-[#43a595]"
+                >
+                  <option value={0}>关闭</option>
+                  <option value={6}>6 小时</option>
+                  <option value={24}>每天</option>
+                  <option value={168}>每周</option>
+                </select>
+              </label>
+              <label className="flex items-center gap-2">
+                保留份数
+                <input
+                  type="number"
+                  min={1}
+                  value={config.backup_keep_count ?? 10}
+                  onChange={(e) => updateBackupSettings({ backup_keep_count: Math.max(1, Number((e.target as HTMLInputElement).value)) })}
+                  className="w-20 px-2 py-1 rounded bg-[#f7f5f0] dark:bg-[#262630] text-[#5d5a4c] dark:text-[#a2e2d8] border border-[#e6e1d5] dark:border-[#323237] focus:outline-none focus:border-[#49b3a1] dark:focus:border-[#43a595]"
+                />
+              </label>
+              <button
+                onClick={createBackup}
+                disabled={backingUp}
+                className={`text-[#49b3a1] dark:text-[#43a595] hover:text-[#3a9e8d] dark:hover:text-[#389384] px-3 py-1.5 ${
+                  backingUp ? 'opacity-50 cursor-not-allowed' : ''
+                }`}
+              >
+                {backingUp ? "备份中..." : "立即备份"}
+              </button>
+            </div>
+            <div className="space-y-2">
+              {backups.map((backup) => (
+                <div
+                  key={backup.file_name}
+                  className="flex items-center justify-between p-2 bg-[#f9f6f0] dark:bg-[#2a2a28] rounded"
+                >
+                  <span className="text-sm text-[#8c7c67] dark:text-[#a6a69e]">
+                    {new Date(backup.created_at).toLocaleString()} · {backup.reason} · {Math.ceil(backup.size_bytes / 1024)} KB
+                  </span>
+                  <button
+                    onClick={() => restoreBackup(backup)}
+                    className="text-sm text-[#49b3a1] dark:text-[#43a595] hover:text-[#3a9e8d] dark:hover:text-[#389384]"
+                  >
+                    恢复
+                  </button>
+                </div>
+              ))}
+              {backups.length === 0 && (
+                <div className="text-sm text-[#8c7c67] dark:text-[#a6a69e]">暂无备份</div>
+              )}
+            </div>
+          </div>
+        </section>
+
+        {/* 数据校验 */}
+        <section className="bg-white dark:bg-[#1e1e24] rounded-lg p-4 shadow-sm">
+          <h2 className="text-base font-medium mb-3 text-[#42403a] dark:text-[#e6e1d5]">数据校验</h2>
+          <div className="space-y-3">
+            <div className="flex items-center justify-between text-sm text-[#8c7c67] dark:text-[#a6a69e]">
+              <span>
+                {auditReport
+                  ? `共 ${auditReport.total_entries} 篇，${auditReport.valid_entries} 篇校验通过`
+                  : "检查每篇日记的签名、事件 ID 和内容是否一致"}
+              </span>
+              <button
+                onClick={auditDiary}
+                disabled={auditing}
+                className={`text-[#49b3a1] dark:text-[#43a595] hover:text-[#3a9e8d] dark:hover:text-[#389384] px-3 py-1.5 ${
+                  auditing ? 'opacity-50 cursor-not-allowed' : ''
+                }`}
+              >
+                {auditing ? "校验中..." : "开始校验"}
+              </button>
+            </div>
+            {auditReport && auditReport.entries.length > 0 && (
+              <div className="space-y-2 max-h-64 overflow-y-auto">
+                {auditReport.entries.map((entry) => (
+                  <div
+                    key={entry.entry_id}
+                    className="p-2 bg-[#f9f6f0] dark:bg-[#2a2a28] rounded text-sm text-[#8c7c67] dark:text-[#a6a69e]"
+                  >
+                    {entry.day ?? entry.entry_id}{entry.trashed && "（回收站）"}：
+                    {entry.problems.map(describeAuditProblem).join("，")}
                   </div>
                 ))}
               </div>
+            )}
+          </div>
+        </section>
+
+        {/* 从中继恢复 */}
+        <section className="bg-white dark:bg-[#1e1e24] rounded-lg p-4 shadow-sm">
+          <h2 className="text-base font-medium mb-3 text-[#42403a] dark:text-[#e6e1d5]">从中继恢复</h2>
+          <div className="space-y-3">
+            <div className="flex items-center justify-between text-sm text-[#8c7c67] dark:text-[#a6a69e]">
+              <span>
+                {restoreProgress
+                  ? describeRestoreProgress(restoreProgress)
+                  : "从中继取回本公钥签名的日记，补全本地缺失的日子"}
+              </span>
+              <div className="flex">
+                <button
+                  onClick={() => restoreFromRelays(true)}
+                  disabled={restoring}
+                  className={`text-[#49b3a1] dark:text-[#43a595] hover:text-[#3a9e8d] dark:hover:text-[#389384] px-3 py-1.5 ${
+                    restoring ? 'opacity-50 cursor-not-allowed' : ''
+                  }`}
+                >
+                  预览
+                </button>
+                <button
+                  onClick={() => restoreFromRelays(false)}
+                  disabled={restoring}
+                  className={`text-[#49b3a1] dark:text-[#43a595] hover:text-[#3a9e8d] dark:hover:text-[#389384] px-3 py-1.5 ${
+                    restoring ? 'opacity-50 cursor-not-allowed' : ''
+                  }`}
+                >
+                  {restoring ? "恢复中..." : "恢复"}
+                </button>
+              </div>
+            </div>
+            {restoreReport && (
+              <div className="p-2 bg-[#f9f6f0] dark:bg-[#2a2a28] rounded text-sm text-[#8c7c67] dark:text-[#a6a69e]">
+                从 {restoreReport.relays.length} 个中继获取 {restoreReport.fetched_events} 个事件，
+                {restoreReport.dry_run ? "可恢复" : "已恢复"} {restoreReport.days.filter((d) => d.outcome === "restored").length} 篇，
+                {restoreReport.days.filter((d) => d.outcome === "unchanged").length} 篇已在本地
+                {restoreReport.days.some((d) => d.outcome === "conflict") &&
+                  `，${restoreReport.days.filter((d) => d.outcome === "conflict").map((d) => d.day).join("、")} 与本地不同，已保留本地版本`}
+                {restoreReport.invalid_events > 0 && `，${restoreReport.invalid_events} 个事件校验失败`}
+                {restoreReport.deleted_days > 0 && `，${restoreReport.deleted_days} 篇已被删除`}
+              </div>
+            )}
+          </div>
+        </section>
+
+        {/* 数据目录 */}
+        <section className="bg-white dark:bg-[#1e1e24] rounded-lg p-4 shadow-sm">
+          <h2 className="text-base font-medium mb-3 text-[#42403a] dark:text-[#e6e1d5]">数据目录</h2>
+          <div className="space-y-3">
+            <div className="text-sm text-[#8c7c67] dark:text-[#a6a69e] break-all">
+              {dataDir ? dataDir.path : "加载中..."}
+              {dataDir?.source === "portable" && " （便携模式）"}
+              {(dataDir?.source === "command_line" || dataDir?.source === "environment") && " （由启动参数或环境变量指定）"}
             </div>
+            {(dataDir?.source === "default" || dataDir?.source === "moved") && (
+              <div className="flex gap-2">
+                <input
+                  type="text"
+                  value={newDataDir}
+                  onInput={(e) => setNewDataDir((e.target as HTMLInputElement).value)}
+                  placeholder="新的数据目录（绝对路径）"
+                  className="flex-1 px-3 py-1.5 text-sm rounded bg-[#f7f5f0] dark:bg-[#262630] text-[#5d5a4c] dark:text-[#a2e2d8] border border-[#e6e1d5] dark:border-[#323237] focus:outline-none focus:border-[#49b3a1] dark:focus:border-[#43a595]"
+                />
+                <button
+                  onClick={moveDataDir}
+                  disabled={movingDataDir || !newDataDir.trim()}
+                  className={`text-[#49b3a1] dark:text-[#43a595] hover:text-[#3a9e8d] dark:hover:text-[#389384] px-3 py-1.5 ${
+                    movingDataDir || !newDataDir.trim() ? 'opacity-50 cursor-not-allowed' : ''
+                  }`}
+                >
+                  {movingDataDir ? "迁移中..." : "迁移"}
+                </button>
+              </div>
+            )}
           </div>
         </section>
 
@@ -0,0 +1,62 @@
+import { useState } from "preact/hooks";
+import * as diaryService from "../utils/diaryService";
+
+interface UnlockPageProps {
+  onUnlocked: () => void;
+}
+
+export function UnlockPage({ onUnlocked }: UnlockPageProps) {
+  const [passphrase, setPassphrase] = useState("");
+  const [unlocking, setUnlocking] = useState(false);
+  const [error, setError] = useState("");
+
+  async function handleSubmit(e: Event) {
+    e.preventDefault();
+    if (!passphrase || unlocking) return;
+
+    setUnlocking(true);
+    try {
+      await diaryService.unlockDiary(passphrase);
+      setPassphrase("");
+      setError("");
+      onUnlocked();
+    } catch (error) {
+      setError(String(error) === "Incorrect passphrase" ? "密码错误" : "解锁失败");
+    } finally {
+      setUnlocking(false);
+    }
+  }
+
+  return (
+    <main className="min-h-screen flex items-center justify-center bg-[#faf9f6] dark:bg-[#121214] px-4">
+      <form
+        onSubmit={handleSubmit}
+        className="w-full max-w-sm bg-white dark:bg-[#1e1e24] rounded-lg p-6 shadow-sm space-y-4"
+      >
+        <h1 className="text-xl font-medium text-[#42403a] dark:text-[#e6e1d5]">日记已加密</h1>
+        <p className="text-sm text-[#8c7c67] dark:text-[#a6a69e]">请输入密码以解锁日记</p>
+        {error && (
+          <div className="p-2 bg-red-100 dark:bg-red-900 text-red-700 dark:text-red-200 rounded text-sm">
+            {error}
+          </div>
+        )}
+        <input
+          type="password"
+          value={passphrase}
+          autoFocus
+          onInput={(e) => setPassphrase((e.target as HTMLInputElement).value)}
+          className="w-full rounded-md border border-[#e9e4d9] dark:border-[#2c2c32] px-3 py-2 bg-white dark:bg-[#1a1a1e] text-[#8c7c67] dark:text-[#a6a69e] focus:outline-none focus:ring-2 focus:ring-[#49b3a1] dark:focus:ring-[#43a595]"
+        />
+        <button
+          type="submit"
+          disabled={unlocking}
+          className={`w-full bg-gradient-to-r from-[#49b3a1] to-[#3a9e8d] dark:from-[#43a595] dark:to-[#389384] text-white text-sm py-2.5 rounded-full hover:shadow-md ${
+            unlocking ? 'opacity-50 cursor-not-allowed' : ''
+          }`}
+        >
+          {unlocking ? "解锁中..." : "解锁"}
+        </button>
+      </form>
+    </main>
+  );
+}
@@ -2,6 +2,7 @@ import { useState, useEffect } from "preact/hooks";
 import { WriteContainer } from "../components/WriteContainer";
 import { Header } from "../components/Header";
 import * as diaryService from "../utils/diaryService";
+import { invoke } from "@tauri-apps/api/core";
 import { useNavigate, useSearchParams } from "react-router-dom";
 import { DiaryEntry } from "../types";
 
@@ -18,9 +19,18 @@ export function WritePage() {
   );
   const [dayHasEntry, setDayHasEntry] = useState<boolean>(false);
   const [errorMessage, setErrorMessage] = useState<string>("");
+  const [publishLater, setPublishLater] = useState(false);
+  const [isPrivate, setIsPrivate] = useState(true);
 
   useEffect(() => {
     loadEntries();
+    invoke<{ private_by_default?: boolean }>("get_config")
+      .then((config) => setIsPrivate(config.private_by_default ?? true))
+      .catch(() => {});
+    // 按设置中的时区确定今天
+    if (!dateParam) {
+      diaryService.getToday().then(setSelectedDay).catch(() => {});
+    }
   }, []);
 
   useEffect(() => {
@@ -64,10 +74,25 @@ export function WritePage() {
 
   async function saveDiaryEntry() {
     if (!content.trim()) return;
-    setErrorMessage("");
-    
+        setErrorMessage("");
+
+    // Relays that would reject the entry for its size, from their NIP-11 limits
+    try {
+      const warnings = await diaryService.checkEntryLimits(content, weather, selectedDay, undefined, isPrivate);
+      if (warnings.length > 0) {
+        const lines = warnings.map((w) =>
+          `${w.relay_url}：${w.limit === "max_content_length" ? "内容" : "消息"}长度 ${w.actual}，上限 ${w.allowed}`
+        );
+        if (!confirm(`这篇日记超出了部分中继的限制，可能无法发布到这些中继：\n${lines.join("\n")}\n\n仍然保存？`)) {
+          return;
+        }
+      }
+    } catch (error) {
+      console.error("Failed to check relay limits:", error);
+    }
+
     try {
-      await diaryService.saveDiaryEntry(content, weather, selectedDay);
+      await diaryService.saveDiaryEntry(content, weather, selectedDay, undefined, publishLater, isPrivate);
       await loadEntries();
       setDayHasEntry(true);
     } catch (error: any) {
@@ -93,6 +118,10 @@ export function WritePage() {
           weather={weather}
           setWeather={setWeather}
           errorMessage={errorMessage}
+          publishLater={publishLater}
+          setPublishLater={setPublishLater}
+          isPrivate={isPrivate}
+          setIsPrivate={setIsPrivate}
           saveDiaryEntry={saveDiaryEntry}
         />
       </div>
@@ -5,4 +5,5 @@ export interface DiaryEntry {
   created_at: string;
   nostr_id?: string;
   day: string; // YYYY-MM-DD format
+  updated_at?: string;
 } 
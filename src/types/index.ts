@@ -5,6 +5,253 @@ export interface DiaryEntry {
   created_at: string;
   nostr_id?: string;
   day: string; // YYYY-MM-DD format
+  updated_at?: string;
+  utc_offset?: string; // e.g. "+08:00"
+  private?: boolean; // published encrypted to the diary's own key
+}
+
+export interface SearchResult {
+  entry: DiaryEntry;
+  snippet: string; // HTML-escaped excerpt, matches wrapped in <mark>
+  rank: number;
+}
+
+export interface EntryRevision {
+  revision: number;
+  nostr_id?: string;
+  content: string;
+  weather: string;
+  created_at: string;
+}
+
+export interface DiffSegment {
+  kind: "equal" | "insert" | "delete";
+  text: string;
+}
+
+export interface RevisionDiff {
+  from_revision: number;
+  to_revision: number;
+  weather_before: string;
+  weather_after: string;
+  changes: DiffSegment[];
+}
+
+export interface TrashedEntry {
+  entry: DiaryEntry;
+  deleted_at: string;
+}
+
+export interface PurgeResult {
+  day: string;
+  deletion_event_id: string;
+  withdrawn_event_ids: string[];
+}
+
+export interface EntryQuery {
+  cursor?: string;
+  limit?: number;
+  start_day?: string;
+  end_day?: string;
+  weather?: string;
+  published?: boolean;
+  shared_with?: string;
+  tag?: string;
+}
+
+export interface EntryPage {
+  entries: DiaryEntry[];
+  total: number;
+  next_cursor?: string;
+}
+
+export interface VaultStatus {
+  encrypted: boolean;
+  unlocked: boolean;
+}
+
+export interface BackupInfo {
+  file_name: string;
+  reason: string;
+  created_at: string;
+  size_bytes: number;
+}
+
+export interface DataDirInfo {
+  path: string;
+  source: "command_line" | "environment" | "portable" | "moved" | "default";
+}
+
+export interface DataDirMove {
+  old_path: string;
+  new_path: string;
+  restart_required: boolean;
+}
+
+export type AuditProblem =
+  | { kind: "missing_event" }
+  | { kind: "unparseable_event"; error: string }
+  | { kind: "placeholder_signature" }
+  | { kind: "invalid_id" }
+  | { kind: "invalid_signature" }
+  | { kind: "foreign_pubkey"; pubkey: string }
+  | { kind: "wrong_kind"; event_kind: number }
+  | { kind: "nostr_id_mismatch"; column: string | null; event: string }
+  | { kind: "content_mismatch" }
+  | { kind: "day_tag_mismatch"; tag: string | null }
+  | { kind: "weather_tag_mismatch"; tag: string | null }
+  | { kind: "decryption_failed"; error: string }
+  | { kind: "visibility_mismatch"; event_private: boolean };
+
+export interface EntryAudit {
+  entry_id: string;
+  day: string | null;
+  nostr_id: string | null;
+  trashed: boolean;
+  problems: AuditProblem[];
+}
+
+export interface AuditReport {
+  checked_at: string;
+  pubkey: string;
+  total_entries: number;
+  valid_entries: number;
+  entries: EntryAudit[];
+}
+
+export interface Attachment {
+  sha256: string;
+  file_name: string;
+  mime_type: string;
+  size_bytes: number;
+  width: number | null;
+  height: number | null;
+  created_at: string;
+}
+
+export interface TagCount {
+  name: string;
+  count: number;
+}
+
+export interface EntryTag {
+  name: string;
+  manual: boolean;
+}
+
+export interface MisfiledEntry {
+  entry_id: string;
+  day: string;
+  created_at: string;
+  suggested_day: string;
+  suggested_day_taken: boolean;
+}
+
+export interface TimezoneReport {
+  timezone: string;
+  today: string;
+  utc_offset: string;
+  misfiled: MisfiledEntry[];
+}
+
+export type OutboxStatus = "pending" | "held" | "sent" | "failed" | "superseded";
+
+export interface OutboxItem {
+  id: number;
+  event_id: string;
+  kind: number;
+  relay_url: string;
+  status: OutboxStatus;
+  attempts: number;
+  last_error: string | null;
+  next_attempt_at: string | null;
+  created_at: string;
+  sent_at: string | null;
+}
+
+export interface RelayReceipt {
+  relay_url: string;
+  accepted: boolean;
+  message: string | null;
+  received_at: string | null;
+  retrying: boolean;
+}
+
+export interface PublishStatus {
+  event_id: string | null;
+  accepted: number;
+  total: number;
+  held: boolean;
+  private: boolean;
+  relays: RelayReceipt[];
+}
+
+export interface RestoreProgress {
+  stage: "connecting" | "fetching" | "saving" | "done";
+  current: number;
+  total: number;
+}
+
+export interface RestoredDay {
+  day: string;
+  event_id: string;
+  created_at: string;
+  outcome: "restored" | "unchanged" | "conflict";
+}
+
+export interface RestoreReport {
+  dry_run: boolean;
+  relays: string[];
+  fetched_events: number;
+  invalid_events: number;
+  deleted_days: number;
+  days: RestoredDay[];
+}
+
+export type RelayRole = "read" | "write";
+
+export interface RelayListEntry {
+  url: string;
+  read: boolean;
+  write: boolean;
+}
+
+export interface RelayHealth {
+  url: string;
+  reachable: boolean;
+  latency_ms: number | null;
+  error: string | null;
+  name: string | null;
+  software: string | null;
+  supported_nips: number[];
+  max_message_length: number | null;
+  max_content_length: number | null;
+  auth_required: boolean;
+  payment_required: boolean;
+  information_error: string | null;
+}
+
+export interface RelayConnection {
+  url: string;
+  status: "initialized" | "pending" | "connecting" | "connected" | "disconnected" | "terminated" | "banned";
+  configured: boolean;
+  connected_at: number | null;
+  latency_ms: number | null;
+  attempts: number;
+  successes: number;
+}
+
+export interface PublicKeyInfo {
+  hex: string;
+  npub: string;
+  relays: string[]; // relay hints of an nprofile
+}
+
+export interface RelayLimitWarning {
+  relay_url: string;
+  limit: "max_message_length" | "max_content_length";
+  allowed: number;
+  actual: number;
 }
 
 export interface LuXunDiaryEntry {
@@ -1,16 +1,295 @@
 import { invoke } from "@tauri-apps/api/core";
-import { DiaryEntry, LuXunDiaryEntry, CommonDiary, UnwrappedGiftResponse } from "../types";
+import { DiaryEntry, LuXunDiaryEntry, CommonDiary, UnwrappedGiftResponse, SearchResult, EntryRevision, RevisionDiff, TrashedEntry, PurgeResult, EntryQuery, EntryPage, VaultStatus, BackupInfo, DataDirInfo, DataDirMove, AuditReport, Attachment, TagCount, EntryTag, TimezoneReport, OutboxItem, OutboxStatus, PublishStatus, RestoreReport, RelayListEntry, RelayHealth, RelayLimitWarning, RelayConnection, PublicKeyInfo } from "../types";
 import luxunDiaries from '../assets/luxun-full-diary.json';
 
-export async function loadNostrPublicKey(): Promise<string> {
+export async function loadNostrPublicKey(): Promise<PublicKeyInfo> {
   try {
-    return await invoke<string>("get_nostr_public_key");
+    return await invoke<PublicKeyInfo>("get_nostr_public_key");
   } catch (error) {
     console.error("Failed to load Nostr public key:", error);
     throw error;
   }
 }
 
+export async function getVaultStatus(): Promise<VaultStatus> {
+  try {
+    return await invoke<VaultStatus>("get_vault_status");
+  } catch (error) {
+    console.error("Failed to get vault status:", error);
+    throw error;
+  }
+}
+
+export async function unlockDiary(passphrase: string): Promise<void> {
+  try {
+    await invoke<void>("unlock_diary", { passphrase });
+  } catch (error) {
+    console.error("Failed to unlock diary:", error);
+    throw error;
+  }
+}
+
+export async function changePassphrase(currentPassphrase: string | null, newPassphrase: string): Promise<void> {
+  try {
+    await invoke<void>("change_passphrase", { currentPassphrase, newPassphrase });
+  } catch (error) {
+    console.error("Failed to change passphrase:", error);
+    throw error;
+  }
+}
+
+export async function listBackups(): Promise<BackupInfo[]> {
+  try {
+    return await invoke<BackupInfo[]>("list_backups");
+  } catch (error) {
+    console.error("Failed to list backups:", error);
+    throw error;
+  }
+}
+
+export async function createBackup(): Promise<BackupInfo> {
+  try {
+    return await invoke<BackupInfo>("create_backup");
+  } catch (error) {
+    console.error("Failed to create backup:", error);
+    throw error;
+  }
+}
+
+export async function restoreBackup(fileName: string): Promise<void> {
+  try {
+    await invoke<void>("restore_backup", { fileName });
+  } catch (error) {
+    console.error("Failed to restore backup:", error);
+    throw error;
+  }
+}
+
+export async function getToday(): Promise<string> {
+  try {
+    return await invoke<string>("get_today");
+  } catch (error) {
+    console.error("Failed to get today:", error);
+    throw error;
+  }
+}
+
+export async function getTimezoneReport(): Promise<TimezoneReport> {
+  try {
+    return await invoke<TimezoneReport>("get_timezone_report");
+  } catch (error) {
+    console.error("Failed to get timezone report:", error);
+    throw error;
+  }
+}
+
+export async function listTags(): Promise<TagCount[]> {
+  try {
+    return await invoke<TagCount[]>("list_tags");
+  } catch (error) {
+    console.error("Failed to list tags:", error);
+    throw error;
+  }
+}
+
+export async function getEntryTags(day: string): Promise<EntryTag[]> {
+  try {
+    return await invoke<EntryTag[]>("get_entry_tags", { day });
+  } catch (error) {
+    console.error("Failed to get entry tags:", error);
+    throw error;
+  }
+}
+
+export async function setEntryTags(day: string, tags: string[]): Promise<EntryTag[]> {
+  try {
+    return await invoke<EntryTag[]>("set_entry_tags", { day, tags });
+  } catch (error) {
+    console.error("Failed to set entry tags:", error);
+    throw error;
+  }
+}
+
+export async function addAttachment(day: string, fileName: string, data: Uint8Array): Promise<Attachment> {
+  try {
+    return await invoke<Attachment>("add_attachment", { day, fileName, data: Array.from(data) });
+  } catch (error) {
+    console.error("Failed to add attachment:", error);
+    throw error;
+  }
+}
+
+export async function listAttachments(day: string): Promise<Attachment[]> {
+  try {
+    return await invoke<Attachment[]>("list_attachments", { day });
+  } catch (error) {
+    console.error("Failed to list attachments:", error);
+    throw error;
+  }
+}
+
+export async function getAttachment(sha256: string, thumbnail = false): Promise<Uint8Array> {
+  try {
+    const data = await invoke<number[]>("get_attachment", { sha256, thumbnail });
+    return new Uint8Array(data);
+  } catch (error) {
+    console.error("Failed to get attachment:", error);
+    throw error;
+  }
+}
+
+export async function removeAttachment(day: string, sha256: string): Promise<void> {
+  try {
+    await invoke<void>("remove_attachment", { day, sha256 });
+  } catch (error) {
+    console.error("Failed to remove attachment:", error);
+    throw error;
+  }
+}
+
+export async function auditDiary(): Promise<AuditReport> {
+  try {
+    return await invoke<AuditReport>("audit_diary");
+  } catch (error) {
+    console.error("Failed to audit diary:", error);
+    throw error;
+  }
+}
+
+export async function listOutbox(status?: OutboxStatus): Promise<OutboxItem[]> {
+  try {
+    return await invoke<OutboxItem[]>("list_outbox", { status });
+  } catch (error) {
+    console.error("Failed to list outbox:", error);
+    throw error;
+  }
+}
+
+export async function retryOutbox(id?: number): Promise<number> {
+  try {
+    return await invoke<number>("retry_outbox", { id });
+  } catch (error) {
+    console.error("Failed to retry outbox:", error);
+    throw error;
+  }
+}
+
+export async function getPublishStatus(day: string): Promise<PublishStatus> {
+  try {
+    return await invoke<PublishStatus>("get_publish_status", { day });
+  } catch (error) {
+    console.error("Failed to get publish status:", error);
+    throw error;
+  }
+}
+
+export async function restoreFromRelays(dryRun: boolean): Promise<RestoreReport> {
+  try {
+    return await invoke<RestoreReport>("restore_from_relays", { dryRun });
+  } catch (error) {
+    console.error("Failed to restore from relays:", error);
+    throw error;
+  }
+}
+
+export async function setEntryVisibility(day: string, isPrivate: boolean): Promise<DiaryEntry> {
+  try {
+    return await invoke<DiaryEntry>("set_entry_visibility", { day, private: isPrivate });
+  } catch (error) {
+    console.error("Failed to set entry visibility:", error);
+    throw error;
+  }
+}
+
+export async function publishPendingEntries(): Promise<number> {
+  try {
+    return await invoke<number>("publish_pending_entries");
+  } catch (error) {
+        console.error("Failed to publish pending entries:", error);
+    throw error;
+  }
+}
+
+export async function getRelayList(): Promise<RelayListEntry[]> {
+  try {
+    return await invoke<RelayListEntry[]>("get_relay_list");
+  } catch (error) {
+    console.error("Failed to get relay list:", error);
+    throw error;
+  }
+}
+
+export async function publishRelayList(): Promise<string> {
+  try {
+    return await invoke<string>("publish_relay_list");
+  } catch (error) {
+    console.error("Failed to publish relay list:", error);
+    throw error;
+  }
+}
+
+export async function importRelayList(): Promise<RelayListEntry[]> {
+  try {
+    return await invoke<RelayListEntry[]>("import_relay_list");
+  } catch (error) {
+    console.error("Failed to import relay list:", error);
+    throw error;
+  }
+}
+
+export async function checkRelays(): Promise<RelayHealth[]> {
+  try {
+    return await invoke<RelayHealth[]>("check_relays");
+  } catch (error) {
+    console.error("Failed to check relays:", error);
+    throw error;
+  }
+}
+
+export async function getRelayConnectionStatus(): Promise<RelayConnection[]> {
+  try {
+    return await invoke<RelayConnection[]>("get_relay_connection_status");
+  } catch (error) {
+    console.error("Failed to get relay connection status:", error);
+    throw error;
+  }
+}
+
+export async function checkEntryLimits(content: string, weather: string, day: string, tags?: string[], isPrivate?: boolean): Promise<RelayLimitWarning[]> {
+  try {
+    return await invoke<RelayLimitWarning[]>("check_entry_limits", {
+      content,
+      weather,
+      day,
+      tags,
+      private: isPrivate
+    });
+  } catch (error) {
+    console.error("Failed to check entry limits:", error);
+    throw error;
+  }
+}
+
+
+
+export async function getDataDirInfo(): Promise<DataDirInfo> {
+  try {
+    return await invoke<DataDirInfo>("get_data_dir_info");
+  } catch (error) {
+    console.error("Failed to get data directory:", error);
+    throw error;
+  }
+}
+
+export async function moveDataDir(targetDir: string): Promise<DataDirMove> {
+  try {
+    return await invoke<DataDirMove>("move_data_dir", { targetDir });
+  } catch (error) {
+    console.error("Failed to move data directory:", error);
+    throw error;
+  }
+}
+
 export async function loadEntries(): Promise<DiaryEntry[]> {
   try {
     return await invoke<DiaryEntry[]>("get_diary_entries");
@@ -20,6 +299,51 @@ export async function loadEntries(): Promise<DiaryEntry[]> {
   }
 }
 
+export async function queryDiaryEntries(query: EntryQuery = {}): Promise<EntryPage> {
+  try {
+    return await invoke<EntryPage>("query_diary_entries", { query });
+  } catch (error) {
+    console.error("Failed to query entries:", error);
+    throw error;
+  }
+}
+
+export async function getEntriesForMonth(year: number, month: number): Promise<DiaryEntry[]> {
+  try {
+    return await invoke<DiaryEntry[]>("get_entries_for_month", { year, month });
+  } catch (error) {
+    console.error("Failed to load entries for month:", error);
+    throw error;
+  }
+}
+
+export async function getEntriesForYear(year: number): Promise<DiaryEntry[]> {
+  try {
+    return await invoke<DiaryEntry[]>("get_entries_for_year", { year });
+  } catch (error) {
+    console.error("Failed to load entries for year:", error);
+    throw error;
+  }
+}
+
+export async function getEntriesInRange(startDay: string, endDay: string): Promise<DiaryEntry[]> {
+  try {
+    return await invoke<DiaryEntry[]>("get_entries_in_range", { startDay, endDay });
+  } catch (error) {
+    console.error("Failed to load entries in range:", error);
+    throw error;
+  }
+}
+
+export async function searchDiaryEntries(query: string, limit?: number): Promise<SearchResult[]> {
+  try {
+    return await invoke<SearchResult[]>("search_diary_entries", { query, limit });
+  } catch (error) {
+    console.error("Failed to search entries:", error);
+    throw error;
+  }
+}
+
 export async function checkDayHasEntry(day: string): Promise<boolean> {
   try {
     return await invoke<boolean>("check_day_has_entry", { day });
@@ -29,7 +353,7 @@ export async function checkDayHasEntry(day: string): Promise<boolean> {
   }
 }
 
-export async function saveDiaryEntry(content: string, weather: string, day: string): Promise<DiaryEntry> {
+export async function saveDiaryEntry(content: string, weather: string, day: string, tags?: string[], publishLater?: boolean, isPrivate?: boolean): Promise<DiaryEntry> {
   if (!content.trim()) {
     throw new Error("Content cannot be empty");
   }
@@ -38,7 +362,10 @@ export async function saveDiaryEntry(content: string, weather: string, day: stri
     return await invoke<DiaryEntry>("save_diary_entry", { 
       content, 
       weather,
-      day
+      day,
+      tags,
+      publishLater,
+      private: isPrivate
     });
   } catch (error) {
     console.error("Failed to save entry:", error);
@@ -46,6 +373,73 @@ export async function saveDiaryEntry(content: string, weather: string, day: stri
   }
 }
 
+export async function updateDiaryEntry(day: string, content: string, weather: string): Promise<DiaryEntry> {
+  if (!content.trim()) {
+    throw new Error("Content cannot be empty");
+  }
+
+  try {
+    return await invoke<DiaryEntry>("update_diary_entry", { day, content, weather });
+  } catch (error) {
+    console.error("Failed to update entry:", error);
+    throw error;
+  }
+}
+
+export async function listEntryRevisions(day: string): Promise<EntryRevision[]> {
+  try {
+    return await invoke<EntryRevision[]>("list_entry_revisions", { day });
+  } catch (error) {
+    console.error("Failed to list entry revisions:", error);
+    throw error;
+  }
+}
+
+export async function diffEntryRevisions(day: string, fromRevision: number, toRevision: number): Promise<RevisionDiff> {
+  try {
+    return await invoke<RevisionDiff>("diff_entry_revisions", { day, fromRevision, toRevision });
+  } catch (error) {
+    console.error("Failed to diff entry revisions:", error);
+    throw error;
+  }
+}
+
+export async function trashDiaryEntry(day: string): Promise<void> {
+  try {
+    await invoke<void>("trash_diary_entry", { day });
+  } catch (error) {
+    console.error("Failed to move entry to trash:", error);
+    throw error;
+  }
+}
+
+export async function restoreDiaryEntry(day: string): Promise<void> {
+  try {
+    await invoke<void>("restore_diary_entry", { day });
+  } catch (error) {
+    console.error("Failed to restore entry:", error);
+    throw error;
+  }
+}
+
+export async function listTrashedEntries(): Promise<TrashedEntry[]> {
+  try {
+    return await invoke<TrashedEntry[]>("list_trashed_entries");
+  } catch (error) {
+    console.error("Failed to list trashed entries:", error);
+    throw error;
+  }
+}
+
+export async function purgeDiaryEntry(day: string): Promise<PurgeResult> {
+  try {
+    return await invoke<PurgeResult>("purge_diary_entry", { day });
+  } catch (error) {
+    console.error("Failed to purge entry:", error);
+    throw error;
+  }
+}
+
 export async function getNostrEvent(nostrId: string): Promise<string> {
   if (!nostrId) {
     throw new Error("Nostr ID cannot be empty");